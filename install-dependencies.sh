@@ -31,11 +31,12 @@ Help:
         packages+=" qemu-system-riscv64 "
         packages+=" binutils-riscv64-unknown-elf "
         packages+=" gdb-multiarch "
+        packages+=" dosfstools mtools "
         sudo apt-get install -y ${packages}
 
     # macOS
     elif command -v brew > /dev/null; then
-        packages+=" coreutils qemu "
+        packages+=" coreutils qemu dosfstools mtools "
         brew install ${packages}
 
     # And fuck everyone else
@@ -1,11 +1,21 @@
+use crate::signal::Signal;
 use core::fmt::{self, Display};
 
 /// Krabby-abi error type
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 #[non_exhaustive]
 pub enum KrabbyAbiError {
     InvalidPid(usize),
     InvalidFileDescriptor(usize),
+    InvalidOpenFlags(usize),
+    InvalidTtyMode(usize),
+    InvalidNice(isize),
+    InvalidWaitFlags(usize),
+    InvalidWaitStatus(usize),
+    InvalidDirEntry,
+    InvalidProcessInfo,
+    InvalidProtection(usize),
+    InvalidMapFlags(usize),
 }
 
 impl Display for KrabbyAbiError {
@@ -17,21 +27,204 @@ impl Display for KrabbyAbiError {
             Self::InvalidFileDescriptor(val) => {
                 write!(f, "Invalid file descriptor: {val}")
             }
+            Self::InvalidOpenFlags(val) => {
+                write!(f, "Invalid open flags: {val:#x}")
+            }
+            Self::InvalidTtyMode(val) => {
+                write!(f, "Invalid TTY mode: {val:#x}")
+            }
+            Self::InvalidNice(val) => {
+                write!(f, "Invalid nice value: {val}")
+            }
+            Self::InvalidWaitFlags(val) => {
+                write!(f, "Invalid wait flags: {val:#x}")
+            }
+            Self::InvalidWaitStatus(val) => {
+                write!(f, "Invalid wait status: {val}")
+            }
+            Self::InvalidDirEntry => {
+                write!(f, "Invalid directory entry")
+            }
+            Self::InvalidProcessInfo => {
+                write!(f, "Invalid process info")
+            }
+            Self::InvalidProtection(val) => {
+                write!(f, "Invalid memory protection: {val:#x}")
+            }
+            Self::InvalidMapFlags(val) => {
+                write!(f, "Invalid map flags: {val:#x}")
+            }
         }
     }
 }
 
-/// Exit error code from a process
+/// Error code returned in `a1` from a failed syscall
+///
+/// Zero is reserved for success
 #[derive(enumn::N, Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(usize)]
+#[non_exhaustive]
+pub enum Errno {
+    /// Unspecified failure
+    Failure = 1,
+    /// Invalid argument
+    InvalidArgument,
+    /// No such syscall
+    InvalidSyscall,
+    /// No such process
+    NoSuchProcess,
+    /// File descriptor is not open
+    BadFileDescriptor,
+    /// No free file descriptors left
+    TooManyOpenFiles,
+    /// No such file or directory
+    NotFound,
+    /// File already exists
+    AlreadyExists,
+    /// Directory is not empty
+    DirectoryNotEmpty,
+    /// No space left on device
+    NoSpace,
+    /// Operation not permitted
+    NotPermitted,
+    /// Pointer outside of the caller's address space
+    BadAddress,
+    /// Ran out of memory or address space
+    OutOfMemory,
+    /// Arguments and environment are too large
+    ArgumentListTooLong,
+    /// File isn't a valid executable
+    InvalidExecutable,
+    /// Unexpected end of input
+    EndOfInput,
+    /// Write could not complete
+    WriteZero,
+    /// Device or driver failure
+    Io,
+    /// Interrupted by a signal
+    Interrupted,
+    /// Gave up waiting
+    TimedOut,
+    /// Wrote to a pipe with no readers
+    BrokenPipe,
+    /// Directory operation on something that isn't a directory
+    NotADirectory,
+    /// File operation on a directory
+    IsADirectory,
+    /// Waited on a process that isn't a child of the caller
+    NoChild,
+}
+
+impl From<Errno> for usize {
+    fn from(errno: Errno) -> Self {
+        errno as usize
+    }
+}
+
+impl From<KrabbyAbiError> for Errno {
+    fn from(err: KrabbyAbiError) -> Self {
+        match err {
+            KrabbyAbiError::InvalidPid(_)
+            | KrabbyAbiError::InvalidOpenFlags(_)
+            | KrabbyAbiError::InvalidTtyMode(_)
+            | KrabbyAbiError::InvalidNice(_)
+            | KrabbyAbiError::InvalidWaitFlags(_)
+            | KrabbyAbiError::InvalidWaitStatus(_)
+            | KrabbyAbiError::InvalidDirEntry
+            | KrabbyAbiError::InvalidProcessInfo
+            | KrabbyAbiError::InvalidProtection(_)
+            | KrabbyAbiError::InvalidMapFlags(_) => Self::InvalidArgument,
+            KrabbyAbiError::InvalidFileDescriptor(_) => Self::BadFileDescriptor,
+        }
+    }
+}
+
+impl Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let description = match self {
+            Self::Failure => "Failure",
+            Self::InvalidArgument => "Invalid argument",
+            Self::InvalidSyscall => "No such syscall",
+            Self::NoSuchProcess => "No such process",
+            Self::BadFileDescriptor => "Bad file descriptor",
+            Self::TooManyOpenFiles => "Too many open files",
+            Self::NotFound => "No such file or directory",
+            Self::AlreadyExists => "File exists",
+            Self::DirectoryNotEmpty => "Directory not empty",
+            Self::NoSpace => "No space left on device",
+            Self::NotPermitted => "Operation not permitted",
+            Self::BadAddress => "Bad address",
+            Self::OutOfMemory => "Out of memory",
+            Self::ArgumentListTooLong => "Argument list too long",
+            Self::InvalidExecutable => "Invalid executable",
+            Self::EndOfInput => "Unexpected end of input",
+            Self::WriteZero => "Failed to write whole buffer",
+            Self::Io => "Input/output error",
+            Self::Interrupted => "Interrupted by signal",
+            Self::TimedOut => "Timed out",
+            Self::BrokenPipe => "Broken pipe",
+            Self::NotADirectory => "Not a directory",
+            Self::IsADirectory => "Is a directory",
+            Self::NoChild => "No child processes",
+        };
+        write!(f, "{description}")
+    }
+}
+
+// Like shells do, exit codes for deaths by signal are offset by this much
+const SIGNAL_EXIT_CODE_BASE: usize = 128;
+
+/// Exit error from a process
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ProcessError {
     /// Generic failure
-    Failure = 1,
+    Failure,
+    /// Killed for accessing memory outside of its address space
+    SegmentationFault,
+    /// Killed by a signal
+    Killed(Signal),
+    /// Killed by an exception the kernel couldn't handle
+    Exception,
+}
+
+impl ProcessError {
+    /// Get the error from an exit code, or `None` for success
+    ///
+    /// Unknown codes are plain failures
+    pub fn from_code(code: usize) -> Option<Self> {
+        match code {
+            0 => None,
+            2 => Some(Self::SegmentationFault),
+            3 => Some(Self::Exception),
+            code if code > SIGNAL_EXIT_CODE_BASE => Some(
+                Signal::n(code - SIGNAL_EXIT_CODE_BASE)
+                    .map(Self::Killed)
+                    .unwrap_or(Self::Failure),
+            ),
+            _ => Some(Self::Failure),
+        }
+    }
 }
 
 impl From<ProcessError> for usize {
     fn from(err: ProcessError) -> Self {
-        err as usize
+        match err {
+            ProcessError::Failure => 1,
+            ProcessError::SegmentationFault => 2,
+            ProcessError::Exception => 3,
+            ProcessError::Killed(signal) => SIGNAL_EXIT_CODE_BASE + usize::from(signal),
+        }
+    }
+}
+
+impl Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Failure => write!(f, "Failure"),
+            Self::SegmentationFault => write!(f, "Segmentation fault"),
+            Self::Killed(signal) => write!(f, "Killed by {signal:?}"),
+            Self::Exception => write!(f, "Exception"),
+        }
     }
 }
 
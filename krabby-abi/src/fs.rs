@@ -1,10 +1,22 @@
 use crate::KrabbyAbiError;
-use core::fmt::{self, Display};
+use core::{
+    fmt::{self, Display},
+    ops::BitOr,
+};
 
 /// File descriptor type
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FileDescriptor(u16);
 
+impl FileDescriptor {
+    /// Standard input
+    pub const STDIN: Self = Self(0);
+    /// Standard output. Goes to the console unless redirected
+    pub const STDOUT: Self = Self(1);
+    /// Standard error. Goes to the console unless redirected
+    pub const STDERR: Self = Self(2);
+}
+
 impl From<FileDescriptor> for usize {
     fn from(fd: FileDescriptor) -> Self {
         fd.0.into()
@@ -26,3 +38,137 @@ impl Display for FileDescriptor {
         self.0.fmt(f)
     }
 }
+
+/// Flags for [Syscall::Open](crate::Syscall::Open)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OpenFlags(usize);
+
+impl OpenFlags {
+    /// Open for reading
+    pub const READ: Self = Self(1 << 0);
+    /// Open for writing
+    pub const WRITE: Self = Self(1 << 1);
+    /// Create the file if it doesn't exist
+    pub const CREATE: Self = Self(1 << 2);
+    /// Truncate the file to zero length if opened for writing
+    pub const TRUNCATE: Self = Self(1 << 3);
+    /// Every write goes to the end of the file
+    pub const APPEND: Self = Self(1 << 4);
+    /// Open a directory, to list with [Syscall::ReadDirectory](crate::Syscall::ReadDirectory)
+    pub const DIRECTORY: Self = Self(1 << 5);
+
+    const ALL: usize = Self::READ.0
+        | Self::WRITE.0
+        | Self::CREATE.0
+        | Self::TRUNCATE.0
+        | Self::APPEND.0
+        | Self::DIRECTORY.0;
+
+    /// Check if all flags in `other` are set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl From<OpenFlags> for usize {
+    fn from(flags: OpenFlags) -> Self {
+        flags.0
+    }
+}
+
+impl TryFrom<usize> for OpenFlags {
+    type Error = KrabbyAbiError;
+    fn try_from(flags: usize) -> Result<Self, KrabbyAbiError> {
+        if flags & !Self::ALL != 0 {
+            return Err(KrabbyAbiError::InvalidOpenFlags(flags));
+        }
+        Ok(Self(flags))
+    }
+}
+
+/// Reference point for [Syscall::Seek](crate::Syscall::Seek), like `whence` in lseek(2)
+#[derive(enumn::N, Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Whence {
+    /// Offset is relative to the start of the file
+    Start = 0,
+    /// Offset is relative to the current position
+    Current,
+    /// Offset is relative to the end of the file
+    End,
+}
+
+/// What a directory entry refers to
+#[derive(enumn::N, Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FileType {
+    /// Regular file
+    File = 1,
+    /// Directory
+    Directory,
+}
+
+/// Entry of a directory, as read by [Syscall::ReadDirectory](crate::Syscall::ReadDirectory)
+///
+/// Encoded as the file type byte, the size as a little-endian `u64`, the name's length as a
+/// little-endian `u16`, then the name
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry<'a> {
+    /// File name, without the directory
+    pub name: &'a str,
+    /// What the entry is
+    pub file_type: FileType,
+    /// Size in bytes. Zero for directories
+    pub size: u64,
+}
+
+impl<'a> DirEntry<'a> {
+    const HEADER_LEN: usize = 1 + 8 + 2;
+
+    /// Write the entry to the start of `buffer`
+    ///
+    /// Returns the number of bytes written, or `None` if it doesn't fit
+    pub fn encode(&self, buffer: &mut [u8]) -> Option<usize> {
+        let name_len = u16::try_from(self.name.len()).ok()?;
+        let len = Self::HEADER_LEN + self.name.len();
+        let buffer = buffer.get_mut(..len)?;
+
+        buffer[0] = self.file_type as u8;
+        buffer[1..9].copy_from_slice(&self.size.to_le_bytes());
+        buffer[9..11].copy_from_slice(&name_len.to_le_bytes());
+        buffer[11..].copy_from_slice(self.name.as_bytes());
+        Some(len)
+    }
+
+    /// Read an entry from the start of `buffer`
+    ///
+    /// Also returns the number of bytes it took up
+    pub fn decode(buffer: &'a [u8]) -> Result<(Self, usize), KrabbyAbiError> {
+        let header = buffer
+            .get(..Self::HEADER_LEN)
+            .ok_or(KrabbyAbiError::InvalidDirEntry)?;
+        let file_type = FileType::n(header[0]).ok_or(KrabbyAbiError::InvalidDirEntry)?;
+        let size = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        let name_len = u16::from_le_bytes(header[9..11].try_into().unwrap());
+
+        let len = Self::HEADER_LEN + usize::from(name_len);
+        let name = buffer
+            .get(Self::HEADER_LEN..len)
+            .and_then(|name| core::str::from_utf8(name).ok())
+            .ok_or(KrabbyAbiError::InvalidDirEntry)?;
+
+        let entry = DirEntry {
+            name,
+            file_type,
+            size,
+        };
+        Ok((entry, len))
+    }
+}
@@ -0,0 +1,3 @@
+/// Timeout seconds for [Syscall::FutexWait](crate::Syscall::FutexWait) that waits for as long
+/// as it takes
+pub const NO_TIMEOUT: usize = usize::MAX;
@@ -5,7 +5,14 @@ mod pid;
 mod sys;
 
 pub mod fs;
+pub mod futex;
+pub mod memory;
+pub mod process;
+pub mod sched;
+pub mod signal;
+pub mod tty;
+pub mod wait;
 
-pub use error::{KrabbyAbiError, ProcessError, ProcessResult};
+pub use error::{Errno, KrabbyAbiError, ProcessError, ProcessResult};
 pub use pid::Pid;
 pub use sys::Syscall;
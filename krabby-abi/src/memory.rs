@@ -0,0 +1,92 @@
+//! Mapping memory into a process, with [Syscall::Mmap](crate::Syscall::Mmap) and
+//! [Syscall::Munmap](crate::Syscall::Munmap)
+use crate::KrabbyAbiError;
+use core::ops::BitOr;
+
+/// What mapped memory may be used for, like `prot` in mmap(2)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Protection(usize);
+
+impl Protection {
+    /// Nothing. Any access is a segmentation fault
+    pub const NONE: Self = Self(0);
+    /// Readable
+    pub const READ: Self = Self(1 << 0);
+    /// Writable. Writable memory is always readable too
+    pub const WRITE: Self = Self(1 << 1);
+    /// Executable. Memory can't be both writable and executable
+    pub const EXECUTE: Self = Self(1 << 2);
+
+    const ALL: usize = Self::READ.0 | Self::WRITE.0 | Self::EXECUTE.0;
+
+    /// Check if all flags in `other` are set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Protection {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl From<Protection> for usize {
+    fn from(prot: Protection) -> Self {
+        prot.0
+    }
+}
+
+impl TryFrom<usize> for Protection {
+    type Error = KrabbyAbiError;
+    fn try_from(prot: usize) -> Result<Self, KrabbyAbiError> {
+        if prot & !Self::ALL != 0 {
+            return Err(KrabbyAbiError::InvalidProtection(prot));
+        }
+        Ok(Self(prot))
+    }
+}
+
+/// Flags for [Syscall::Mmap](crate::Syscall::Mmap)
+///
+/// Mapped memory is always private to the process and starts out zeroed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MapFlags(usize);
+
+impl MapFlags {
+    /// Let the kernel choose where the memory goes. The address is only a hint
+    pub const NONE: Self = Self(0);
+    /// Map at exactly the address given, failing if anything is already there
+    pub const FIXED: Self = Self(1 << 0);
+
+    const ALL: usize = Self::FIXED.0;
+
+    /// Check if all flags in `other` are set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MapFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl From<MapFlags> for usize {
+    fn from(flags: MapFlags) -> Self {
+        flags.0
+    }
+}
+
+impl TryFrom<usize> for MapFlags {
+    type Error = KrabbyAbiError;
+    fn try_from(flags: usize) -> Result<Self, KrabbyAbiError> {
+        if flags & !Self::ALL != 0 {
+            return Err(KrabbyAbiError::InvalidMapFlags(flags));
+        }
+        Ok(Self(flags))
+    }
+}
@@ -0,0 +1,198 @@
+//! What processes are up to, from [Syscall::Pinfo](crate::Syscall::Pinfo) and
+//! [Syscall::ListProcesses](crate::Syscall::ListProcesses)
+use crate::{sched::Nice, KrabbyAbiError, Pid};
+use core::{
+    fmt::{self, Display},
+    time::Duration,
+};
+
+/// Scheduling state of a process, which is the most active of its threads'
+#[derive(enumn::N, Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ProcessState {
+    /// Waiting for its turn on a CPU
+    Ready = 1,
+    /// On a CPU
+    Running,
+    /// Waiting for something, given by [ProcessInfo::blocked_on]
+    Blocked,
+    /// Stopped by a signal until it's continued
+    Stopped,
+    /// Exited, but not cleaned up yet
+    Zombie,
+}
+
+impl Display for ProcessState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let state = match self {
+            Self::Ready => "ready",
+            Self::Running => "running",
+            Self::Blocked => "blocked",
+            Self::Stopped => "stopped",
+            Self::Zombie => "zombie",
+        };
+        f.write_str(state)
+    }
+}
+
+/// What a blocked process is waiting for
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockReason {
+    /// A process to exit, or maybe stop
+    WaitProcess(Pid),
+    /// A thread to exit
+    JoinThread(Pid),
+    /// Input from the terminal
+    Terminal,
+    /// A sleep to finish
+    Sleep,
+    /// A wakeup through a futex
+    Futex,
+    /// Data in a pipe
+    PipeRead,
+    /// Room in a pipe
+    PipeWrite,
+}
+
+impl BlockReason {
+    // Kind and PID, with zero for no reason or no PID
+    fn encode(reason: Option<Self>) -> (u8, u16) {
+        match reason {
+            None => (0, 0),
+            Some(Self::WaitProcess(pid)) => (1, pid.into()),
+            Some(Self::JoinThread(tid)) => (2, tid.into()),
+            Some(Self::Terminal) => (3, 0),
+            Some(Self::Sleep) => (4, 0),
+            Some(Self::Futex) => (5, 0),
+            Some(Self::PipeRead) => (6, 0),
+            Some(Self::PipeWrite) => (7, 0),
+        }
+    }
+
+    fn decode(kind: u8, pid: u16) -> Result<Option<Self>, KrabbyAbiError> {
+        let pid = || Pid::maybe_from_u16(pid).ok_or(KrabbyAbiError::InvalidProcessInfo);
+        let reason = match kind {
+            0 => return Ok(None),
+            1 => Self::WaitProcess(pid()?),
+            2 => Self::JoinThread(pid()?),
+            3 => Self::Terminal,
+            4 => Self::Sleep,
+            5 => Self::Futex,
+            6 => Self::PipeRead,
+            7 => Self::PipeWrite,
+            _ => return Err(KrabbyAbiError::InvalidProcessInfo),
+        };
+        Ok(Some(reason))
+    }
+}
+
+impl Display for BlockReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::WaitProcess(pid) => write!(f, "wait {pid}"),
+            Self::JoinThread(tid) => write!(f, "join {tid}"),
+            Self::Terminal => write!(f, "terminal"),
+            Self::Sleep => write!(f, "sleep"),
+            Self::Futex => write!(f, "futex"),
+            Self::PipeRead => write!(f, "pipe read"),
+            Self::PipeWrite => write!(f, "pipe write"),
+        }
+    }
+}
+
+/// Snapshot of a process
+///
+/// These are encoded back to back in the buffer passed to the syscalls
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProcessInfo<'a> {
+    /// Process ID
+    pub pid: Pid,
+    /// Parent process, if it was forked
+    pub ppid: Option<Pid>,
+    /// Process group
+    pub pgid: Pid,
+    /// What it's doing
+    pub state: ProcessState,
+    /// What it's waiting for, if it's blocked
+    pub blocked_on: Option<BlockReason>,
+    /// Niceness of the main thread
+    pub nice: Nice,
+    /// Number of threads
+    pub threads: u16,
+    /// Time all of its threads have spent running
+    pub cpu_time: Duration,
+    /// Pages of memory mapped into its address space
+    pub resident_pages: u64,
+    /// Name of the program, without its directory
+    pub name: &'a str,
+}
+
+impl<'a> ProcessInfo<'a> {
+    const HEADER_LEN: usize = 2 + 2 + 2 + 1 + 1 + 2 + 1 + 2 + 8 + 8 + 2;
+
+    /// Number of bytes [ProcessInfo::encode] writes
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_LEN + self.name.len()
+    }
+
+    /// Write the info to the start of `buffer`
+    ///
+    /// Returns the number of bytes written, or `None` if it doesn't fit
+    pub fn encode(&self, buffer: &mut [u8]) -> Option<usize> {
+        let name_len = u16::try_from(self.name.len()).ok()?;
+        let len = self.encoded_len();
+        let buffer = buffer.get_mut(..len)?;
+
+        let (block_kind, block_pid) = BlockReason::encode(self.blocked_on);
+        let cpu_time = u64::try_from(self.cpu_time.as_nanos()).unwrap_or(u64::MAX);
+        buffer[0..2].copy_from_slice(&u16::from(self.pid).to_le_bytes());
+        buffer[2..4].copy_from_slice(&self.ppid.map_or(0, u16::from).to_le_bytes());
+        buffer[4..6].copy_from_slice(&u16::from(self.pgid).to_le_bytes());
+        buffer[6] = self.state as u8;
+        buffer[7] = block_kind;
+        buffer[8..10].copy_from_slice(&block_pid.to_le_bytes());
+        buffer[10] = isize::from(self.nice) as u8;
+        buffer[11..13].copy_from_slice(&self.threads.to_le_bytes());
+        buffer[13..21].copy_from_slice(&cpu_time.to_le_bytes());
+        buffer[21..29].copy_from_slice(&self.resident_pages.to_le_bytes());
+        buffer[29..31].copy_from_slice(&name_len.to_le_bytes());
+        buffer[31..].copy_from_slice(self.name.as_bytes());
+        Some(len)
+    }
+
+    /// Read info from the start of `buffer`
+    ///
+    /// Also returns the number of bytes it took up
+    pub fn decode(buffer: &'a [u8]) -> Result<(Self, usize), KrabbyAbiError> {
+        let header = buffer
+            .get(..Self::HEADER_LEN)
+            .ok_or(KrabbyAbiError::InvalidProcessInfo)?;
+        let u16_at = |i: usize| u16::from_le_bytes(header[i..i + 2].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+        let pid = |pid| Pid::maybe_from_u16(pid).ok_or(KrabbyAbiError::InvalidProcessInfo);
+
+        let state = ProcessState::n(header[6]).ok_or(KrabbyAbiError::InvalidProcessInfo)?;
+        let nice = Nice::try_from(header[10] as i8 as isize)
+            .map_err(|_| KrabbyAbiError::InvalidProcessInfo)?;
+
+        let len = Self::HEADER_LEN + usize::from(u16_at(29));
+        let name = buffer
+            .get(Self::HEADER_LEN..len)
+            .and_then(|name| core::str::from_utf8(name).ok())
+            .ok_or(KrabbyAbiError::InvalidProcessInfo)?;
+
+        let info = ProcessInfo {
+            pid: pid(u16_at(0))?,
+            ppid: Pid::maybe_from_u16(u16_at(2)),
+            pgid: pid(u16_at(4))?,
+            state,
+            blocked_on: BlockReason::decode(header[7], u16_at(8))?,
+            nice,
+            threads: u16_at(11),
+            cpu_time: Duration::from_nanos(u64_at(13)),
+            resident_pages: u64_at(21),
+            name,
+        };
+        Ok((info, len))
+    }
+}
@@ -0,0 +1,52 @@
+use crate::KrabbyAbiError;
+use core::fmt::{self, Display};
+
+/// Niceness of a process, from -20 to 19
+///
+/// Nicer processes are scheduled after less nice ones, and get shorter time slices
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Nice(i8);
+
+impl Nice {
+    /// Least nice - scheduled first, and for the longest
+    pub const MIN: Self = Self(-20);
+    /// Nicest - scheduled last, and for the shortest
+    pub const MAX: Self = Self(19);
+    /// What processes start out with
+    pub const DEFAULT: Self = Self(0);
+}
+
+impl From<Nice> for isize {
+    fn from(nice: Nice) -> Self {
+        nice.0.into()
+    }
+}
+
+impl From<Nice> for usize {
+    fn from(nice: Nice) -> Self {
+        isize::from(nice) as usize
+    }
+}
+
+impl TryFrom<isize> for Nice {
+    type Error = KrabbyAbiError;
+    fn try_from(nice: isize) -> Result<Self, KrabbyAbiError> {
+        if !(isize::from(Self::MIN)..=isize::from(Self::MAX)).contains(&nice) {
+            return Err(KrabbyAbiError::InvalidNice(nice));
+        }
+        Ok(Self(nice as i8))
+    }
+}
+
+impl TryFrom<usize> for Nice {
+    type Error = KrabbyAbiError;
+    fn try_from(nice: usize) -> Result<Self, KrabbyAbiError> {
+        Self::try_from(nice as isize)
+    }
+}
+
+impl Display for Nice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,59 @@
+/// Signal number, matching Linux
+#[derive(enumn::N, Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Signal {
+    /// Interrupt from the terminal (Ctrl-C)
+    Interrupt = 2,
+    /// Terminate immediately. Can't be caught or ignored
+    Kill = 9,
+    /// User-defined
+    User1 = 10,
+    /// User-defined
+    User2 = 12,
+    /// Polite request to terminate
+    Terminate = 15,
+    /// A child process exited or stopped
+    Child = 17,
+    /// Carry on after being stopped
+    Continue = 18,
+    /// Stop until continued. Can't be caught or ignored
+    Stop = 19,
+    /// Stop request from the terminal (Ctrl-Z)
+    TerminalStop = 20,
+}
+
+impl Signal {
+    /// Highest signal number, plus one
+    pub const COUNT: usize = 32;
+
+    /// Can a handler be installed for this signal, or the signal be ignored?
+    pub const fn catchable(self) -> bool {
+        !matches!(self, Self::Kill | Self::Stop)
+    }
+
+    /// Does the default action terminate the process, rather than stop it or ignore the signal?
+    pub const fn terminates_by_default(self) -> bool {
+        !matches!(
+            self,
+            Self::Child | Self::Continue | Self::Stop | Self::TerminalStop
+        )
+    }
+
+    /// Does the default action stop the process?
+    pub const fn stops_by_default(self) -> bool {
+        matches!(self, Self::Stop | Self::TerminalStop)
+    }
+}
+
+impl From<Signal> for usize {
+    fn from(signal: Signal) -> Self {
+        signal as usize
+    }
+}
+
+/// Handler address for [Syscall::SetSignalHandler](crate::Syscall::SetSignalHandler) that
+/// restores the default action
+pub const SIGNAL_DEFAULT: usize = 0;
+/// Handler address for [Syscall::SetSignalHandler](crate::Syscall::SetSignalHandler) that
+/// ignores the signal
+pub const SIGNAL_IGNORE: usize = 1;
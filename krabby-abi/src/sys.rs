@@ -5,9 +5,17 @@ pub enum Syscall {
     PutChar = 1,
     GetChar,
     PutString,
+    /// Get a [ProcessInfo](crate::process::ProcessInfo) for a process, or the caller if the PID
+    /// is zero
+    ///
+    /// Returns the number of bytes written
     Pinfo,
     Fork,
     Exit,
+    /// Wait for a child process to exit, or optionally stop
+    ///
+    /// Returns a [WaitStatus](crate::wait::WaitStatus). The exit status is kept until the parent
+    /// waits on it, and fails with [Errno::NoChild](crate::Errno::NoChild) after that
     WaitPid,
     Sleep,
     RequestMemory,
@@ -17,4 +25,91 @@ pub enum Syscall {
     Test,
     /// Open file
     Open,
+    /// Read from file descriptor
+    Read,
+    /// Close file descriptor
+    Close,
+    /// Reposition file descriptor offset
+    Seek,
+    /// Write to file descriptor
+    Write,
+    /// Shrink or extend file to given size
+    Truncate,
+    /// Create directory
+    MakeDirectory,
+    /// Remove file or empty directory
+    Remove,
+    /// Replace the current process image with a program from disk
+    Exec,
+    /// Move this process or one of its children into a process group
+    SetProcessGroup,
+    /// Get the process group of a process
+    GetProcessGroup,
+    /// Give a process group control of the terminal, if this process's group has it, or it
+    /// belongs to a child's group
+    SetForegroundGroup,
+    /// Get the terminal's [TtyMode](crate::tty::TtyMode)
+    GetTtyMode,
+    /// Set the terminal's [TtyMode](crate::tty::TtyMode)
+    SetTtyMode,
+    /// Send a [Signal](crate::signal::Signal) to a process
+    Kill,
+    /// Choose what happens when a [Signal](crate::signal::Signal) arrives
+    SetSignalHandler,
+    /// Return from a signal handler, restoring the interrupted context
+    SigReturn,
+    /// Set the [Nice](crate::sched::Nice) value of a thread of this process or one of its
+    /// children
+    SetPriority,
+    /// Get the [Nice](crate::sched::Nice) value of a thread
+    GetPriority,
+    /// Start a thread in the calling process
+    SpawnThread,
+    /// Wait for a thread of the calling process to exit
+    ///
+    /// Returns a [WaitStatus](crate::wait::WaitStatus). The exit status is kept until a thread
+    /// waits on it
+    JoinThread,
+    /// Exit the calling thread, leaving the rest of the process running
+    ExitThread,
+    /// Block until woken through a word of memory, if it still holds an expected value
+    FutexWait,
+    /// Wake threads blocked on a word of memory
+    FutexWake,
+    /// Create a pipe, returning its read and write ends
+    Pipe,
+    /// Make a file descriptor refer to the same file as another
+    Dup2,
+    /// Read [DirEntry](crate::fs::DirEntry)s from a directory opened with
+    /// [OpenFlags::DIRECTORY](crate::fs::OpenFlags::DIRECTORY)
+    ///
+    /// Returns the number of bytes read, which is zero once every entry has been read
+    ReadDirectory,
+    /// Get the time since the machine booted, in nanoseconds
+    Uptime,
+    /// Get [ProcessInfo](crate::process::ProcessInfo)s for every process with a PID of at least
+    /// some number, in PID order, for as many as fit
+    ///
+    /// Returns the number of bytes written, which is zero once there are no more processes
+    ListProcesses,
+    /// Get the PID of the calling process
+    GetPid,
+    /// Map zeroed memory into the calling process, with some
+    /// [Protection](crate::memory::Protection) and [MapFlags](crate::memory::MapFlags)
+    ///
+    /// Pages are only allocated when first touched. Returns the address of the mapping
+    Mmap,
+    /// Unmap memory mapped by [Syscall::Mmap] or [Syscall::MapSharedMemory], freeing it
+    ///
+    /// Any part of the range that isn't mapped is skipped
+    Munmap,
+    /// Create a named segment of zeroed memory that processes can share
+    CreateSharedMemory,
+    /// Map all of a shared memory segment into the calling process, like [Syscall::Mmap] does.
+    /// Every process mapping it sees the same memory, even across forks
+    ///
+    /// Returns the address of the mapping
+    MapSharedMemory,
+    /// Remove the name of a shared memory segment. Its memory is freed once no process maps it
+    RemoveSharedMemory,
 }
@@ -0,0 +1,55 @@
+use crate::KrabbyAbiError;
+use core::ops::BitOr;
+
+/// Terminal settings for [Syscall::SetTtyMode](crate::Syscall::SetTtyMode)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TtyMode(usize);
+
+impl TtyMode {
+    /// Raw mode - input is delivered as soon as it's typed, and Ctrl-C and Ctrl-Z are just
+    /// other characters
+    pub const RAW: Self = Self(0);
+    /// Input is delivered a line at a time, and can be edited before then. Ctrl-C interrupts
+    /// the foreground process group, and Ctrl-Z stops it
+    pub const CANONICAL: Self = Self(1 << 0);
+    /// Echo input back as it's typed
+    pub const ECHO: Self = Self(1 << 1);
+    /// What the terminal starts out in - canonical mode with echo
+    pub const DEFAULT: Self = Self(Self::CANONICAL.0 | Self::ECHO.0);
+
+    const ALL: usize = Self::CANONICAL.0 | Self::ECHO.0;
+
+    /// Check if all flags in `other` are set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for TtyMode {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl BitOr for TtyMode {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl From<TtyMode> for usize {
+    fn from(mode: TtyMode) -> Self {
+        mode.0
+    }
+}
+
+impl TryFrom<usize> for TtyMode {
+    type Error = KrabbyAbiError;
+    fn try_from(mode: usize) -> Result<Self, KrabbyAbiError> {
+        if mode & !Self::ALL != 0 {
+            return Err(KrabbyAbiError::InvalidTtyMode(mode));
+        }
+        Ok(Self(mode))
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{signal::Signal, KrabbyAbiError, ProcessError, ProcessResult};
+use core::ops::BitOr;
+
+// Codes from here up are stopped processes, offset by the signal that stopped them
+const STOPPED_CODE_BASE: usize = 256;
+
+/// Flags for [Syscall::WaitPid](crate::Syscall::WaitPid)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WaitFlags(usize);
+
+impl WaitFlags {
+    /// Wait for the process to exit
+    pub const EXITED: Self = Self(0);
+    /// Also return if the process is stopped by a signal
+    pub const STOPPED: Self = Self(1 << 0);
+
+    const ALL: usize = Self::STOPPED.0;
+
+    /// Check if all flags in `other` are set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for WaitFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl From<WaitFlags> for usize {
+    fn from(flags: WaitFlags) -> Self {
+        flags.0
+    }
+}
+
+impl TryFrom<usize> for WaitFlags {
+    type Error = KrabbyAbiError;
+    fn try_from(flags: usize) -> Result<Self, KrabbyAbiError> {
+        if flags & !Self::ALL != 0 {
+            return Err(KrabbyAbiError::InvalidWaitFlags(flags));
+        }
+        Ok(Self(flags))
+    }
+}
+
+/// What happened to a process or thread that was waited on, returned from
+/// [Syscall::WaitPid](crate::Syscall::WaitPid) and
+/// [Syscall::JoinThread](crate::Syscall::JoinThread)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// It exited
+    Exited(ProcessResult),
+    /// It was stopped by a signal, and may be continued later
+    Stopped(Signal),
+}
+
+impl From<WaitStatus> for usize {
+    fn from(status: WaitStatus) -> Self {
+        match status {
+            WaitStatus::Exited(Ok(())) => 0,
+            WaitStatus::Exited(Err(err)) => err.into(),
+            WaitStatus::Stopped(signal) => STOPPED_CODE_BASE + usize::from(signal),
+        }
+    }
+}
+
+impl TryFrom<usize> for WaitStatus {
+    type Error = KrabbyAbiError;
+    fn try_from(code: usize) -> Result<Self, KrabbyAbiError> {
+        match code {
+            0 => Ok(Self::Exited(Ok(()))),
+            code if code < STOPPED_CODE_BASE => {
+                let err = ProcessError::from_code(code).unwrap_or(ProcessError::Failure);
+                Ok(Self::Exited(Err(err)))
+            }
+            code => Signal::n(code - STOPPED_CODE_BASE)
+                .map(Self::Stopped)
+                .ok_or(KrabbyAbiError::InvalidWaitStatus(code)),
+        }
+    }
+}
@@ -1,10 +1,7 @@
 // This is a build script - it runs before any Rust code is compiled
 use anyhow::{bail, Result};
-use elf::{endian::LittleEndian, ElfStream};
 use std::{
-    env,
-    fs::{self, File},
-    iter,
+    env, fs,
     path::{Path, PathBuf},
     process::Command,
 };
@@ -23,21 +20,15 @@ fn main() -> Result<()> {
     #[cfg(not(feature = "test"))]
     let user_programs = ["dratinit"];
 
-    // Build userspace
+    // Build userspace and embed the resulting ELF files
     for krate in user_programs {
-        let (entry, file) = objcopy(build_crate(krate)?)?;
-        let len = file.len();
+        let elf_path = fs::canonicalize(build_crate(krate)?)?;
 
-        let mut contents = format!(
+        let contents = format!(
             "// @generated
-pub const ENTRY_OFFSET: usize = 0x{entry:08x};
-pub static BIN: [u8; 0x{len:x}] = [
+pub static ELF: &[u8] = include_bytes!({elf_path:?});
 "
         );
-        for byte in file {
-            contents += format!("0x{byte:02x},").as_str();
-        }
-        contents += "];";
 
         let generated_file_path = format!("{USERSPACE_DIR}/{krate}.rs");
         fs::write(&generated_file_path, contents.as_bytes())?;
@@ -46,53 +37,6 @@ pub static BIN: [u8; 0x{len:x}] = [
     Ok(())
 }
 
-// Extract bits from an ELF file
-fn objcopy(path: impl AsRef<Path>) -> Result<(usize, Vec<u8>)> {
-    let mut elf = ElfStream::<LittleEndian, _>::open_stream(File::open(path)?)?;
-    let entry = elf.ehdr.e_entry;
-    let mut start = None;
-    let mut bytes: Vec<u8> = Vec::new();
-
-    const PROGBITS: u32 = 0x1;
-    const NOBITS: u32 = 0x8;
-    for sh in elf.section_headers().clone() {
-        if sh.sh_type != PROGBITS && sh.sh_type != NOBITS {
-            continue;
-        }
-
-        let sh_name: String = elf
-            .section_headers_with_strtab()?
-            .1
-            .unwrap()
-            .get(sh.sh_name as usize)?
-            .into();
-        if sh_name.starts_with(".debug") || sh_name.starts_with(".comment") {
-            continue;
-        }
-
-        let start = *(start.get_or_insert(sh.sh_addr));
-
-        let addr = sh.sh_addr.checked_sub(start).unwrap().try_into()?;
-        let size = usize::try_from(sh.sh_size)?;
-
-        let section = elf.section_data(&sh)?.0;
-
-        if bytes.len() < addr {
-            bytes.extend(iter::repeat(0).take(addr - bytes.len()));
-        }
-        if bytes.len() > addr {
-            panic!("Outside of section!");
-        }
-        bytes.extend(section);
-        if size > section.len() {
-            bytes.extend(iter::repeat(0).take(size - section.len()));
-        }
-    }
-
-    let start = start.unwrap();
-    Ok(((entry - start).try_into()?, bytes))
-}
-
 // Build a crate and return a path to the binary
 fn build_crate(krate: impl AsRef<str>) -> Result<PathBuf> {
     let krate = String::from(krate.as_ref());
@@ -1,12 +1,199 @@
-use crate::mmu::PAGE_SIZE;
+//! The kernel heap, which grows by taking pages from the page allocator
+use crate::mmu::{self, Page, PAGE_SIZE};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use talc::*;
 
-static mut ARENA: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+// Smallest span of pages claimed for the heap. Until the rest of RAM is added, the page
+// allocator only has the `.table_heap` section, which kernel page tables need too
+const MIN_SPAN_PAGES: usize = 4;
+// New spans are at least this fraction of the heap, so a big heap doesn't take a span per
+// handful of pages, without taking much more from user space than it needs
+const GROWTH_DIVISOR: usize = 4;
+// Most spans claimed at once
+const MAX_SPANS: usize = 32;
+// Talc keeps its bins at the start of the first span claimed
+const BINS_SIZE: usize = PAGE_SIZE;
+// Room for talc's tags at the ends of a span and around an allocation
+const TAG_OVERHEAD: usize = 8 * mem::size_of::<usize>();
+// Freeing something at least this big checks for spans that can be given back
+const RELEASE_THRESHOLD: usize = MIN_SPAN_PAGES * PAGE_SIZE;
 
 #[global_allocator]
-static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom> = Talc::new(unsafe {
-    // if we're in a hosted environment, the Rust runtime may allocate before
-    // main() is called, so we need to initialize the arena automatically
-    ClaimOnOom::new(Span::from_const_array(core::ptr::addr_of!(ARENA)))
-})
-.lock();
+static ALLOCATOR: Allocator = Allocator(Talc::new(KernelClaimer::new()).lock());
+
+// Bytes handed out by the heap and not freed yet
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// How much of the kernel heap is in use
+#[derive(Copy, Clone, Debug)]
+pub struct HeapStats {
+    /// Number of spans of pages making up the heap
+    pub spans: usize,
+    /// Bytes of memory claimed from the page allocator
+    pub size: usize,
+    /// Bytes allocated and not freed yet
+    pub allocated: usize,
+}
+
+/// Get kernel heap usage
+pub fn stats() -> HeapStats {
+    let talc = ALLOCATOR.0.lock();
+    let spans = talc
+        .oom_handler
+        .spans
+        .iter()
+        .filter(|span| span.num_pages > 0);
+    HeapStats {
+        spans: spans.clone().count(),
+        size: spans.map(|span| span.num_pages * PAGE_SIZE).sum(),
+        allocated: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+struct Allocator(Talck<spin::Mutex<()>, KernelClaimer>);
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.0.alloc(layout) };
+        if !ptr.is_null() {
+            ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.dealloc(ptr, layout) };
+        ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        if layout.size() >= RELEASE_THRESHOLD {
+            KernelClaimer::release(&mut self.0.lock());
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let ptr = unsafe { self.0.realloc(ptr, layout, new_size) };
+        if !ptr.is_null() {
+            ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+}
+
+// Pages from the page allocator, and the part of them talc is using
+#[derive(Copy, Clone)]
+struct HeapSpan {
+    // Address of the first page, or 0 if the slot is free
+    pages: usize,
+    num_pages: usize,
+    heap: Span,
+}
+
+impl HeapSpan {
+    const fn empty() -> Self {
+        Self {
+            pages: 0,
+            num_pages: 0,
+            heap: Span::empty(),
+        }
+    }
+}
+
+// Spans of pages that make up the heap
+struct KernelClaimer {
+    spans: [HeapSpan; MAX_SPANS],
+}
+
+impl KernelClaimer {
+    const fn new() -> Self {
+        Self {
+            spans: [HeapSpan::empty(); MAX_SPANS],
+        }
+    }
+
+    // Give every span with nothing allocated in it back to the page allocator, since user space
+    // gets its pages from there too
+    fn release(talc: &mut Talc<Self>) {
+        // The first span holds talc's bins, so it's kept for good
+        for i in 1..MAX_SPANS {
+            let span = talc.oom_handler.spans[i];
+            if span.heap.is_empty() || !talc.get_allocated_span(span.heap).is_empty() {
+                continue;
+            }
+            unsafe {
+                talc.truncate(span.heap, Span::empty());
+            }
+            talc.oom_handler.spans[i] = HeapSpan::empty();
+            unsafe {
+                mmu::free(span.pages as *mut Page<PAGE_SIZE>);
+            }
+        }
+    }
+
+    // Pages needed to fit an allocation of `layout` in a new span
+    fn pages_for(&self, layout: Layout) -> Option<usize> {
+        let bins = if self.spans[0].num_pages == 0 {
+            BINS_SIZE
+        } else {
+            0
+        };
+        // Talc puts a tag in front of each allocation, so anything aligned to more than a word may
+        // need padding, even at the start of a page
+        let padding = if layout.align() > mem::align_of::<usize>() {
+            layout.align()
+        } else {
+            0
+        };
+        let size = layout.size().checked_add(bins + padding + TAG_OVERHEAD)?;
+        Some(size.div_ceil(PAGE_SIZE))
+    }
+}
+
+impl OomHandler for KernelClaimer {
+    fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()> {
+        let claimer = &talc.oom_handler;
+        let slot = claimer
+            .spans
+            .iter()
+            .position(|span| span.num_pages == 0)
+            .ok_or(())?;
+        let needed = claimer.pages_for(layout).ok_or(())?;
+        let heap_pages: usize = claimer.spans.iter().map(|span| span.num_pages).sum();
+        let wanted = needed.max(MIN_SPAN_PAGES).max(heap_pages / GROWTH_DIVISOR);
+
+        // Spans have to be physically contiguous, so settle for less if RAM is fragmented. If
+        // even that fails, the allocation does
+        let mut pages = mmu::zalloc_slice::<Page<PAGE_SIZE>>(wanted)
+            .or_else(|_| mmu::zalloc_slice::<Page<PAGE_SIZE>>(needed))
+            .map_err(|_| ())?;
+        let num_pages = pages.num_pages();
+        let base = pages.as_mut_ptr() as *mut u8;
+
+        // If talc won't take the pages, they go straight back
+        let heap = unsafe { talc.claim(Span::from_base_size(base, num_pages * PAGE_SIZE))? };
+        talc.oom_handler.spans[slot] = HeapSpan {
+            pages: pages.leak() as *mut u8 as usize,
+            num_pages,
+            heap,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(feature = "test")]
+pub fn test() {
+    use alloc::vec;
+
+    let before = stats();
+    let big = vec![0xa5u8; 16 * RELEASE_THRESHOLD];
+    let during = stats();
+    assert!(during.size >= before.size + big.len());
+    assert!(big.iter().all(|&byte| byte == 0xa5));
+
+    drop(big);
+    let after = stats();
+    assert!(after.size < during.size);
+}
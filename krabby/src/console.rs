@@ -1,13 +1,17 @@
 //! Kernel console
 use crate::{
+    allocator,
     functions::{self, GroupBytesBy},
-    globals, println,
+    globals,
+    loader::ElfFile,
+    println,
     process::Process,
     scheduler,
     serial::Serial,
     userspace, KernelError, KernelResult,
 };
-use core::{fmt::Display, ptr};
+use alloc::format;
+use core::fmt::Display;
 use crusty_line::CrustyLine;
 use owo_colors::OwoColorize;
 use schmargs::Schmargs;
@@ -39,7 +43,7 @@ fn parse_line(line: &str) -> KernelResult<()> {
 
     match command {
         HelpArgs::NAME | "?" => {
-            let command_vector: [(&'static str, &'static str, &dyn Display); 6] = [
+            let command_vector: [(&'static str, &'static str, &dyn Display); 7] = [
                 (HelpArgs::NAME, HelpArgs::DESCRIPTION, &HelpArgs::help()),
                 (
                     MemdumpArgs::NAME,
@@ -50,6 +54,11 @@ fn parse_line(line: &str) -> KernelResult<()> {
                 (PokeArgs::NAME, PokeArgs::DESCRIPTION, &PokeArgs::help()),
                 (PanicArgs::NAME, PanicArgs::DESCRIPTION, &PanicArgs::help()),
                 (CsrArgs::NAME, CsrArgs::DESCRIPTION, &CsrArgs::help()),
+                (
+                    MeminfoArgs::NAME,
+                    MeminfoArgs::DESCRIPTION,
+                    &MeminfoArgs::help(),
+                ),
             ];
 
             let args = HelpArgs::parse(args)?;
@@ -131,16 +140,33 @@ fn parse_line(line: &str) -> KernelResult<()> {
             functions::show_csr_registers(&registers, false)?;
         }
 
+        // Kernel heap usage
+        MeminfoArgs::NAME => {
+            MeminfoArgs::parse(args)?;
+            let stats = allocator::stats();
+            println!(
+                "Heap size: {} KiB in {} spans",
+                stats.size / 1024,
+                stats.spans
+            );
+            println!("Allocated: {} KiB", stats.allocated / 1024);
+            println!(
+                "Free:      {} KiB",
+                stats.size.saturating_sub(stats.allocated) / 1024
+            );
+        }
+
         // Run process
         RunArgs::NAME => {
-            let RunArgs { address } = RunArgs::parse(args)?;
-
-            let size = userspace::dratinit::BIN.len();
-            let address = address.unwrap_or(ptr::addr_of!(userspace::dratinit::BIN) as *const u8);
-            let entry_offset = userspace::dratinit::ENTRY_OFFSET;
+            let RunArgs { path } = RunArgs::parse(args)?;
 
-            let process = unsafe { Process::new(address, size, entry_offset)? };
-            scheduler::start_with(process);
+            let thread = if let Some(path) = path {
+                let file = ElfFile::read(path)?;
+                Process::new(file.bytes(), format!("{path}\0").as_bytes())?
+            } else {
+                Process::new(userspace::dratinit::ELF, b"dratinit\0")?
+            };
+            scheduler::start_with(thread);
         }
 
         _ => {
@@ -210,10 +236,15 @@ struct CsrArgs<'a> {
     registers: Option<alloc::vec::Vec<&'a str>>,
 }
 
+/// Show kernel heap usage
+#[derive(Schmargs)]
+#[schmargs(name = "meminfo")]
+struct MeminfoArgs {}
+
 /// Run program
 #[derive(Schmargs)]
 #[schmargs(name = "run")]
-struct RunArgs {
-    /// The address of the code
-    address: Option<*const u8>,
+struct RunArgs<'a> {
+    /// Path to an ELF file on disk. Runs the built-in init if omitted
+    path: Option<&'a str>,
 }
@@ -6,6 +6,9 @@ use core::{
 };
 use derive_more::{From, Into};
 
+/// Most harts we support. Any beyond this are left parked
+pub const MAX_HARTS: usize = 8;
+
 /// Hart (hardware thread) ID type
 #[derive(Copy, From, Into, Clone, PartialEq, Eq, Debug)]
 #[into(usize)]
@@ -23,6 +26,19 @@ impl HartId {
     pub const fn is_zero(self) -> bool {
         self.0 == 0
     }
+
+    /// The hart we're running on
+    ///
+    /// The kernel keeps this in `tp`, which is restored from the kernel trap frame on every trap
+    pub fn current() -> Self {
+        Self(Register::ThreadPointer.value())
+    }
+}
+
+impl From<usize> for HartId {
+    fn from(hart: usize) -> Self {
+        Self(hart)
+    }
 }
 
 impl Display for HartId {
@@ -77,6 +93,8 @@ pub enum Register {
     ReturnAddress = 1,
     StackPointer,
     GlobalPointer,
+    /// Holds the [HartId] while in the kernel
+    ThreadPointer,
     /// Argment 0 or return value
     Arg0 = 10,
     /// Argument 1 or secondary return value
@@ -99,6 +117,7 @@ impl Register {
                 ReturnAddress => asm!("mv {}, ra", out(reg) val),
                 StackPointer => asm!("mv {}, sp", out(reg) val),
                 GlobalPointer => asm!("mv {}, gp", out(reg) val),
+                ThreadPointer => asm!("mv {}, tp", out(reg) val),
                 Arg0 => asm!("mv {}, a0", out(reg) val),
                 Arg1 => asm!("mv {}, a1", out(reg) val),
                 Arg2 => asm!("mv {}, a2", out(reg) val),
@@ -119,6 +138,7 @@ impl Register {
             ReturnAddress => "ra",
             StackPointer => "sp",
             GlobalPointer => "gp",
+            ThreadPointer => "tp",
             Arg0 => "a0",
             Arg1 => "a1",
             Arg2 => "a2",
@@ -10,11 +10,16 @@ use crate::{
     prelude::*,
     util::*,
 };
-use core::{mem::size_of, ptr::write_volatile, time::Duration};
+use core::{
+    mem::size_of,
+    ptr::{read_volatile, write_volatile},
+    time::Duration,
+};
 
 const NANOS_PER_SECOND: u128 = 1_000_000_000;
 // This is not a typo - it's 4094, not 4096
 const CLINT_MAX_HARTS: usize = 4094;
+const CLINT_MSIP_REG: usize = 0x0000;
 const CLINT_MTIMECMP_REG: usize = 0x4000;
 const CLINT_MTIME_REG: usize = 0xbff8;
 
@@ -32,6 +37,11 @@ impl Driver {
         let address = self.base_address.wrapping_byte_add(offset);
         unsafe { write_volatile(address, value) }
     }
+
+    unsafe fn read(&self, offset: usize) -> u64 {
+        let address = self.base_address.wrapping_byte_add(offset);
+        unsafe { read_volatile(address) }
+    }
 }
 
 impl TimerDriver for Driver {
@@ -43,11 +53,24 @@ impl TimerDriver for Driver {
         let val = cycles as u64;
         let offset = CLINT_MTIMECMP_REG + hart * size_of::<u64>();
 
+        // mtime is shared by all harts, so leave it alone and count from wherever it's at
         unsafe {
-            self.write(CLINT_MTIME_REG, 0);
-            self.write(offset, val);
+            let now = self.read(CLINT_MTIME_REG);
+            self.write(offset, now.wrapping_add(val));
         }
     }
+
+    fn send_ipi(&mut self, hart: HartId) {
+        let hart = usize::from(hart);
+        assert!(hart < CLINT_MAX_HARTS);
+
+        // MSIP registers are only 32 bits wide
+        let address = self
+            .base_address
+            .wrapping_byte_add(CLINT_MSIP_REG + hart * size_of::<u32>())
+            .cast::<u32>();
+        unsafe { write_volatile(address, 1) }
+    }
 }
 
 fn load(info: &LoadContext) -> KernelResult<Option<LoadResult>> {
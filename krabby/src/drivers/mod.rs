@@ -1,9 +1,6 @@
 //! Drivers and driver accessories
-use crate::{interrupts, prelude::*, process::ProcessState, scheduler};
-use alloc::{
-    collections::{BTreeSet, VecDeque},
-    sync::Arc,
-};
+use crate::{interrupts, prelude::*, tty};
+use alloc::{collections::BTreeSet, sync::Arc};
 use core::{fmt::Debug, time::Duration};
 use fdt::{node::FdtNode, Fdt};
 use spin::{Mutex, RwLock};
@@ -65,30 +62,22 @@ impl Drivers {
                 (*self.uart.write()).get_or_insert(driver.clone());
                 if let Some(int_id) = info.interrupts.first() {
                     let driver = driver.clone();
-                    // TODO: this should be a ringbuffer
-                    let uart_buffer = Arc::new(Mutex::new((Utf8Parser::new(), VecDeque::new())));
-                    interrupts::register_handler(*int_id, move |int_id| {
-                        // Push next chars into buffer
-                        let mut driver = driver.lock();
-                        let mut buffer = uart_buffer.lock();
-                        while let Some(byte) = driver.coupling.next_byte() {
-                            if let Some(ch) = buffer.0.push(byte)? {
-                                buffer.1.push_back(ch);
+                    let parser = Mutex::new(Utf8Parser::new());
+                    interrupts::register_handler(*int_id, move |_int_id| {
+                        // Drain the UART first, since echoing input needs the driver
+                        let mut chars = Vec::new();
+                        {
+                            let mut driver = driver.lock();
+                            let mut parser = parser.lock();
+                            while let Some(byte) = driver.coupling.next_byte() {
+                                if let Some(ch) = parser.push(byte)? {
+                                    chars.push(ch);
+                                }
                             }
                         }
 
-                        // And send to process
-                        scheduler::on_interrupt(int_id, |process| {
-                            let mut temp_buffer = Default::default();
-                            core::mem::swap(&mut buffer.1, &mut temp_buffer);
-                            process.stdin_buffer.extend(temp_buffer.into_iter());
-                            if let Some(c) = process.stdin_buffer.pop_front() {
-                                process.frame.as_mut().set_return_value(&Ok(c as usize));
-                                process.state = ProcessState::Ready;
-                            }
-                            Ok(())
-                        })?;
-                        Ok(())
+                        // And send to the terminal
+                        tty::receive(&chars)
                     });
                 }
             }
@@ -187,6 +176,9 @@ pub trait InterruptControllerDriver: Debug + Send {
 /// Driver for a CPU timer
 pub trait TimerDriver: Debug + Send {
     fn set_alarm(&mut self, hart: HartId, duration: Duration);
+
+    /// Send an inter-processor interrupt to `hart`
+    fn send_ipi(&mut self, hart: HartId);
 }
 
 /// Block device driver (e.g. SSD/MMC)
@@ -27,7 +27,10 @@ const SECTOR_SIZE: usize = 512;
 struct HalImpl;
 unsafe impl Hal for HalImpl {
     fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
-        let virt_address: *mut [Page<PAGE_SIZE>] = mmu::zalloc_slice(pages).leak();
+        // The HAL has no way to report failure
+        let virt_address: *mut [Page<PAGE_SIZE>] = mmu::zalloc_slice(pages)
+            .expect("Out of memory for DMA")
+            .leak();
         let phys_address =
             usize::from(mmu::ks_vaddr_to_paddr(virt_address as *mut u8 as usize).unwrap());
 
@@ -6,7 +6,8 @@ use core::{
     str::Utf8Error,
 };
 use crusty_line::CrustyLineError;
-use krabby_abi::KrabbyAbiError;
+use elf::ParseError as ElfParseError;
+use krabby_abi::{fs::FileDescriptor, Errno, KrabbyAbiError};
 use schmargs::{SchmargsError, StrippedSchmargsError};
 use utf8_parser::Utf8ParserError;
 use virtio_drivers::{transport::mmio::MmioError, Error as VirtioError};
@@ -32,6 +33,42 @@ pub enum KernelError {
     /// Unexpected end of input
     #[display("Unexpected end of input")]
     EndOfInput,
+    /// Write could not complete
+    #[display("Failed to write whole buffer")]
+    WriteZero,
+    /// Operation not permitted, e.g. writing to a file opened read-only
+    #[display("Operation not permitted")]
+    NotPermitted,
+    /// No such file or directory
+    #[display("Not found")]
+    NotFound,
+    /// File or directory already exists
+    #[display("Already exists")]
+    AlreadyExists,
+    /// Directory can't be removed while it has entries
+    #[display("Directory not empty")]
+    DirectoryNotEmpty,
+    /// Expected a directory
+    #[display("Not a directory")]
+    NotADirectory,
+    /// Expected a file, but got a directory
+    #[display("Is a directory")]
+    IsADirectory,
+    /// Filesystem is full
+    #[display("No space left on device")]
+    NoSpace,
+    /// Blocking call was interrupted by a signal
+    #[display("Interrupted")]
+    Interrupted,
+    /// Blocking call gave up waiting
+    #[display("Timed out")]
+    TimedOut,
+    /// Operation has to wait, e.g. reading from an empty pipe
+    #[display("Would block")]
+    WouldBlock,
+    /// Wrote to a pipe whose read end is closed
+    #[display("Broken pipe")]
+    BrokenPipe,
     /// Attempted to access forbidden page
     #[display("Forbidden page")]
     ForbiddenPage,
@@ -41,9 +78,21 @@ pub enum KernelError {
     /// No such process
     #[display("Process not found: {}", _0)]
     ProcessNotFound(Pid),
+    /// Waited on a process that isn't a child, or was already waited on
+    #[display("Not a child: {}", _0)]
+    NotAChild(Pid),
     /// Invalid PID
     #[display("Invalid PID: {}", _0)]
     InvalidPid(usize),
+    /// File descriptor is not open
+    #[display("Bad file descriptor: {}", _0)]
+    BadFileDescriptor(FileDescriptor),
+    /// Arguments and environment don't fit on the new stack
+    #[display("Argument list too long")]
+    ArgumentListTooLong,
+    /// No free file descriptors left
+    #[display("Too many open files")]
+    TooManyOpenFiles,
     /// Invalid Interrupt ID
     #[display("Invalid interruptId: {}", _0)]
     InvalidIntId(usize),
@@ -68,9 +117,24 @@ pub enum KernelError {
     /// Address is not mapped to kernel space
     #[display("Not mapped: {}", _0)]
     NotMapped(usize),
+    /// Address is already mapped
+    #[display("Already mapped: {}", _0)]
+    AlreadyMapped(usize),
+    /// User page would be both writable and executable
+    #[display("Writable and executable: {}", _0)]
+    WritableAndExecutable(usize),
+    /// Ran out of memory or address space
+    #[display("Out of memory")]
+    OutOfMemory,
+    /// Page is shared by too many page tables
+    #[display("Too many references to page")]
+    TooManyReferences,
     /// Misaligned size
     #[display("Size is misaligned: {}", _0)]
     SizeMisaligned(usize),
+    /// Executable can't be loaded
+    #[display("Invalid ELF: {}", _0)]
+    InvalidElf(&'static str),
     /// Missing FDT node property
     #[display("Missing FDT node property: {}", _0)]
     MissingProperty(&'static str),
@@ -98,6 +162,9 @@ pub enum KernelError {
     /// Converted from [crusty_line::CrustyLineError]
     #[from]
     CrustyLineError(CrustyLineError),
+    /// Converted from [elf::ParseError]
+    #[from]
+    ElfParseError(ElfParseError),
     /// Converted from [virtio_drivers::Error]
     #[from]
     VirtioError(VirtioError),
@@ -106,9 +173,61 @@ pub enum KernelError {
     MmioError(MmioError),
 }
 
-impl From<KernelError> for usize {
-    fn from(_: KernelError) -> Self {
-        1
+impl From<&KernelError> for Errno {
+    fn from(err: &KernelError) -> Self {
+        match err {
+            KernelError::InvalidArguments
+            | KernelError::Conversion
+            | KernelError::InvalidPid(_)
+            | KernelError::SizeMisaligned(_)
+            | KernelError::Utf8Error(_)
+            | KernelError::ParseIntError(_)
+            | KernelError::Utf8ParserError(_)
+            | KernelError::SchmargsError(_)
+            | KernelError::TryFromIntError(_) => Errno::InvalidArgument,
+            KernelError::InvalidSyscall(_) => Errno::InvalidSyscall,
+            KernelError::ProcessNotFound(_) => Errno::NoSuchProcess,
+            KernelError::NotAChild(_) => Errno::NoChild,
+            KernelError::BadFileDescriptor(_) => Errno::BadFileDescriptor,
+            KernelError::TooManyOpenFiles => Errno::TooManyOpenFiles,
+            KernelError::NotFound => Errno::NotFound,
+            KernelError::AlreadyExists => Errno::AlreadyExists,
+            KernelError::DirectoryNotEmpty => Errno::DirectoryNotEmpty,
+            KernelError::NotADirectory => Errno::NotADirectory,
+            KernelError::IsADirectory => Errno::IsADirectory,
+            KernelError::NoSpace => Errno::NoSpace,
+            KernelError::NotPermitted | KernelError::WritableAndExecutable(_) => {
+                Errno::NotPermitted
+            }
+            KernelError::Interrupted => Errno::Interrupted,
+            KernelError::TimedOut => Errno::TimedOut,
+            KernelError::BrokenPipe => Errno::BrokenPipe,
+            KernelError::ForbiddenPage
+            | KernelError::NullPointer
+            | KernelError::InvalidVirtualAddress(_)
+            | KernelError::AddressNotPageAligned(_)
+            | KernelError::NotMapped(_) => Errno::BadAddress,
+            KernelError::OutOfMemory | KernelError::TooManyReferences => Errno::OutOfMemory,
+            KernelError::ArgumentListTooLong => Errno::ArgumentListTooLong,
+            KernelError::InvalidElf(_) | KernelError::ElfParseError(_) => Errno::InvalidExecutable,
+            KernelError::EndOfInput => Errno::EndOfInput,
+            KernelError::WriteZero => Errno::WriteZero,
+            KernelError::FileSystem(_)
+            | KernelError::DriverFailure(_)
+            | KernelError::DriverUninitialized
+            | KernelError::VirtioError(_)
+            | KernelError::MmioError(_) => Errno::Io,
+            KernelError::KrabbyAbiError(err) => Errno::from(*err),
+            KernelError::Generic(_)
+            | KernelError::WouldBlock
+            | KernelError::InvalidIntId(_)
+            | KernelError::InterruptUnavailable
+            | KernelError::InvalidPhysicalAddress(_)
+            | KernelError::AlreadyMapped(_)
+            | KernelError::MissingProperty(_)
+            | KernelError::FmtError(_)
+            | KernelError::CrustyLineError(_) => Errno::Failure,
+        }
     }
 }
 
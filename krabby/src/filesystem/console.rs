@@ -0,0 +1,38 @@
+//! The console as a file, which is where standard output and error go unless redirected
+use super::{FileRef, FileRefImpl};
+use crate::prelude::*;
+use alloc::sync::Arc;
+use krabby_abi::fs::Whence;
+use utf8_parser::Utf8Parser;
+
+#[derive(Debug)]
+struct Console;
+
+impl FileRefImpl for Console {
+    fn read_blocking(&self, _buffer: &mut [u8]) -> KernelResult<usize> {
+        Err(KernelError::NotPermitted)
+    }
+
+    fn seek_blocking(&self, _offset: isize, _whence: Whence) -> KernelResult<usize> {
+        Err(KernelError::InvalidArguments)
+    }
+
+    fn write_blocking(&self, buffer: &[u8]) -> KernelResult<usize> {
+        let mut parser = Utf8Parser::new();
+        for byte in buffer {
+            if let Some(ch) = parser.push(*byte)? {
+                print!("{ch}");
+            }
+        }
+        Ok(buffer.len())
+    }
+
+    fn truncate_blocking(&self, _size: usize) -> KernelResult<()> {
+        Err(KernelError::InvalidArguments)
+    }
+}
+
+/// Open the console for writing
+pub fn open() -> FileRef {
+    FileRef(Arc::new(Console))
+}
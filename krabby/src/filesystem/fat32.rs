@@ -4,14 +4,21 @@ use crate::{
     prelude::*,
 };
 use alloc::sync::Arc;
-use core::cmp;
-use fatfs::{FileSystem as Fat, IoBase, IoError, Read, Seek, SeekFrom, Write};
+use core::{cmp, fmt};
+use fatfs::{IoBase, IoError, Read, Seek, SeekFrom, Write};
+use krabby_abi::fs::{DirEntry, FileType, OpenFlags, Whence};
 use spin::Mutex;
 
 impl From<fatfs::Error<Self>> for KernelError {
     fn from(err: fatfs::Error<Self>) -> Self {
         match err {
             fatfs::Error::Io(err) => err,
+            fatfs::Error::NotFound => Self::NotFound,
+            fatfs::Error::AlreadyExists => Self::AlreadyExists,
+            fatfs::Error::DirectoryIsNotEmpty => Self::DirectoryNotEmpty,
+            fatfs::Error::NotEnoughSpace => Self::NoSpace,
+            fatfs::Error::UnexpectedEof => Self::EndOfInput,
+            fatfs::Error::WriteZero => Self::WriteZero,
             err => Self::FileSystem(Box::new(err)),
         }
     }
@@ -22,10 +29,10 @@ impl IoError for KernelError {
         false
     }
     fn new_unexpected_eof_error() -> Self {
-        todo!()
+        Self::EndOfInput
     }
     fn new_write_zero_error() -> Self {
-        todo!()
+        Self::WriteZero
     }
 }
 
@@ -33,25 +40,31 @@ impl IoBase for Hal {
     type Error = KernelError;
 }
 
+const SECTOR_SIZE: usize = 512;
+
 struct Hal {
+    // Capacity in bytes
     capacity: usize,
     sector_size: usize,
     pos: usize,
-    temp_buffer: [u8; 512],
+    temp_buffer: [u8; SECTOR_SIZE],
     driver: Arc<Mutex<Driver<dyn BlockDriver>>>,
 }
 
 impl Hal {
     fn new(driver: Arc<Mutex<Driver<dyn BlockDriver>>>) -> KernelResult<Self> {
-        let (capacity, sector_size) = {
+        let (sectors, sector_size) = {
             let mut driver = driver.lock();
             (driver.coupling.capacity()?, driver.coupling.sector_size()?)
         };
+        if sector_size != SECTOR_SIZE {
+            return Err(KernelError::Generic("Unsupported sector size"));
+        }
         Ok(Self {
-            capacity,
+            capacity: sectors * sector_size,
             sector_size,
             pos: 0,
-            temp_buffer: [0; 512],
+            temp_buffer: [0; SECTOR_SIZE],
             driver,
         })
     }
@@ -80,65 +93,267 @@ impl Read for Hal {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         assert!(!buf.is_empty());
 
-        let bytes_to_read = buf.len();
         let sector_size = self.sector_size;
-        let mut start = self.pos;
-        let end = self.pos + bytes_to_read;
+        let bytes_to_read = cmp::min(buf.len(), self.capacity.saturating_sub(self.pos));
 
         let mut driver = self.driver.lock();
         let driver = &mut driver.coupling;
 
-        while start < end {
-            let start_offset = start - start.align_down(sector_size);
-            driver.read_blocking(start.align_down(sector_size), &mut self.temp_buffer)?;
+        let mut done = 0;
+        while done < bytes_to_read {
+            let sector = (self.pos + done).align_down(sector_size);
+            let offset = self.pos + done - sector;
+            let size = cmp::min(sector_size - offset, bytes_to_read - done);
 
-            buf[..cmp::min(sector_size - start_offset, end - start)].copy_from_slice(
-                &self.temp_buffer[start_offset
-                    ..(cmp::min(sector_size - start_offset, end - start) + start_offset)],
-            );
+            driver.read_blocking(sector, &mut self.temp_buffer)?;
+            buf[done..done + size].copy_from_slice(&self.temp_buffer[offset..offset + size]);
 
-            start = start.align_next(sector_size);
+            done += size;
         }
 
-        self.pos += bytes_to_read;
+        self.pos += done;
 
-        Ok(bytes_to_read)
+        Ok(done)
     }
 }
 
 impl Write for Hal {
     fn flush(&mut self) -> Result<(), Self::Error> {
+        // Writes go straight to the device
         Ok(())
     }
 
-    fn write(&mut self, _buf: &[u8]) -> Result<usize, Self::Error> {
-        todo!("impl Write")
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        assert!(!buf.is_empty());
+
+        let sector_size = self.sector_size;
+        let bytes_to_write = cmp::min(buf.len(), self.capacity.saturating_sub(self.pos));
+
+        let mut driver = self.driver.lock();
+        let driver = &mut driver.coupling;
+
+        let mut done = 0;
+        while done < bytes_to_write {
+            let sector = (self.pos + done).align_down(sector_size);
+            let offset = self.pos + done - sector;
+            let size = cmp::min(sector_size - offset, bytes_to_write - done);
+
+            // Read-modify-write, unless we're overwriting the whole sector
+            if size != sector_size {
+                driver.read_blocking(sector, &mut self.temp_buffer)?;
+            }
+            self.temp_buffer[offset..offset + size].copy_from_slice(&buf[done..done + size]);
+            driver.write_blocking(sector, &mut self.temp_buffer)?;
+
+            done += size;
+        }
+
+        self.pos += done;
+
+        Ok(done)
     }
 }
 
 #[derive(Debug)]
-struct Fat32FileRefImpl;
+struct Fat32FileRefImpl {
+    fs: Fat32FileSystem,
+    path: String,
+    // Shared by every file descriptor referring to this open file
+    position: Mutex<usize>,
+    flags: OpenFlags,
+}
 
 impl FileRefImpl for Fat32FileRefImpl {
-    fn read_blocking(&mut self, _buffer: &mut [u8]) -> KernelResult<()> {
-        todo!()
+    fn read_blocking(&self, buffer: &mut [u8]) -> KernelResult<usize> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(KernelError::NotPermitted);
+        }
+        let mut position = self.position.lock();
+        let bytes_read = self
+            .fs
+            .clone()
+            .read_blocking(&self.path, buffer, *position)?;
+        *position += bytes_read;
+        Ok(bytes_read)
+    }
+
+    fn seek_blocking(&self, offset: isize, whence: Whence) -> KernelResult<usize> {
+        let mut position = self.position.lock();
+        let base = match whence {
+            Whence::Start => 0,
+            Whence::Current => *position,
+            Whence::End => self.fs.clone().size_blocking(&self.path)?,
+        };
+        *position = base
+            .checked_add_signed(offset)
+            .ok_or(KernelError::InvalidArguments)?;
+        Ok(*position)
+    }
+
+    fn write_blocking(&self, buffer: &[u8]) -> KernelResult<usize> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(KernelError::NotPermitted);
+        }
+        let mut fs = self.fs.clone();
+        let mut position = self.position.lock();
+        if self.flags.contains(OpenFlags::APPEND) {
+            *position = fs.size_blocking(&self.path)?;
+        }
+        let bytes_written = fs.write_blocking(&self.path, buffer, *position)?;
+        *position += bytes_written;
+        Ok(bytes_written)
+    }
+
+    fn truncate_blocking(&self, size: usize) -> KernelResult<()> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(KernelError::NotPermitted);
+        }
+        self.fs.clone().truncate_blocking(&self.path, size)
+    }
+}
+
+#[derive(Debug)]
+struct Fat32DirRefImpl {
+    fs: Fat32FileSystem,
+    path: String,
+    // Number of entries already read
+    index: Mutex<usize>,
+}
+
+impl FileRefImpl for Fat32DirRefImpl {
+    fn read_blocking(&self, _buffer: &mut [u8]) -> KernelResult<usize> {
+        Err(KernelError::IsADirectory)
+    }
+
+    // Seeking moves between entries, so a directory can be listed again from the start
+    fn seek_blocking(&self, offset: isize, whence: Whence) -> KernelResult<usize> {
+        let mut index = self.index.lock();
+        let base = match whence {
+            Whence::Start => 0,
+            Whence::Current => *index,
+            Whence::End => return Err(KernelError::InvalidArguments),
+        };
+        *index = base
+            .checked_add_signed(offset)
+            .ok_or(KernelError::InvalidArguments)?;
+        Ok(*index)
+    }
+
+    fn write_blocking(&self, _buffer: &[u8]) -> KernelResult<usize> {
+        Err(KernelError::IsADirectory)
+    }
+
+    fn truncate_blocking(&self, _size: usize) -> KernelResult<()> {
+        Err(KernelError::IsADirectory)
+    }
+
+    fn read_dir_blocking(&self, buffer: &mut [u8]) -> KernelResult<usize> {
+        let mut index = self.index.lock();
+        let fat = self.fs.fat.lock();
+        let dir = open_dir(&fat, &self.path)?;
+
+        let mut bytes_read = 0;
+        // fatfs can't resume iterating, so skip what was already read
+        let mut skip = *index;
+        for entry in dir.iter() {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name == "." || name == ".." {
+                continue;
+            }
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+
+            let (file_type, size) = if entry.is_dir() {
+                (FileType::Directory, 0)
+            } else {
+                (FileType::File, entry.len())
+            };
+            let entry = DirEntry {
+                name: &name,
+                file_type,
+                size,
+            };
+
+            match entry.encode(&mut buffer[bytes_read..]) {
+                Some(len) => {
+                    bytes_read += len;
+                    *index += 1;
+                }
+                // The buffer can't even fit one entry
+                None if bytes_read == 0 => return Err(KernelError::InvalidArguments),
+                None => break,
+            }
+        }
+        Ok(bytes_read)
     }
 }
 
+type Fat = fatfs::FileSystem<Hal, fatfs::NullTimeProvider, fatfs::LossyOemCpConverter>;
+type Dir<'a> = fatfs::Dir<'a, Hal, fatfs::NullTimeProvider, fatfs::LossyOemCpConverter>;
+
+/// Handle to a mounted FAT32 volume. Clones refer to the same volume
+#[derive(Clone)]
 pub struct Fat32FileSystem {
-    fat: Fat<Hal, fatfs::NullTimeProvider, fatfs::LossyOemCpConverter>,
+    fat: Arc<Mutex<Fat>>,
+}
+
+impl fmt::Debug for Fat32FileSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "<Fat32FileSystem>")
+    }
 }
 
 impl FileSystem for Fat32FileSystem {
-    fn open_blocking(&mut self, _path: impl AsRef<str>) -> KernelResult<FileRef> {
-        Ok(FileRef(Box::new(Fat32FileRefImpl)))
+    fn open_blocking(&mut self, path: impl AsRef<str>, flags: OpenFlags) -> KernelResult<FileRef> {
+        let path = relative(path.as_ref());
+
+        if flags.contains(OpenFlags::DIRECTORY) {
+            if flags.contains(OpenFlags::WRITE) || flags.contains(OpenFlags::CREATE) {
+                return Err(KernelError::IsADirectory);
+            }
+            open_dir(&self.fat.lock(), path)?;
+            return Ok(FileRef(Arc::new(Fat32DirRefImpl {
+                fs: self.clone(),
+                path: path.into(),
+                index: Mutex::new(0),
+            })));
+        }
+
+        // Make sure the file actually exists before handing out a reference to it
+        {
+            let fat = self.fat.lock();
+            let file = if flags.contains(OpenFlags::CREATE) {
+                fat.root_dir().create_file(path)
+            } else {
+                fat.root_dir().open_file(path)
+            };
+            // fatfs reports opening a directory as a file as invalid input
+            let mut file = file.map_err(|err| match err {
+                fatfs::Error::InvalidInput => KernelError::IsADirectory,
+                err => err.into(),
+            })?;
+            if flags.contains(OpenFlags::TRUNCATE) && flags.contains(OpenFlags::WRITE) {
+                file.truncate()?;
+            }
+        }
+
+        Ok(FileRef(Arc::new(Fat32FileRefImpl {
+            fs: self.clone(),
+            path: path.into(),
+            position: Mutex::new(0),
+            flags,
+        })))
     }
 
     fn list_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<Vec<String>> {
-        let dir = self.fat.root_dir().open_dir(path.as_ref()).unwrap();
+        let fat = self.fat.lock();
+        let dir = open_dir(&fat, relative(path.as_ref()))?;
         let mut vec = Vec::new();
         for item in dir.iter() {
-            vec.push(item.unwrap().file_name());
+            vec.push(item?.file_name());
         }
         Ok(vec)
     }
@@ -148,10 +363,75 @@ impl FileSystem for Fat32FileSystem {
         path: impl AsRef<str>,
         buffer: &mut [u8],
         offset: usize,
-    ) -> KernelResult<()> {
-        let mut file = self.fat.root_dir().open_file(path.as_ref())?;
+    ) -> KernelResult<usize> {
+        let fat = self.fat.lock();
+        let mut file = fat.root_dir().open_file(relative(path.as_ref()))?;
         file.seek(SeekFrom::Start(offset.try_into()?))?;
-        file.read_exact(buffer)?;
+
+        let mut bytes_read = 0;
+        while bytes_read < buffer.len() {
+            let size = file.read(&mut buffer[bytes_read..])?;
+            if size == 0 {
+                break;
+            }
+            bytes_read += size;
+        }
+        Ok(bytes_read)
+    }
+
+    fn size_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<usize> {
+        let fat = self.fat.lock();
+        let mut file = fat.root_dir().open_file(relative(path.as_ref()))?;
+        Ok(file.seek(SeekFrom::End(0))?.try_into()?)
+    }
+
+    fn write_blocking(
+        &mut self,
+        path: impl AsRef<str>,
+        buffer: &[u8],
+        offset: usize,
+    ) -> KernelResult<usize> {
+        let fat = self.fat.lock();
+        let mut file = fat.root_dir().open_file(relative(path.as_ref()))?;
+
+        // Writing past the end leaves a hole, which reads back as zeroes
+        let size = file.seek(SeekFrom::End(0))?;
+        let offset = u64::try_from(offset)?;
+        if offset > size {
+            fill_zeroes(&mut file, offset - size)?;
+        }
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(buffer)?;
+        file.flush()?;
+        Ok(buffer.len())
+    }
+
+    fn truncate_blocking(&mut self, path: impl AsRef<str>, size: usize) -> KernelResult<()> {
+        let fat = self.fat.lock();
+        let mut file = fat.root_dir().open_file(relative(path.as_ref()))?;
+
+        let current_size = file.seek(SeekFrom::End(0))?;
+        let size = u64::try_from(size)?;
+        if size > current_size {
+            fill_zeroes(&mut file, size - current_size)?;
+        } else {
+            file.seek(SeekFrom::Start(size))?;
+            file.truncate()?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    fn mkdir_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<()> {
+        let fat = self.fat.lock();
+        fat.root_dir().create_dir(relative(path.as_ref()))?;
+        Ok(())
+    }
+
+    fn remove_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<()> {
+        let fat = self.fat.lock();
+        fat.root_dir().remove(relative(path.as_ref()))?;
         Ok(())
     }
 }
@@ -159,7 +439,37 @@ impl FileSystem for Fat32FileSystem {
 impl Fat32FileSystem {
     pub fn new(driver: Arc<Mutex<Driver<dyn BlockDriver>>>) -> KernelResult<Self> {
         Ok(Self {
-            fat: Fat::new(Hal::new(driver)?, Default::default()).unwrap(),
+            fat: Arc::new(Mutex::new(
+                Fat::new(Hal::new(driver)?, Default::default()).unwrap(),
+            )),
         })
     }
 }
+
+// Write `count` zeroes at the current position
+fn fill_zeroes(file: &mut impl Write<Error = KernelError>, mut count: u64) -> KernelResult<()> {
+    const ZEROES: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+    while count > 0 {
+        let size = cmp::min(count, ZEROES.len() as u64);
+        file.write_all(&ZEROES[..size as usize])?;
+        count -= size;
+    }
+    Ok(())
+}
+
+// Open a directory by its relative path, which is empty for the root directory
+fn open_dir<'a>(fat: &'a Fat, path: &str) -> KernelResult<Dir<'a>> {
+    if path.is_empty() {
+        return Ok(fat.root_dir());
+    }
+    fat.root_dir().open_dir(path).map_err(|err| match err {
+        // fatfs reports opening a file as a directory as invalid input
+        fatfs::Error::InvalidInput => KernelError::NotADirectory,
+        err => err.into(),
+    })
+}
+
+// fatfs paths are relative to the root directory
+fn relative(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
@@ -1,29 +1,122 @@
-use crate::prelude::*;
+use crate::{drivers::DRIVERS, prelude::*, process::BlockCondition};
+use alloc::sync::Arc;
 use core::fmt;
+use fat32::Fat32FileSystem;
+use krabby_abi::fs::{OpenFlags, Whence};
+use spin::Mutex;
 
+pub mod console;
 pub mod fat32;
+pub mod pipe;
+
+/// The root filesystem. Mounted from the first block device on first use
+static ROOT_FILESYSTEM: Mutex<Option<Fat32FileSystem>> = Mutex::new(None);
+
+/// Get a handle to the root filesystem, mounting it if necessary
+pub fn root() -> KernelResult<Fat32FileSystem> {
+    let mut root = ROOT_FILESYSTEM.lock();
+    if let Some(fs) = &*root {
+        return Ok(fs.clone());
+    }
+
+    let device = DRIVERS
+        .block
+        .read()
+        .as_ref()
+        .cloned()
+        .ok_or(KernelError::DriverUninitialized)?;
+    let fs = Fat32FileSystem::new(device)?;
+    *root = Some(fs.clone());
+    Ok(fs)
+}
 
 /// Reference to an open file, meant to be stored in a process's file descriptor table
-#[derive(Debug)]
-pub struct FileRef(Box<dyn FileRefImpl>);
+///
+/// Clones refer to the same open file, and share its position
+#[derive(Clone, Debug)]
+pub struct FileRef(Arc<dyn FileRefImpl>);
+
+trait FileRefImpl: fmt::Debug + Send + Sync {
+    fn read_blocking(&self, buffer: &mut [u8]) -> KernelResult<usize>;
+    fn seek_blocking(&self, offset: isize, whence: Whence) -> KernelResult<usize>;
+    fn write_blocking(&self, buffer: &[u8]) -> KernelResult<usize>;
+    fn truncate_blocking(&self, size: usize) -> KernelResult<()>;
 
-trait FileRefImpl: fmt::Debug + Send {
-    fn read_blocking(&mut self, buffer: &mut [u8]) -> KernelResult<()>;
+    fn read_dir_blocking(&self, _buffer: &mut [u8]) -> KernelResult<usize> {
+        Err(KernelError::NotADirectory)
+    }
+
+    // What a read or write that failed with [KernelError::WouldBlock] has to wait for, or
+    // `None` if it can go ahead now
+    fn wait_condition(&self, _write: bool) -> Option<BlockCondition> {
+        None
+    }
 }
 
-impl FileRefImpl for FileRef {
-    fn read_blocking(&mut self, buffer: &mut [u8]) -> KernelResult<()> {
+impl FileRef {
+    /// Read into `buffer` from the current position
+    ///
+    /// Returns the number of bytes read, which is zero at the end of the file. Fails with
+    /// [KernelError::WouldBlock] if there's nothing to read yet
+    pub fn read_blocking(&self, buffer: &mut [u8]) -> KernelResult<usize> {
         self.0.read_blocking(buffer)
     }
+
+    /// Move the current position
+    ///
+    /// Returns the new position
+    pub fn seek_blocking(&self, offset: isize, whence: Whence) -> KernelResult<usize> {
+        self.0.seek_blocking(offset, whence)
+    }
+
+    /// Write `buffer` at the current position
+    ///
+    /// Returns the number of bytes written. Fails with [KernelError::WouldBlock] if there's no
+    /// room to write yet
+    pub fn write_blocking(&self, buffer: &[u8]) -> KernelResult<usize> {
+        self.0.write_blocking(buffer)
+    }
+
+    /// Shrink or extend the file to `size` bytes
+    pub fn truncate_blocking(&self, size: usize) -> KernelResult<()> {
+        self.0.truncate_blocking(size)
+    }
+
+    /// Read encoded [DirEntry](krabby_abi::fs::DirEntry)s into `buffer`, if this is a directory
+    ///
+    /// Returns the number of bytes read, which is zero once every entry has been read
+    pub fn read_dir_blocking(&self, buffer: &mut [u8]) -> KernelResult<usize> {
+        self.0.read_dir_blocking(buffer)
+    }
+
+    /// Condition to block on after a read or write failed with [KernelError::WouldBlock]
+    ///
+    /// `None` means the file became ready in the meantime
+    pub fn wait_condition(&self, write: bool) -> Option<BlockCondition> {
+        self.0.wait_condition(write)
+    }
 }
 
 pub trait FileSystem {
-    fn open_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<FileRef>;
+    fn open_blocking(&mut self, path: impl AsRef<str>, flags: OpenFlags) -> KernelResult<FileRef>;
     fn list_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<Vec<String>>;
+    /// Returns the number of bytes read, which may be short at the end of the file
     fn read_blocking(
         &mut self,
         path: impl AsRef<str>,
         buffer: &mut [u8],
         offset: usize,
-    ) -> KernelResult<()>;
+    ) -> KernelResult<usize>;
+    fn size_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<usize>;
+    /// Returns the number of bytes written
+    fn write_blocking(
+        &mut self,
+        path: impl AsRef<str>,
+        buffer: &[u8],
+        offset: usize,
+    ) -> KernelResult<usize>;
+    fn truncate_blocking(&mut self, path: impl AsRef<str>, size: usize) -> KernelResult<()>;
+    fn mkdir_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<()>;
+    /// Remove a file or empty directory
+    fn remove_blocking(&mut self, path: impl AsRef<str>) -> KernelResult<()>;
 }
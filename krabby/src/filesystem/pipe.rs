@@ -0,0 +1,167 @@
+//! Pipes, one-way channels between processes through a kernel buffer
+//!
+//! Reading from an empty pipe or writing to a full one blocks until the other end catches up.
+//! Once the write end is closed, reads drain what's left and then hit the end of the file, and
+//! once the read end is closed, writes fail
+use super::{FileRef, FileRefImpl};
+use crate::{prelude::*, process::BlockCondition, scheduler};
+use alloc::{collections::VecDeque, sync::Arc};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use krabby_abi::fs::Whence;
+use spin::Mutex;
+
+// Bytes a pipe holds before writers have to wait
+const PIPE_CAPACITY: usize = 4096;
+
+static NEXT_PIPE_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+struct Pipe {
+    buffer: VecDeque<u8>,
+    read_open: bool,
+    write_open: bool,
+}
+
+#[derive(Debug)]
+struct PipeReader {
+    id: usize,
+    pipe: Arc<Mutex<Pipe>>,
+}
+
+#[derive(Debug)]
+struct PipeWriter {
+    id: usize,
+    pipe: Arc<Mutex<Pipe>>,
+}
+
+/// Create a pipe
+///
+/// Returns its read end and its write end
+pub fn new() -> (FileRef, FileRef) {
+    let id = NEXT_PIPE_ID.fetch_add(1, Ordering::Relaxed);
+    let pipe = Arc::new(Mutex::new(Pipe {
+        buffer: VecDeque::new(),
+        read_open: true,
+        write_open: true,
+    }));
+    (
+        FileRef(Arc::new(PipeReader {
+            id,
+            pipe: pipe.clone(),
+        })),
+        FileRef(Arc::new(PipeWriter { id, pipe })),
+    )
+}
+
+impl FileRefImpl for PipeReader {
+    fn read_blocking(&self, buffer: &mut [u8]) -> KernelResult<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let (was_full, len) = {
+            let mut pipe = self.pipe.lock();
+            if pipe.buffer.is_empty() {
+                // Nothing more is coming once the write end is closed
+                return if pipe.write_open {
+                    Err(KernelError::WouldBlock)
+                } else {
+                    Ok(0)
+                };
+            }
+
+            let was_full = pipe.buffer.len() == PIPE_CAPACITY;
+            let len = buffer.len().min(pipe.buffer.len());
+            for (dst, src) in buffer.iter_mut().zip(pipe.buffer.drain(..len)) {
+                *dst = src;
+            }
+            (was_full, len)
+        };
+
+        if was_full {
+            scheduler::retry_blocked(BlockCondition::OnPipeWrite(self.id));
+        }
+        Ok(len)
+    }
+
+    fn seek_blocking(&self, _offset: isize, _whence: Whence) -> KernelResult<usize> {
+        Err(KernelError::InvalidArguments)
+    }
+
+    fn write_blocking(&self, _buffer: &[u8]) -> KernelResult<usize> {
+        Err(KernelError::NotPermitted)
+    }
+
+    fn truncate_blocking(&self, _size: usize) -> KernelResult<()> {
+        Err(KernelError::InvalidArguments)
+    }
+
+    fn wait_condition(&self, write: bool) -> Option<BlockCondition> {
+        let pipe = self.pipe.lock();
+        (!write && pipe.buffer.is_empty() && pipe.write_open)
+            .then_some(BlockCondition::OnPipeRead(self.id))
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.pipe.lock().read_open = false;
+        // Writers find out the hard way
+        scheduler::retry_blocked(BlockCondition::OnPipeWrite(self.id));
+    }
+}
+
+impl FileRefImpl for PipeWriter {
+    fn read_blocking(&self, _buffer: &mut [u8]) -> KernelResult<usize> {
+        Err(KernelError::NotPermitted)
+    }
+
+    fn seek_blocking(&self, _offset: isize, _whence: Whence) -> KernelResult<usize> {
+        Err(KernelError::InvalidArguments)
+    }
+
+    fn write_blocking(&self, buffer: &[u8]) -> KernelResult<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let (was_empty, len) = {
+            let mut pipe = self.pipe.lock();
+            if !pipe.read_open {
+                return Err(KernelError::BrokenPipe);
+            }
+            let room = PIPE_CAPACITY - pipe.buffer.len();
+            if room == 0 {
+                return Err(KernelError::WouldBlock);
+            }
+
+            let was_empty = pipe.buffer.is_empty();
+            let len = buffer.len().min(room);
+            pipe.buffer.extend(&buffer[..len]);
+            (was_empty, len)
+        };
+
+        if was_empty {
+            scheduler::retry_blocked(BlockCondition::OnPipeRead(self.id));
+        }
+        Ok(len)
+    }
+
+    fn truncate_blocking(&self, _size: usize) -> KernelResult<()> {
+        Err(KernelError::InvalidArguments)
+    }
+
+    fn wait_condition(&self, write: bool) -> Option<BlockCondition> {
+        let pipe = self.pipe.lock();
+        (write && pipe.buffer.len() == PIPE_CAPACITY && pipe.read_open)
+            .then_some(BlockCondition::OnPipeWrite(self.id))
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.pipe.lock().write_open = false;
+        // Readers see the end of the file
+        scheduler::retry_blocked(BlockCondition::OnPipeRead(self.id));
+    }
+}
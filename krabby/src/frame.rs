@@ -6,7 +6,7 @@ use core::{
     ptr,
     sync::atomic::{AtomicU32, Ordering},
 };
-use krabby_abi::ProcessResult;
+use krabby_abi::{wait::WaitStatus, Errno};
 
 /// Put trap frame in scratch register
 pub fn set_kernel_trap_frame(hart: HartId) {
@@ -24,10 +24,12 @@ pub fn set_kernel_trap_frame(hart: HartId) {
     let mut frame = mmu::zalloc::<TrapFrame>(TrapFrame {
         regs: Default::default(),
         pid: None,
+        tid: None,
         root_page_table: ptr::null_mut(),
         satp: mmu::ks_satp().expect("Failed to get SATP").into(),
         kernel_frame: ptr::null(),
-    });
+    })
+    .expect("Failed to allocate trap frame");
     // Self referential
     frame.as_mut().kernel_frame = frame.as_const_ptr();
     // Set stack and global
@@ -37,6 +39,8 @@ pub fn set_kernel_trap_frame(hart: HartId) {
     frame
         .as_mut()
         .set_reg(Register::GlobalPointer, Register::GlobalPointer.value());
+    // Restored on every trap, so we always know which hart we're on
+    frame.as_mut().set_reg(Register::ThreadPointer, hart);
 
     set_current_trap_frame(frame.leak());
 }
@@ -84,6 +88,8 @@ pub struct TrapFrame {
     pub kernel_frame: *const TrapFrame,
     /// Process ID (0 if kernel)
     pub pid: Option<Pid>,
+    /// Thread ID (0 if kernel)
+    pub tid: Option<Pid>,
     /// Supervisor Address Translation/Protection register
     /// (physical address of the root page table)
     pub satp: usize,
@@ -112,25 +118,24 @@ impl TrapFrame {
         self.set_reg(Register::StackPointer, val)
     }
 
-    /// Set the return value (a0/a1)
+    /// Set the return value (a0), and the [Errno] (a1) on failure
     pub fn set_return_value<T: Into<usize> + Copy>(&mut self, val: &KernelResult<T>) {
-        if let Ok(val) = val {
-            self.set_reg(Register::Arg0, (*val).into());
-            self.set_reg(Register::Arg1, 0);
-        } else {
-            self.set_reg(Register::Arg0, 0);
-            self.set_reg(Register::Arg1, 1);
+        match val {
+            Ok(val) => {
+                self.set_reg(Register::Arg0, (*val).into());
+                self.set_reg(Register::Arg1, 0);
+            }
+            Err(err) => {
+                self.set_reg(Register::Arg0, 0);
+                self.set_reg(Register::Arg1, Errno::from(err).into());
+            }
         }
     }
 
-    /// Set the return value (a0/a1) from exit result
-    pub fn set_exit_value(&mut self, val: ProcessResult) {
-        self.set_reg(Register::Arg0, 0);
-        if val.is_ok() {
-            self.set_reg(Register::Arg1, 0);
-        } else {
-            self.set_reg(Register::Arg1, 1);
-        }
+    /// Set the return value (a0/a1) of a successful wait to `status`
+    pub fn set_wait_status(&mut self, status: WaitStatus) {
+        self.set_reg(Register::Arg0, status.into());
+        self.set_reg(Register::Arg1, 0);
     }
 
     /// Get root page table
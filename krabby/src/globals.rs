@@ -1,4 +1,5 @@
 //! Global data that is initialized once and only once
+use core::ops::Range;
 use fdt::Fdt;
 
 // WARNING: This needs to only be initialized ONCE
@@ -10,6 +11,8 @@ static mut GLOBAL_DATA: Option<GlobalData> = None;
 pub struct GlobalData {
     /// The device tree passed from the bootloader
     pub device_tree: Fdt<'static>,
+    /// Physical memory the device tree sits in, so it isn't handed out as free memory
+    pub device_tree_memory: Range<usize>,
 }
 
 /// Initialize global data
@@ -18,12 +21,15 @@ pub struct GlobalData {
 ///
 /// This must be exactly once, and there must be no other reads during initialization. This means
 /// not calling [get]
-pub unsafe fn initialize(device_tree: Fdt<'static>) {
+pub unsafe fn initialize(device_tree: Fdt<'static>, device_tree_memory: Range<usize>) {
     unsafe {
         if GLOBAL_DATA.is_some() {
             panic!("Already initialized global data");
         }
-        GLOBAL_DATA = Some(GlobalData { device_tree })
+        GLOBAL_DATA = Some(GlobalData {
+            device_tree,
+            device_tree_memory,
+        })
     }
 }
 
@@ -5,7 +5,7 @@
 //#![warn(missing_docs)]
 extern crate alloc;
 
-mod allocator;
+pub mod allocator;
 mod asm;
 pub mod console;
 pub mod cpu;
@@ -17,14 +17,19 @@ pub mod functions;
 pub mod globals;
 pub mod idle;
 pub mod interrupts;
+pub mod loader;
 pub mod mmu;
 pub mod panic;
+pub mod physical_memory;
 pub mod process;
 pub mod scheduler;
 pub mod serial;
+pub mod shared_memory;
+pub mod smp;
 pub mod syscalls;
 pub mod timer;
 pub mod trap;
+pub mod tty;
 pub mod userspace;
 pub mod util;
 
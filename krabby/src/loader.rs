@@ -0,0 +1,144 @@
+//! ELF executable loader
+use crate::{
+    filesystem::{self, FileSystem},
+    mmu::{self, Page, PageAllocation, PageType, Sv39PageTable, PAGE_SIZE},
+    prelude::*,
+    util::*,
+};
+use core::{cmp, slice};
+use elf::{abi, endian::LittleEndian, file::Class, ElfBytes};
+
+/// Lowest address a user program may be loaded at. Everything below is reserved for the kernel
+pub const USERSPACE_VADDR_START: usize = 0xf000_0000;
+
+/// An ELF file read from disk into kernel memory
+#[derive(Debug)]
+pub struct ElfFile {
+    // The kernel heap is tiny, so the file is staged in pages instead
+    buffer: PageAllocation<[Page<PAGE_SIZE>]>,
+    size: usize,
+}
+
+impl ElfFile {
+    /// Read an ELF file from the root filesystem
+    pub fn read(path: impl AsRef<str>) -> KernelResult<Self> {
+        let path = path.as_ref();
+        let mut fs = filesystem::root()?;
+        let size = fs.size_blocking(path)?;
+        if size == 0 {
+            return Err(KernelError::InvalidElf("Empty file"));
+        }
+
+        let mut file = Self {
+            buffer: mmu::zalloc_slice(align_up::<PAGE_SIZE>(size) / PAGE_SIZE)?,
+            size,
+        };
+        let bytes = unsafe { slice::from_raw_parts_mut(file.buffer.as_mut_ptr() as *mut u8, size) };
+        if fs.read_blocking(path, bytes, 0)? != size {
+            return Err(KernelError::EndOfInput);
+        }
+
+        Ok(file)
+    }
+
+    /// Contents of the file
+    pub fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.buffer.as_const_ptr() as *const u8, self.size) }
+    }
+}
+
+/// Where a program ended up after [load]
+#[derive(Copy, Clone, Debug)]
+pub struct Image {
+    /// Address of the first instruction
+    pub entry: usize,
+    /// Page-aligned address immediately after the highest segment
+    pub end: usize,
+}
+
+/// Map the loadable segments of an ELF file into a fresh user page table
+///
+/// Each segment gets the permissions it asks for, so text is read-execute, data and .bss
+/// read-write, and read-only data read-only. Segments that are both writable and executable are
+/// rejected. If this fails, `table` may be left partially filled and should be dropped
+pub fn load(table: &mut Sv39PageTable, bytes: &[u8]) -> KernelResult<Image> {
+    let file = ElfBytes::<LittleEndian>::minimal_parse(bytes)?;
+    let ehdr = file.ehdr;
+    if ehdr.class != Class::ELF64 {
+        return Err(KernelError::InvalidElf("Not a 64-bit ELF"));
+    }
+    if ehdr.e_machine != abi::EM_RISCV {
+        return Err(KernelError::InvalidElf("Not a RISC-V ELF"));
+    }
+    if ehdr.e_type != abi::ET_EXEC {
+        return Err(KernelError::InvalidElf("Not an executable"));
+    }
+
+    let mut end = 0;
+    let phdrs = file
+        .segments()
+        .ok_or(KernelError::InvalidElf("No program headers"))?;
+    for phdr in phdrs.iter().filter(|phdr| phdr.p_type == abi::PT_LOAD) {
+        let vaddr = usize::try_from(phdr.p_vaddr)?;
+        let mem_size = usize::try_from(phdr.p_memsz)?;
+        let data = file.segment_data(&phdr)?;
+        if data.len() > mem_size {
+            return Err(KernelError::InvalidElf(
+                "Segment file size exceeds memory size",
+            ));
+        }
+        if mem_size == 0 {
+            continue;
+        }
+        if vaddr < USERSPACE_VADDR_START {
+            return Err(KernelError::InvalidElf("Segment overlaps kernel space"));
+        }
+
+        let (read, write, execute) = (
+            phdr.p_flags & abi::PF_R != 0,
+            phdr.p_flags & abi::PF_W != 0,
+            phdr.p_flags & abi::PF_X != 0,
+        );
+        let page_type = match (read, write, execute) {
+            (_, true, true) => {
+                return Err(KernelError::InvalidElf(
+                    "Segment is both writable and executable",
+                ))
+            }
+            (true, false, true) => PageType::UserReadExecute,
+            (false, false, true) => PageType::UserExecute,
+            (_, true, false) => PageType::UserReadWrite,
+            (_, false, false) => PageType::UserReadOnly,
+        };
+
+        let segment_end = vaddr
+            .checked_add(mem_size)
+            .ok_or(KernelError::InvalidElf("Segment out of range"))?;
+        let data_end = vaddr + data.len();
+
+        // Whatever isn't copied from the file stays zeroed, which takes care of .bss
+        // Segments sharing a page fail here with `AlreadyMapped`, since each page gets a single
+        // set of permissions
+        for page_vaddr in (align_down::<PAGE_SIZE>(vaddr)..segment_end).step_by(PAGE_SIZE) {
+            let page = mmu::map_new_user_page(table, page_vaddr.try_into()?, page_type)?;
+
+            let copy_start = cmp::max(page_vaddr, vaddr);
+            let copy_end = cmp::min(page_vaddr + PAGE_SIZE, data_end);
+            if copy_start < copy_end {
+                page.0[copy_start - page_vaddr..copy_end - page_vaddr]
+                    .copy_from_slice(&data[copy_start - vaddr..copy_end - vaddr]);
+            }
+        }
+
+        end = cmp::max(end, align_up::<PAGE_SIZE>(segment_end));
+    }
+
+    if end == 0 {
+        return Err(KernelError::InvalidElf("No loadable segments"));
+    }
+
+    Ok(Image {
+        entry: usize::try_from(ehdr.e_entry)?,
+        end,
+    })
+}
@@ -10,8 +10,9 @@ use krabby::{
     drivers::{ns16550::Ns16550Driver, UartDriver, DRIVERS},
     frame, globals, mmu,
     mmu::PAGE_SIZE,
+    physical_memory,
     prelude::*,
-    timer,
+    scheduler, smp, timer,
     util::*,
 };
 use owo_colors::OwoColorize;
@@ -20,10 +21,13 @@ extern "C" {
     fn enter_supervisor_mode(pmo: isize) -> !;
 }
 
+// How often each hart's alarm goes off
+const TIMER_PERIOD: Duration = Duration::from_millis(100);
+
 /// Machine pre-mmu entry point
 #[no_mangle]
 unsafe fn boot(hart_id: HartId, fdt_ptr: *const u8, pmo: isize) {
-    // Boot should only see the hart 0
+    // The other harts stay parked until kmain wakes them
     assert!(hart_id.is_zero());
 
     // Early init uart
@@ -37,14 +41,13 @@ unsafe fn boot(hart_id: HartId, fdt_ptr: *const u8, pmo: isize) {
     // Initialize global variables
     uart_driver.send_str("> fdt\n");
     let fdt_size = unsafe { Fdt::from_ptr(fdt_ptr).unwrap().total_size() };
-    let fdt_ptr = mmu::map_device(
-        align_down::<PAGE_SIZE>(fdt_ptr as usize),
-        align_up::<PAGE_SIZE>(fdt_size),
-    )
-    .unwrap();
+    let fdt_memory = align_down::<PAGE_SIZE>(fdt_ptr as usize)
+        ..align_up::<PAGE_SIZE>(fdt_ptr as usize + fdt_size);
+    let fdt_ptr = mmu::map_device(fdt_memory.start, fdt_memory.len()).unwrap()
+        + (fdt_ptr as usize - fdt_memory.start);
     unsafe {
         let fdt = Fdt::from_ptr(fdt_ptr as *const u8).unwrap();
-        globals::initialize(fdt);
+        globals::initialize(fdt, fdt_memory);
     };
 
     uart_driver.send_str("> initializing mmu\n");
@@ -59,10 +62,12 @@ unsafe fn boot(hart_id: HartId, fdt_ptr: *const u8, pmo: isize) {
 /// Supervisor entry point
 #[no_mangle]
 unsafe fn kmain() {
-    // TODO: set hart ID
     // Set trap frame
     frame::set_kernel_trap_frame(HartId::zero());
 
+    // Hand the rest of RAM to the page allocator
+    physical_memory::init(&globals::get().device_tree).unwrap();
+
     // Initialize drivers
     DRIVERS.init(&globals::get().device_tree).unwrap();
 
@@ -76,7 +81,12 @@ unsafe fn kmain() {
     }
 
     // Initialize timer
-    timer::set_timer_period(HartId::zero(), Duration::from_millis(100)).unwrap();
+    timer::init(&globals::get().device_tree).unwrap();
+    timer::set_timer_period(HartId::zero(), TIMER_PERIOD).unwrap();
+
+    // Bring up the other harts
+    smp::set_online();
+    smp::start_harts(&globals::get().device_tree).unwrap();
 
     println!("{}", "Welcome to KabutOS!!!".cyan().bold());
 
@@ -87,3 +97,21 @@ unsafe fn kmain() {
         run_console();
     }
 }
+
+/// Supervisor entry point for every hart but the first
+#[no_mangle]
+unsafe fn kmain_secondary(hart_id: HartId) {
+    frame::set_kernel_trap_frame(hart_id);
+
+    unsafe {
+        riscv::register::sstatus::set_spie();
+        riscv::register::sstatus::set_sum();
+        // External interrupts are left to the first hart
+        riscv::register::sie::set_ssoft();
+    }
+
+    timer::set_timer_period(hart_id, TIMER_PERIOD).unwrap();
+    smp::set_online();
+
+    scheduler::start();
+}
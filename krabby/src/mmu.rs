@@ -2,10 +2,23 @@
 //! free(entry_addr);
 //!
 //! Most of this is based off <https://osblog.stephenmarz.com/ch3.2.html>
-use crate::{prelude::*, util::aligned};
+use crate::{
+    cpu::MAX_HARTS,
+    prelude::*,
+    smp,
+    util::{align_down, align_next, align_up, aligned},
+};
 use alloc::sync::Arc;
 use bilge::prelude::*;
-use core::{ffi::c_void, fmt::Debug, mem, ptr};
+use core::{
+    cmp,
+    ffi::c_void,
+    fmt::Debug,
+    mem,
+    ops::Range,
+    ptr, slice,
+    sync::atomic::{AtomicU8, Ordering},
+};
 use page_alloc::RecordsPage;
 use spin::{Mutex, RwLock};
 
@@ -18,41 +31,69 @@ pub static PHYSICAL_MEMORY_OFFSET: RwLock<isize> = RwLock::new(0);
 extern "C" {
     static mut table_heap_bottom: RecordsPage<PAGE_SIZE>;
     static table_heap_top: c_void;
+    static virtual_base: c_void;
     static kernel_start: c_void;
+    static kernel_end: c_void;
+    static stack_guard: c_void;
     static stack_bottom: c_void;
     static stack_top: c_void;
+    static hart_stacks_bottom: c_void;
+    static hart_stacks_top: c_void;
 }
 
 pub const PAGE_SIZE: usize = 4096;
+// Size of the pages mapped by a leaf in a level one table
+const MEGAPAGE_SIZE: usize = PAGE_SIZE * ENTRIES_IN_PAGE_TABLE;
+/// Size of the kernel stack of each hart besides hart 0
+pub const HART_STACK_SIZE: usize = 64 * 1024;
+// Each of those stacks sits above its own guard page
+const HART_STACK_SLOT_SIZE: usize = PAGE_SIZE + HART_STACK_SIZE;
 const MAX_VIRTUAL_ADDRESS: usize = (1 << 39) - 1;
 const MAX_PHYSICAL_ADDRESS: usize = (1 << 56) - 1;
 const ENTRIES_IN_PAGE_TABLE: usize = 512;
 
+/// How user space touched a page
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PageType {
     UserReadOnly,
     UserReadWrite,
+    UserReadExecute,
     UserExecute,
     Kernel,
 }
 
 impl PageType {
     const fn read(self) -> bool {
-        true
+        !matches!(self, Self::UserExecute)
     }
 
     const fn write(self) -> bool {
-        matches!(self, Self::UserExecute | Self::UserReadWrite | Self::Kernel)
+        matches!(self, Self::UserReadWrite | Self::Kernel)
     }
 
     const fn execute(self) -> bool {
-        matches!(self, Self::UserExecute | Self::Kernel)
+        matches!(
+            self,
+            Self::UserReadExecute | Self::UserExecute | Self::Kernel
+        )
     }
 
     const fn user(self) -> bool {
         !matches!(self, Self::Kernel)
     }
 
+    /// Can pages of this type be written to?
+    pub const fn writable(self) -> bool {
+        self.write()
+    }
+
     const fn global(self) -> bool {
         !self.user()
     }
@@ -75,11 +116,57 @@ pub struct Sv39PageTableEntry {
     _reserved: u10,
 }
 
+// Software-defined bit (in the RSW field) marking a read-only mapping of a page that's
+// logically writable, but shared with another page table
+const COPY_ON_WRITE_BIT: u64 = 1 << 8;
+// Software-defined bit marking a mapping of shared memory, whose page belongs to a
+// [SharedAllocation] rather than the page tables mapping it
+const SHARED_BIT: u64 = 1 << 9;
+
 impl Sv39PageTableEntry {
     const fn zero() -> Self {
         Self { value: 0 }
     }
 
+    /// Is this a read-only mapping that gets copied on the first write?
+    pub fn copy_on_write(&self) -> bool {
+        self.value & COPY_ON_WRITE_BIT != 0
+    }
+
+    fn set_copy_on_write(&mut self, copy_on_write: bool) {
+        if copy_on_write {
+            self.value |= COPY_ON_WRITE_BIT;
+        } else {
+            self.value &= !COPY_ON_WRITE_BIT;
+        }
+    }
+
+    /// Does this map a page of shared memory?
+    pub fn shared(&self) -> bool {
+        self.value & SHARED_BIT != 0
+    }
+
+    // Is this a user page owned by the page tables mapping it?
+    fn owned_user_page(&self) -> bool {
+        self.user() && !self.shared()
+    }
+
+    // Leaf entry mapping a single page
+    fn leaf(paddr: Sv39PhysicalAddress, page_type: PageType) -> Self {
+        Self::new(
+            true,
+            page_type.read(),
+            page_type.write(),
+            page_type.execute(),
+            page_type.user(),
+            page_type.global(),
+            Default::default(),
+            paddr.ppn0(),
+            paddr.ppn1(),
+            paddr.ppn2(),
+        )
+    }
+
     /// Is this a leaf node(i.e., does not link to a page table)?
     pub fn is_leaf(&self) -> bool {
         self.read() | self.write() | self.execute()
@@ -317,9 +404,60 @@ fn map_kernel_space_with_pmo_offset(
         )?;
     }
 
+    // Stacks for the other harts, leaving out the guard pages
+    unsafe {
+        let hart_stacks_bottom_addr = ptr::from_ref(&hart_stacks_bottom) as usize;
+        let hart_stacks_top_addr = ptr::from_ref(&hart_stacks_top) as usize;
+        assert_eq!(
+            hart_stacks_top_addr - hart_stacks_bottom_addr,
+            (MAX_HARTS - 1) * HART_STACK_SLOT_SIZE
+        );
+        for slot in (hart_stacks_bottom_addr..hart_stacks_top_addr).step_by(HART_STACK_SLOT_SIZE) {
+            let stack_bottom_addr = slot + PAGE_SIZE;
+            map_range(
+                table,
+                Sv39VirtualAddress::try_from(
+                    stack_bottom_addr.checked_add_signed(-pmo_offset).unwrap(),
+                )?,
+                Sv39PhysicalAddress::try_from(stack_bottom_addr.checked_add_signed(pmo).unwrap())?,
+                PageType::Kernel,
+                HART_STACK_SIZE,
+            )?;
+        }
+    }
+
+    // RAM added to the page allocator, copied out so mapping can allocate page tables
+    let zones = *ZONES.lock();
+    for zone in zones.as_slice() {
+        map_kernel_memory(
+            table,
+            zone.start,
+            zone.start.checked_add_signed(pmo).unwrap(),
+            zone.end() - zone.start,
+        )?;
+    }
+
     Ok(())
 }
 
+/// Get the guard page and the top of the kernel stack belonging to `hart`
+pub fn kernel_stack(hart: HartId) -> (usize, usize) {
+    let hart = usize::from(hart);
+    assert!(hart < MAX_HARTS);
+    unsafe {
+        if hart == 0 {
+            (
+                ptr::from_ref(&stack_guard) as usize,
+                ptr::from_ref(&stack_top) as usize,
+            )
+        } else {
+            let guard =
+                ptr::from_ref(&hart_stacks_bottom) as usize + (hart - 1) * HART_STACK_SLOT_SIZE;
+            (guard, guard + HART_STACK_SLOT_SIZE)
+        }
+    }
+}
+
 /// Set the root page table address
 pub fn set_root_page_table(asid: u16, paddr: Sv39PhysicalAddress) {
     // PPN is 4k-aligned, and we don't store the trailing zeroes
@@ -381,11 +519,10 @@ fn vaddr_to_paddr_inner(
             if user_only && !entry.user() {
                 return Err(KernelError::ForbiddenPage);
             }
-            return entry.physical_address().offset(
-                u16::from(vaddr.page_offset())
-                    .try_into()
-                    .expect("isize should hold u16"),
-            );
+            // Leaves above the last level map bigger pages
+            let page_size = PAGE_SIZE << (9 * (2 - step));
+            let offset = usize::from(vaddr) & (page_size - 1);
+            return entry.physical_address().offset(offset as isize);
         }
 
         table = unsafe {
@@ -403,7 +540,9 @@ fn vaddr_to_paddr_inner(
 
 fn clean_page_table(table: &Sv39PageTable, pmo: isize) -> KernelResult<()> {
     for entry in table.entries {
-        if entry.valid() && !entry.is_leaf() {
+        if entry.valid() && entry.is_leaf() && entry.owned_user_page() {
+            release_user_page(entry.physical_address(), pmo);
+        } else if entry.valid() && !entry.is_leaf() {
             let entry: usize = entry.physical_address().to_vaddr_with_pmo(pmo)?.into();
             unsafe {
                 free(entry as *mut Sv39PageTable);
@@ -464,32 +603,55 @@ pub fn map_range(
 }
 
 /// Map a single virtual page to a physical page
+///
+/// User pages are never both writable and executable, so nothing user space writes can be run
 pub fn map_page(
-    mut table: &mut Sv39PageTable,
+    table: &mut Sv39PageTable,
     vaddr: Sv39VirtualAddress,
     paddr: Sv39PhysicalAddress,
     page_type: PageType,
 ) -> KernelResult<()> {
-    if !vaddr.is_page_aligned() {
-        return Err(KernelError::AddressNotPageAligned(usize::from(vaddr)));
-    }
     if !paddr.is_page_aligned() {
         return Err(KernelError::AddressNotPageAligned(usize::from(paddr)));
     }
+    map_entry(table, vaddr, Sv39PageTableEntry::leaf(paddr, page_type))
+}
+
+// Point the leaf entry for `vaddr` at `entry`, creating intermediate tables as needed
+fn map_entry(
+    table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    entry: Sv39PageTableEntry,
+) -> KernelResult<()> {
+    map_entry_at_level(table, vaddr, entry, 0)
+}
+
+// Same as [map_entry], but the leaf goes in a table at `level`, so it maps a bigger page
+fn map_entry_at_level(
+    mut table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    entry: Sv39PageTableEntry,
+    level: usize,
+) -> KernelResult<()> {
+    assert!(level < 2);
+    let page_size = PAGE_SIZE << (9 * level);
+    if usize::from(vaddr) & (page_size - 1) != 0 {
+        return Err(KernelError::AddressNotPageAligned(usize::from(vaddr)));
+    }
 
     let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
 
     // Walk page tables
-    for step in 0..2 {
+    for step in 0..2 - level {
         let index = u16::from([vaddr.vpn2(), vaddr.vpn1()][step]) as usize;
 
-        let mut entry = table.entry(index);
-        if !entry.valid() {
-            let addr = (zalloc::<Page<PAGE_SIZE>>(Default::default()).leak() as usize)
+        let mut branch = table.entry(index);
+        if !branch.valid() {
+            let addr = (zalloc::<Page<PAGE_SIZE>>(Default::default())?.leak() as usize)
                 .checked_add_signed(pmo)
                 .unwrap();
             let phys = Sv39PhysicalAddress::try_from(addr)?;
-            entry = table.set_entry(
+            branch = table.set_entry(
                 index,
                 Sv39PageTableEntry::new(
                     true,
@@ -497,22 +659,23 @@ pub fn map_page(
                     false,
                     false,
                     false,
-                    page_type.global(),
+                    entry.global(),
                     Default::default(),
                     phys.ppn0(),
                     phys.ppn1(),
                     phys.ppn2(),
                 ),
             );
-            assert_eq!(addr, entry.physical_address().into())
+            assert_eq!(addr, branch.physical_address().into())
         }
-        if entry.is_leaf() {
-            panic!("Unexpected leaf node in page table! (step {step}): {entry:?}");
+        // Already covered by a bigger page
+        if branch.is_leaf() {
+            return Err(KernelError::AlreadyMapped(usize::from(vaddr)));
         }
 
         table = unsafe {
             Sv39PageTable::mut_from_addr(
-                entry
+                branch
                     .physical_address()
                     .to_vaddr_with_pmo(pmo)
                     .expect("Table address translation overflow!!")
@@ -521,27 +684,429 @@ pub fn map_page(
         };
     }
 
-    let index = u16::from(vaddr.vpn0()) as usize;
+    let index = u16::from([vaddr.vpn0(), vaddr.vpn1()][level]) as usize;
     if table.entry(index).valid() {
-        panic!("Table already mapped!");
+        return Err(KernelError::AlreadyMapped(usize::from(vaddr)));
+    }
+    // W^X
+    if entry.user() && entry.write() && entry.execute() {
+        return Err(KernelError::WritableAndExecutable(usize::from(vaddr)));
     }
 
-    table.set_entry(
-        index,
-        Sv39PageTableEntry::new(
-            true,
-            page_type.read(),
-            page_type.write(),
-            page_type.execute(),
-            page_type.user(),
-            page_type.global(),
-            Default::default(),
-            paddr.ppn0(),
-            paddr.ppn1(),
-            paddr.ppn2(),
-        ),
-    );
+    table.set_entry(index, entry);
+
+    Ok(())
+}
+
+// Find the leaf entry mapping `vaddr`
+fn leaf_entry_mut(
+    mut table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    pmo: isize,
+) -> KernelResult<&mut Sv39PageTableEntry> {
+    for step in 0..=2 {
+        let index = u16::from([vaddr.vpn2(), vaddr.vpn1(), vaddr.vpn0()][step]) as usize;
+        let entry = table.entry(index);
+
+        if !entry.valid() {
+            return Err(KernelError::NotMapped(vaddr.into()));
+        }
+
+        if entry.is_leaf() {
+            return Ok(&mut table.entries[index]);
+        }
+
+        table = unsafe {
+            Sv39PageTable::mut_from_addr(entry.physical_address().to_vaddr_with_pmo(pmo)?.into())
+        };
+    }
+    panic!("Walked right off the table!");
+}
+
+// Run `f` over every user leaf entry in `table`
+fn for_each_user_leaf(
+    table: &mut Sv39PageTable,
+    level: usize,
+    base: usize,
+    pmo: isize,
+    f: &mut impl FnMut(Sv39VirtualAddress, &mut Sv39PageTableEntry) -> KernelResult<()>,
+) -> KernelResult<()> {
+    for index in 0..ENTRIES_IN_PAGE_TABLE {
+        let entry = table.entry(index);
+        if !entry.valid() {
+            continue;
+        }
 
+        let vaddr = base | (index << (12 + 9 * level));
+        if entry.is_leaf() {
+            if entry.user() {
+                f(vaddr.try_into()?, &mut table.entries[index])?;
+            }
+        } else if level > 0 {
+            let next = unsafe {
+                Sv39PageTable::mut_from_addr(
+                    entry.physical_address().to_vaddr_with_pmo(pmo)?.into(),
+                )
+            };
+            for_each_user_leaf(next, level - 1, vaddr, pmo, f)?;
+        }
+    }
+    Ok(())
+}
+
+// Kernel space address of a page in the page heap
+fn heap_page(paddr: Sv39PhysicalAddress, pmo: isize) -> *mut Page<PAGE_SIZE> {
+    usize::from(paddr).checked_add_signed(-pmo).unwrap() as *mut Page<PAGE_SIZE>
+}
+
+// Add a reference to a user page
+fn retain_user_page(paddr: Sv39PhysicalAddress, pmo: isize) -> KernelResult<()> {
+    let refcount = zone_of(heap_page(paddr, pmo) as usize).refcount(heap_page(paddr, pmo) as usize);
+    refcount
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+            assert!(count > 0);
+            count.checked_add(1)
+        })
+        .map_err(|_| KernelError::TooManyReferences)?;
+    Ok(())
+}
+
+// Drop a reference to a user page, freeing it once nothing maps it anymore
+fn release_user_page(paddr: Sv39PhysicalAddress, pmo: isize) {
+    let page = heap_page(paddr, pmo);
+    let previous = zone_of(page as usize)
+        .refcount(page as usize)
+        .fetch_sub(1, Ordering::AcqRel);
+    assert!(previous > 0, "Released unreferenced user page");
+    if previous == 1 {
+        unsafe {
+            free(page);
+        }
+    }
+}
+
+// Number of page tables mapping a user page
+fn user_page_refcount(paddr: Sv39PhysicalAddress, pmo: isize) -> u8 {
+    let page = heap_page(paddr, pmo) as usize;
+    zone_of(page).refcount(page).load(Ordering::Acquire)
+}
+
+// Allocate a zeroed user page with one reference
+fn alloc_user_page(pmo: isize) -> KernelResult<(Sv39PhysicalAddress, *mut Page<PAGE_SIZE>)> {
+    let page = zalloc::<Page<PAGE_SIZE>>(Default::default())?.leak();
+    zone_of(page as usize)
+        .refcount(page as usize)
+        .store(1, Ordering::Release);
+    let paddr = (page as usize)
+        .checked_add_signed(pmo)
+        .unwrap()
+        .try_into()?;
+    Ok((paddr, page))
+}
+
+/// Allocate a zeroed page and map it into user space
+///
+/// The page belongs to `table`, and is freed when the last table mapping it is dropped.
+/// Returns the page so it can be filled in
+pub fn map_new_user_page(
+    table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    page_type: PageType,
+) -> KernelResult<&mut Page<PAGE_SIZE>> {
+    assert!(page_type.user());
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let (paddr, page) = alloc_user_page(pmo)?;
+    if let Err(err) = map_page(table, vaddr, paddr, page_type) {
+        release_user_page(paddr, pmo);
+        return Err(err);
+    }
+    Ok(unsafe { &mut *page })
+}
+
+/// Map every page of a shared allocation into user space, from `vaddr` on
+///
+/// The pages still belong to `shared`, so it has to outlive the mappings
+pub fn map_shared_pages(
+    table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    shared: &SharedAllocation<[Page<PAGE_SIZE>]>,
+    page_type: PageType,
+    stale: &mut StaleMappings,
+) -> KernelResult<()> {
+    assert!(page_type.user());
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    for i in 0..shared.num_pages() {
+        let offset = i * PAGE_SIZE;
+        let paddr = (shared.addr() + offset)
+            .checked_add_signed(pmo)
+            .unwrap()
+            .try_into()?;
+        let mut entry = Sv39PageTableEntry::leaf(paddr, page_type);
+        entry.value |= SHARED_BIT;
+        if let Err(err) = map_entry(table, vaddr.offset(offset as isize)?, entry) {
+            for j in 0..i {
+                unmap_user_page(table, vaddr.offset((j * PAGE_SIZE) as isize)?, stale)?;
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Unmap a user page, freeing it once nothing else maps it
+///
+/// Pages of shared memory aren't freed, since they belong to their [SharedAllocation]. Returns
+/// false if nothing was mapped there. Harts may still have the page in their TLBs, so it's only
+/// let go of once `stale` has flushed them
+pub fn unmap_user_page(
+    table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    stale: &mut StaleMappings,
+) -> KernelResult<bool> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let entry = match leaf_entry_mut(table, vaddr, pmo) {
+        Ok(entry) if entry.user() => entry,
+        Ok(_) | Err(KernelError::NotMapped(_)) => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    let owned = entry.owned_user_page();
+    let paddr = entry.physical_address();
+    *entry = Sv39PageTableEntry::zero();
+    stale.changed = true;
+    if owned {
+        stale.pages.push(paddr);
+    }
+    Ok(true)
+}
+
+/// User mappings that were changed, which harts may still have cached in their TLBs
+///
+/// Dropping this flushes every hart that might, and only then releases the pages and shared
+/// memory that were unmapped. Flushing other harts waits on them, so this must be dropped with
+/// no locks held
+#[derive(Default)]
+pub struct StaleMappings {
+    // Other harts may be running threads that use the page table
+    other_harts: bool,
+    changed: bool,
+    // Unmapped pages, each holding a reference that's released after the flush
+    pages: Vec<Sv39PhysicalAddress>,
+    // Shared memory that was unmapped. Fields are dropped after `drop` runs, so after the flush
+    segments: Vec<Arc<SharedAllocation<[Page<PAGE_SIZE>]>>>,
+}
+
+impl StaleMappings {
+    /// Make other harts flush as well, for page tables that threads on them may be using
+    pub fn include_other_harts(&mut self) {
+        self.other_harts = true;
+    }
+
+    /// Keep shared memory alive until the flush, since it was mapped where it was unmapped from
+    pub fn keep_segment(&mut self, segment: Arc<SharedAllocation<[Page<PAGE_SIZE>]>>) {
+        self.segments.push(segment);
+    }
+}
+
+impl Drop for StaleMappings {
+    fn drop(&mut self) {
+        if !self.changed {
+            return;
+        }
+        if self.other_harts {
+            smp::flush_tlbs();
+        } else {
+            riscv::asm::sfence_vma_all();
+        }
+
+        let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+        for paddr in self.pages.drain(..) {
+            release_user_page(paddr, pmo);
+        }
+    }
+}
+
+/// Share every user page in `parent` with `child`
+///
+/// Writable pages become copy-on-write in both tables, so neither sees the other's writes. Shared
+/// memory stays shared, and the caller has to keep it alive for the child too. Harts may still
+/// have the parent's pages cached as writable until `stale` flushes them
+pub fn fork_user_pages(
+    parent: &mut Sv39PageTable,
+    child: &mut Sv39PageTable,
+    stale: &mut StaleMappings,
+) -> KernelResult<()> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    for_each_user_leaf(parent, 2, 0, pmo, &mut |vaddr, entry| {
+        if entry.shared() {
+            return map_entry(child, vaddr, *entry);
+        }
+        if entry.write() {
+            entry.set_write(false);
+            entry.set_copy_on_write(true);
+            stale.changed = true;
+        }
+        retain_user_page(entry.physical_address(), pmo)?;
+        if let Err(err) = map_entry(child, vaddr, *entry) {
+            release_user_page(entry.physical_address(), pmo);
+            return Err(err);
+        }
+        Ok(())
+    })
+}
+
+/// Check if `table` already lets user space touch the page at `vaddr` the way `access` does
+pub fn user_may_access(
+    table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    access: Access,
+) -> bool {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    leaf_entry_mut(table, vaddr, pmo).is_ok_and(|entry| {
+        entry.user()
+            && match access {
+                Access::Read => entry.read(),
+                Access::Write => entry.write(),
+                Access::Execute => entry.execute(),
+            }
+    })
+}
+
+/// Count the pages mapped into user space by `table`
+pub fn count_user_pages(table: &mut Sv39PageTable) -> KernelResult<usize> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let mut count = 0;
+    for_each_user_leaf(table, 2, 0, pmo, &mut |_, _| {
+        count += 1;
+        Ok(())
+    })?;
+    Ok(count)
+}
+
+/// Resolve a write to a copy-on-write page, giving `table` its own copy if the page is still
+/// shared
+///
+/// Returns false if `vaddr` isn't mapped copy-on-write. Harts may still have the old page cached
+/// until `stale` flushes them
+pub fn copy_on_write(
+    table: &mut Sv39PageTable,
+    vaddr: Sv39VirtualAddress,
+    stale: &mut StaleMappings,
+) -> KernelResult<bool> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let Ok(entry) = leaf_entry_mut(table, vaddr, pmo) else {
+        return Ok(false);
+    };
+    if !entry.user() || !entry.copy_on_write() {
+        return Ok(false);
+    }
+
+    let paddr = entry.physical_address();
+    let shared = user_page_refcount(paddr, pmo) > 1;
+    if shared {
+        let (new_paddr, new_page) = alloc_user_page(pmo)?;
+        unsafe {
+            new_page.copy_from_nonoverlapping(heap_page(paddr, pmo), 1);
+        }
+        *entry = Sv39PageTableEntry::leaf(new_paddr, PageType::UserReadWrite);
+        stale.changed = true;
+        stale.pages.push(paddr);
+    }
+
+    entry.set_write(true);
+    entry.set_copy_on_write(false);
+    Ok(true)
+}
+
+// Kernel space address of the user byte at `vaddr`, if user space may read it (or write it, if
+// `write` is set)
+//
+// Anything else is a `ForbiddenPage`, so the kernel never faults on a bad user pointer
+fn user_address(
+    table: &Sv39PageTable,
+    vaddr: usize,
+    write: bool,
+    pmo: isize,
+) -> KernelResult<usize> {
+    let vaddr = Sv39VirtualAddress::try_from(vaddr).map_err(|_| KernelError::ForbiddenPage)?;
+
+    let mut table = table;
+    for step in 0..=2 {
+        let index = u16::from([vaddr.vpn2(), vaddr.vpn1(), vaddr.vpn0()][step]) as usize;
+        let entry = table.entry(index);
+
+        if !entry.valid() {
+            return Err(KernelError::ForbiddenPage);
+        }
+
+        if entry.is_leaf() {
+            // User space only ever gets single pages
+            if step != 2 || !entry.user() || !entry.read() || (write && !entry.write()) {
+                return Err(KernelError::ForbiddenPage);
+            }
+            let paddr =
+                usize::from(entry.physical_address()) | usize::from(u16::from(vaddr.page_offset()));
+            return paddr
+                .checked_add_signed(-pmo)
+                .ok_or(KernelError::InvalidPhysicalAddress(paddr));
+        }
+
+        table = unsafe {
+            Sv39PageTable::mut_from_addr(entry.physical_address().to_vaddr_with_pmo(pmo)?.into())
+        };
+    }
+    panic!("Walked right off the table!");
+}
+
+/// Run `f` over each page-contiguous chunk of the user buffer at `start`, stopping early if `f`
+/// handles less than the whole chunk
+///
+/// Every page is checked to be user-accessible (and writable, if `write` is set) before `f`
+/// sees it. Returns the total number of bytes handled
+pub fn with_user_buffer(
+    table: &Sv39PageTable,
+    start: usize,
+    len: usize,
+    write: bool,
+    mut f: impl FnMut(&mut [u8]) -> KernelResult<usize>,
+) -> KernelResult<usize> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let end = start.checked_add(len).ok_or(KernelError::ForbiddenPage)?;
+    let mut addr = start;
+
+    // This has to be done per page because the pages may not be contiguous in kernel space
+    while addr < end {
+        let size = cmp::min(end - addr, align_next::<PAGE_SIZE>(addr) - addr);
+        let kernel_addr = user_address(table, addr, write, pmo)?;
+        let chunk = unsafe { slice::from_raw_parts_mut(kernel_addr as *mut u8, size) };
+        let handled = f(chunk)?;
+        addr += handled;
+        if handled < size {
+            break;
+        }
+    }
+
+    Ok(addr - start)
+}
+
+/// Fill `buffer` from user memory at `start`
+pub fn copy_from_user(table: &Sv39PageTable, start: usize, buffer: &mut [u8]) -> KernelResult<()> {
+    let mut offset = 0;
+    with_user_buffer(table, start, buffer.len(), false, |slice| {
+        buffer[offset..][..slice.len()].copy_from_slice(slice);
+        offset += slice.len();
+        Ok(slice.len())
+    })?;
+    Ok(())
+}
+
+/// Copy `buffer` into user memory at `start`
+pub fn copy_to_user(table: &Sv39PageTable, start: usize, buffer: &[u8]) -> KernelResult<()> {
+    let mut offset = 0;
+    with_user_buffer(table, start, buffer.len(), true, |slice| {
+        slice.copy_from_slice(&buffer[offset..][..slice.len()]);
+        offset += slice.len();
+        Ok(slice.len())
+    })?;
     Ok(())
 }
 
@@ -631,7 +1196,7 @@ impl<T: ?Sized> Drop for PageAllocation<T> {
     }
 }
 
-/// A self-deallocating read-only sharable page allocation
+/// A self-deallocating sharable page allocation
 #[derive(Debug)]
 pub struct SharedAllocation<T: ?Sized> {
     address: Option<*const T>,
@@ -674,15 +1239,214 @@ impl<T: ?Sized> Drop for SharedAllocation<T> {
     }
 }
 
-/// Allocate a new T, with page-grain allocation
-pub fn zalloc<T>(obj: T) -> PageAllocation<T> {
-    let records = unsafe { ptr::addr_of_mut!(table_heap_bottom) };
-    let top = unsafe { ptr::from_ref(&table_heap_top) };
-    let first_page_address = records as usize + PAGE_SIZE;
-    let heap_size = (top as usize - first_page_address) / PAGE_SIZE;
+// The most pages one records page can keep track of, at four per byte
+const MAX_ZONE_PAGES: usize = PAGE_SIZE * 4;
+// The most zones of RAM besides the `.table_heap` section
+const MAX_ZONES: usize = 32;
+
+// A run of physical memory the page allocator hands out
+//
+// It starts with a records page for the allocator, then a reference count for each page in
+// case it's mapped into user space, then the pages themselves
+#[derive(Copy, Clone, Debug)]
+struct Zone {
+    // Kernel space address of the records page
+    start: usize,
+    num_pages: usize,
+}
+
+impl Zone {
+    // Lay out a zone in as much of `pages` pages from `start` as it can use
+    const fn new(start: usize, pages: usize) -> Option<Self> {
+        let Some(usable) = pages.checked_sub(1) else {
+            return None;
+        };
+        // Each page takes up a byte of reference count as well as itself
+        let num_pages = usable - usable.div_ceil(PAGE_SIZE + 1);
+        let num_pages = if num_pages > MAX_ZONE_PAGES {
+            MAX_ZONE_PAGES
+        } else {
+            num_pages
+        };
+        if num_pages == 0 {
+            return None;
+        }
+        Some(Self { start, num_pages })
+    }
+
+    fn records(&self) -> *mut RecordsPage<PAGE_SIZE> {
+        self.start as *mut RecordsPage<PAGE_SIZE>
+    }
+
+    fn first_page(&self) -> usize {
+        self.start + (1 + self.num_pages.div_ceil(PAGE_SIZE)) * PAGE_SIZE
+    }
+
+    // Kernel space address right after the zone
+    fn end(&self) -> usize {
+        self.first_page() + self.num_pages * PAGE_SIZE
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        (self.first_page()..self.end()).contains(&address)
+    }
+
+    fn refcount(&self, page: usize) -> &'static AtomicU8 {
+        assert!(self.contains(page) && aligned::<PAGE_SIZE>(page));
+        let index = (page - self.first_page()) / PAGE_SIZE;
+        unsafe { &*((self.start + PAGE_SIZE + index) as *const AtomicU8) }
+    }
+
+    // Take pages with `allocate`, given the zone's records, first page, and number of pages
+    fn allocate<R>(
+        &self,
+        allocate: &mut impl FnMut(&mut RecordsPage<PAGE_SIZE>, *const c_void, usize) -> Option<R>,
+    ) -> Option<R> {
+        let records = unsafe { &mut *self.records() };
+        allocate(records, self.first_page() as *const c_void, self.num_pages)
+    }
+
+    fn deallocate<T: ?Sized>(&self, address: *const T) {
+        let records = unsafe { &mut *self.records() };
+        records.deallocate(self.first_page() as *const c_void, self.num_pages, address);
+    }
+}
+
+// Zones of RAM added by [add_physical_memory]
+#[derive(Copy, Clone)]
+struct Zones {
+    zones: [Zone; MAX_ZONES],
+    count: usize,
+}
+
+impl Zones {
+    fn as_slice(&self) -> &[Zone] {
+        &self.zones[..self.count]
+    }
+}
+
+// Also held while touching the records of any zone, so harts take turns allocating
+static ZONES: Mutex<Zones> = Mutex::new(Zones {
+    zones: [Zone {
+        start: 0,
+        num_pages: 0,
+    }; MAX_ZONES],
+    count: 0,
+});
+
+// The zone in the `.table_heap` section, which is all there is until the rest of RAM is added
+//
+// Its address changes when paging is turned on, so it isn't kept with the other zones
+fn table_heap_zone() -> Zone {
+    let bottom = unsafe { ptr::addr_of!(table_heap_bottom) } as usize;
+    let top = unsafe { ptr::from_ref(&table_heap_top) } as usize;
+    Zone::new(bottom, (top - bottom) / PAGE_SIZE).expect("Table heap too small")
+}
+
+// The zone a page allocation came from
+fn zone_of(address: usize) -> Zone {
+    let table_heap = table_heap_zone();
+    if table_heap.contains(address) {
+        return table_heap;
+    }
+    *ZONES
+        .lock()
+        .as_slice()
+        .iter()
+        .find(|zone| zone.contains(address))
+        .expect("Address isn't in any zone")
+}
 
-    let (address, num_pages) =
-        unsafe { (*records).allocate(first_page_address as *const c_void, heap_size) };
+// Take pages from the first zone with room
+//
+// Zones of RAM go first, saving the table heap for when they run out
+fn allocate<R>(
+    mut allocate: impl FnMut(&mut RecordsPage<PAGE_SIZE>, *const c_void, usize) -> Option<R>,
+) -> KernelResult<R> {
+    let zones = ZONES.lock();
+    zones
+        .as_slice()
+        .iter()
+        .copied()
+        .chain([table_heap_zone()])
+        .find_map(|zone| zone.allocate(&mut allocate))
+        .ok_or(KernelError::OutOfMemory)
+}
+
+/// Hand a range of physical memory to the page allocator
+///
+/// It's mapped into kernel space at the same offset as the kernel, and into every page table
+/// made by [map_kernel_space] from now on
+pub fn add_physical_memory(range: Range<usize>) -> KernelResult<()> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let start = align_up::<PAGE_SIZE>(range.start);
+    let end = align_down::<PAGE_SIZE>(range.end);
+
+    let mut paddr = start;
+    while let Some(zone) = Zone::new(
+        paddr.checked_add_signed(-pmo).unwrap(),
+        end.saturating_sub(paddr) / PAGE_SIZE,
+    ) {
+        if ZONES.lock().count == MAX_ZONES {
+            warn!("Leaving memory from {paddr:#x} unused: too many zones");
+            break;
+        }
+
+        let size = zone.end() - zone.start;
+        map_kernel_memory(&mut ROOT_PAGE_TABLE.lock(), zone.start, paddr, size)?;
+        riscv::asm::sfence_vma_all();
+        zero_out(zone.records(), PAGE_SIZE);
+
+        let mut zones = ZONES.lock();
+        let count = zones.count;
+        zones.zones[count] = zone;
+        zones.count += 1;
+        paddr += size;
+    }
+
+    Ok(())
+}
+
+// Map kernel memory to its physical memory, with megapages wherever a whole one fits
+fn map_kernel_memory(
+    table: &mut Sv39PageTable,
+    vaddr: usize,
+    paddr: usize,
+    size: usize,
+) -> KernelResult<()> {
+    let mut offset = 0;
+    while offset < size {
+        let (vaddr, paddr) = (vaddr + offset, paddr + offset);
+        let entry = Sv39PageTableEntry::leaf(paddr.try_into()?, PageType::Kernel);
+        if aligned::<MEGAPAGE_SIZE>(vaddr)
+            && aligned::<MEGAPAGE_SIZE>(paddr)
+            && size - offset >= MEGAPAGE_SIZE
+        {
+            map_entry_at_level(table, vaddr.try_into()?, entry, 1)?;
+            offset += MEGAPAGE_SIZE;
+        } else {
+            map_entry(table, vaddr.try_into()?, entry)?;
+            offset += PAGE_SIZE;
+        }
+    }
+    Ok(())
+}
+
+/// Physical memory taken up by the kernel image, including its stacks and table heap
+pub fn kernel_image() -> Range<usize> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let start = unsafe { ptr::from_ref(&virtual_base) } as usize;
+    let end = unsafe { ptr::from_ref(&kernel_end) } as usize;
+    start.checked_add_signed(pmo).unwrap()..end.checked_add_signed(pmo).unwrap()
+}
+
+/// Allocate a new T, with page-grain allocation
+///
+/// Fails with `OutOfMemory` if no physical memory is left
+pub fn zalloc<T>(obj: T) -> KernelResult<PageAllocation<T>> {
+    let (address, num_pages) = allocate(|records, heap_start, heap_size| {
+        records.try_allocate::<T>(heap_start, heap_size)
+    })?;
 
     zero_out(address, PAGE_SIZE * num_pages);
 
@@ -691,10 +1455,10 @@ pub fn zalloc<T>(obj: T) -> PageAllocation<T> {
         mem::forget(obj);
     }
 
-    PageAllocation {
+    Ok(PageAllocation {
         address: Some(address),
         num_pages,
-    }
+    })
 }
 
 /// Deallocate address
@@ -703,36 +1467,29 @@ pub fn zalloc<T>(obj: T) -> PageAllocation<T> {
 /// address must be valid and allocated
 pub unsafe fn free<T: ?Sized>(address: *mut T) {
     assert!(!address.is_null());
-    let records = unsafe { ptr::addr_of_mut!(table_heap_bottom) };
-    let top = unsafe { ptr::from_ref(&table_heap_top) };
-    let first_page_address = records as usize + PAGE_SIZE;
-    let heap_size = (top as usize - first_page_address) / PAGE_SIZE;
+    let zone = zone_of(address as *const () as usize);
 
     unsafe {
         ptr::drop_in_place(address);
     }
 
-    unsafe {
-        (*records).deallocate(first_page_address as *const c_void, heap_size, address);
-    }
+    let _zones = ZONES.lock();
+    zone.deallocate(address);
 }
 
 /// Allocate and zero a new `[T]`, with page-grain allocation
-pub fn zalloc_slice<T>(num_pages: usize) -> PageAllocation<[T]> {
-    let records = unsafe { ptr::addr_of_mut!(table_heap_bottom) };
-    let top = unsafe { ptr::from_ref(&table_heap_top) };
-    let first_page_address = records as usize + PAGE_SIZE;
-    let heap_size = (top as usize - first_page_address) / PAGE_SIZE;
-
-    let address = unsafe {
-        (*records).allocate_slice(first_page_address as *const c_void, heap_size, num_pages)
-    };
+///
+/// Fails with `OutOfMemory` if no physical memory is left
+pub fn zalloc_slice<T>(num_pages: usize) -> KernelResult<PageAllocation<[T]>> {
+    let address = allocate(|records, heap_start, heap_size| {
+        records.try_allocate_slice::<T>(heap_start, heap_size, num_pages)
+    })?;
     zero_out(address, num_pages * PAGE_SIZE);
 
-    PageAllocation {
+    Ok(PageAllocation {
         address: Some(address),
         num_pages,
-    }
+    })
 }
 
 // Zero-out bytes
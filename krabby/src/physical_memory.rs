@@ -0,0 +1,91 @@
+//! Finding the RAM the device tree describes, and handing whatever's free to the page allocator
+//!
+//! Until this runs, the page allocator only has the `.table_heap` section of the kernel image
+use crate::{globals, loader::USERSPACE_VADDR_START, mmu, mmu::PHYSICAL_MEMORY_OFFSET, prelude::*};
+use alloc::vec;
+use core::ops::Range;
+use fdt::{node::FdtNode, Fdt};
+
+/// Add all free RAM in the device tree to the page allocator
+pub fn init(fdt: &Fdt) -> KernelResult<()> {
+    let pmo = *(PHYSICAL_MEMORY_OFFSET.read());
+    let device_tree_memory = globals::get().device_tree_memory.clone();
+
+    let mut reserved = vec![mmu::kernel_image(), device_tree_memory.clone()];
+    // The device tree is mapped at its physical address, which RAM can't be mapped over
+    reserved.push(offset_range(&device_tree_memory, pmo));
+    reserved.extend(fdt.memory_reservations().map(|reservation| {
+        let start = reservation.address() as usize;
+        start..start + reservation.size()
+    }));
+    if let Some(node) = fdt.find_node("/reserved-memory") {
+        reserved.extend(node.children().flat_map(regions));
+    }
+    if let Some(initrd) = initrd(fdt) {
+        reserved.push(initrd);
+    }
+
+    // RAM has to land below user space once it's mapped into kernel space
+    let limit = USERSPACE_VADDR_START.saturating_add_signed(pmo);
+    let ram = fdt
+        .all_nodes()
+        .filter(|node| {
+            node.property("device_type")
+                .and_then(|property| property.as_str())
+                == Some("memory")
+        })
+        .flat_map(regions)
+        .map(|range| range.start..range.end.min(limit));
+
+    for range in ram {
+        for free in subtract(range, &reserved) {
+            println!(
+                "Adding {}KiB of RAM at {:#x}",
+                free.len() / 1024,
+                free.start
+            );
+            mmu::add_physical_memory(free)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Physical memory described by a node's `reg` property
+fn regions<'b, 'a>(node: FdtNode<'b, 'a>) -> impl Iterator<Item = Range<usize>> + 'b {
+    node.reg().into_iter().flatten().map(|region| {
+        let start = region.starting_address as usize;
+        start..start + region.size.unwrap_or(0)
+    })
+}
+
+// Memory the bootloader loaded an initial ramdisk into
+fn initrd(fdt: &Fdt) -> Option<Range<usize>> {
+    let chosen = fdt.find_node("/chosen")?;
+    let start = chosen.property("linux,initrd-start")?.as_usize()?;
+    let end = chosen.property("linux,initrd-end")?.as_usize()?;
+    Some(start..end)
+}
+
+// The physical addresses that would be mapped over a range of kernel space addresses
+fn offset_range(range: &Range<usize>, pmo: isize) -> Range<usize> {
+    range.start.saturating_add_signed(pmo)..range.end.saturating_add_signed(pmo)
+}
+
+// What's left of `range` with every range in `holes` cut out
+fn subtract(range: Range<usize>, holes: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut pieces = vec![range];
+    for hole in holes {
+        pieces = pieces
+            .into_iter()
+            .flat_map(|piece| {
+                [
+                    piece.start..piece.end.min(hole.start),
+                    piece.start.max(hole.end)..piece.end,
+                ]
+            })
+            .filter(|piece| !piece.is_empty())
+            .collect();
+    }
+    pieces
+}
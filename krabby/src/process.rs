@@ -1,131 +1,307 @@
 use crate::{
-    filesystem::FileRef,
+    filesystem::{self, FileRef},
     frame::{self, TrapFrame},
-    mmu::{self, Page, PageAllocation, PageType, SharedAllocation, Sv39PageTable, PAGE_SIZE},
+    loader,
+    mmu::{self, Access, PageAllocation, PageType, StaleMappings, Sv39PageTable, PAGE_SIZE},
     prelude::*,
+    shared_memory::Segment,
     timer::Instant,
     util::*,
 };
-use alloc::{
-    collections::{BTreeMap, VecDeque},
-    sync::Arc,
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::{
+    mem, ptr, slice,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+use krabby_abi::{
+    fs::FileDescriptor,
+    memory::{MapFlags, Protection},
+    process::{BlockReason, ProcessInfo, ProcessState},
+    sched::Nice,
+    signal::Signal,
+    ProcessError, ProcessResult,
 };
-use core::ptr;
-use krabby_abi::{fs::FileDescriptor, ProcessResult};
 use riscv::register::sstatus;
+use spin::Mutex;
 
-const STACK_PAGES_PER_PROCESS: usize = 2;
-const USERSPACE_VADDR_START: usize = 0xf000_0000;
+// The main thread's stack grows down from here. It's well away from the image and heap, so it has
+// room to grow
+const STACK_TOP: usize = 0x3f_0000_0000;
+// Stack pages mapped up front for the main thread. The rest are mapped on demand
+const INITIAL_STACK_PAGES: usize = 2;
+// Largest a stack can grow. The page below each stack is left unmapped as a guard
+const MAX_STACK_PAGES: usize = 256;
+// Address space taken up by each thread's stack, guard page included
+const STACK_SLOT_SIZE: usize = (MAX_STACK_PAGES + 1) * PAGE_SIZE;
+// Most threads a process can have at once. Each gets its own stack slot, counting down from the
+// main thread's
+const MAX_THREADS: usize = u64::BITS as usize;
+// Bottom of the lowest stack slot. The image and heap have to stay below this
+const STACKS_BOTTOM: usize = STACK_TOP - MAX_THREADS * STACK_SLOT_SIZE;
+// Stack slot of the main thread
+const MAIN_STACK: usize = 0;
+// Memory mapped wherever there's room goes as high as it can below here, leaving the space above
+// the heap for it to grow into
+const MAPPINGS_TOP: usize = STACKS_BOTTOM;
+// Most of the stack that may be taken up by arguments and environment variables. This leaves
+// the rest of the stack for the program itself
+const MAX_ARGUMENTS_SIZE: usize = PAGE_SIZE;
+// File descriptors below this are reserved for stdin, stdout, and stderr
+const FIRST_FILE_DESCRIPTOR: usize = 3;
+// Time slice of a thread with the default nice value. Less nice threads get up to twice this
+const DEFAULT_TIME_SLICE: Duration = Duration::from_millis(200);
+// Priority boost for threads that gave up the CPU before their time slice ran out, so
+// interactive threads get in ahead of busy ones of the same niceness
+const INTERACTIVE_BONUS: isize = 5;
+// Ready threads gain a point of priority for each of these they wait, so threads of higher
+// priority can't keep them from running forever
+const AGING_INTERVAL: Duration = Duration::from_millis(100);
 
-/// Process state
+/// Thread state
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum ProcessState {
-    /// Process is waiting to be run
+pub enum ThreadState {
+    /// Thread is waiting to be run
     Ready,
-    /// Process is running
+    /// Thread is running
     Running,
-    /// Process has been terminated but not yet reaped
+    /// Thread has been terminated but not yet reaped
     Zombie(ProcessResult),
-    /// Process is blocked on some condition
+    /// Thread is blocked on some condition
     Blocked(BlockCondition),
 }
 
-/// Condition on which a process is blocked
+/// Condition on which a thread is blocked
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum BlockCondition {
     /// Waiting on the death of some PID
     OnDeathOfPid(Pid),
+    /// Waiting on some PID to die or stop
+    OnChangeOfPid(Pid),
+    /// Waiting on the death of some thread
+    OnDeathOfThread(Pid),
     /// Waiting on uart character available
     OnUart(InterruptId),
     /// Waiting for the delay to reach 0
     Until(Instant),
+    /// Waiting to be woken through the futex word at a physical address, until an optional
+    /// deadline
+    OnFutex {
+        paddr: usize,
+        deadline: Option<Instant>,
+    },
+    /// Waiting for data in the pipe with some ID
+    OnPipeRead(usize),
+    /// Waiting for room in the pipe with some ID
+    OnPipeWrite(usize),
+    /// The thread's process, with some PID, is stopped until continued
+    Stopped(Pid),
+}
+
+impl BlockCondition {
+    /// What a process blocked on this is waiting for, as far as userspace is concerned
+    ///
+    /// Stopped processes aren't waiting for anything, so that's `None`
+    pub fn reason(&self) -> Option<BlockReason> {
+        let reason = match *self {
+            Self::OnDeathOfPid(pid) | Self::OnChangeOfPid(pid) => BlockReason::WaitProcess(pid),
+            Self::OnDeathOfThread(tid) => BlockReason::JoinThread(tid),
+            Self::OnUart(_) => BlockReason::Terminal,
+            Self::Until(_) => BlockReason::Sleep,
+            Self::OnFutex { .. } => BlockReason::Futex,
+            Self::OnPipeRead(_) => BlockReason::PipeRead,
+            Self::OnPipeWrite(_) => BlockReason::PipeWrite,
+            Self::Stopped(_) => return None,
+        };
+        Some(reason)
+    }
+}
+
+/// What one thread adds to its process's [ProcessInfo]
+#[derive(Copy, Clone, Debug)]
+pub struct ThreadInfo {
+    pub tid: Pid,
+    pub state: ThreadState,
+    pub nice: Nice,
+    pub cpu_time: Duration,
+}
+
+/// What a process does when it receives a signal
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignalAction {
+    /// Terminate or ignore, depending on the signal
+    Default,
+    /// Discard the signal
+    Ignore,
+    /// Run a handler in userspace
+    Handler {
+        /// Called with the signal, `handler`, and the address of the saved context, which it
+        /// passes to [Syscall::SigReturn](krabby_abi::Syscall::SigReturn) when done
+        entry: usize,
+        /// The handler itself
+        handler: usize,
+    },
+}
+
+// Memory mapped with [Syscall::Mmap](krabby_abi::Syscall::Mmap), whose pages are mapped when
+// first touched, or shared memory, whose pages are all mapped up front
+#[derive(Clone, Debug)]
+struct Region {
+    start: usize,
+    end: usize,
+    // How its pages are mapped, or `None` if they can't be touched at all
+    page_type: Option<PageType>,
+    // Kept alive for as long as any of it is mapped
+    segment: Option<Segment>,
+}
+
+impl Region {
+    fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+// How pages mapped with protection `prot` get mapped, if they can be touched at all
+//
+// Memory can't be both writable and executable
+fn page_type(prot: Protection) -> KernelResult<Option<PageType>> {
+    let (read, write, execute) = (
+        prot.contains(Protection::READ),
+        prot.contains(Protection::WRITE),
+        prot.contains(Protection::EXECUTE),
+    );
+    Ok(match (read, write, execute) {
+        (_, true, true) => return Err(KernelError::NotPermitted),
+        (true, false, true) => Some(PageType::UserReadExecute),
+        (false, false, true) => Some(PageType::UserExecute),
+        (_, true, false) => Some(PageType::UserReadWrite),
+        (true, false, false) => Some(PageType::UserReadOnly),
+        (false, false, false) => None,
+    })
+}
+
+// Context saved on the user stack while a signal handler runs
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+struct SignalFrame {
+    regs: [usize; 32],
+    pc: usize,
 }
 
 /// Represents a process
+///
+/// A process is an address space and the resources its threads share
 #[derive(Debug)]
 pub struct Process {
     pub pid: Pid,
-    pub state: ProcessState,
-    pub pc: usize,
-    pub frame: PageAllocation<TrapFrame>,
-    // TODO: Probably should be a ringbuffer
-    pub stdin_buffer: VecDeque<char>,
+    /// Process group, which decides who may read from the terminal
+    pub pgid: Pid,
+    /// Parent process, if it was forked
+    pub ppid: Option<Pid>,
+    /// Name of the program it's running, for process listings
+    pub name: String,
+    // Set once the process exits. Its threads follow as they're scheduled
+    exit_status: Option<ProcessResult>,
+    // Bit mask of signals waiting to be delivered
+    pending_signals: u32,
+    // Signal that stopped the process. Its threads block as they're scheduled, until continued
+    stopped: Option<Signal>,
+    signal_actions: [SignalAction; Signal::COUNT],
     pub file_descriptors: BTreeMap<FileDescriptor, FileRef>,
+    // Start of the heap, which is mapped on demand up to the breakline
+    heap_start: usize,
     // The current top of of virtual memory. Grows as heap grows
     breakline: usize,
-    code: Arc<SharedAllocation<[Page<PAGE_SIZE>]>>,
+    // Mapped memory, sorted by address. It's all between the breakline and the stacks
+    regions: Vec<Region>,
+    // Bit mask of stack slots in use by threads
+    stacks: u64,
+    // Threads that haven't been reaped yet
+    threads: usize,
+    // Owns every page mapped into user space
     root_page_table: PageAllocation<Sv39PageTable>,
-    stack: PageAllocation<[Page<PAGE_SIZE>; STACK_PAGES_PER_PROCESS]>,
-    /// Collection of heap allocation pages
-    heap: Vec<PageAllocation<[Page<PAGE_SIZE>]>>,
+}
+
+/// A flow of control within a process
+///
+/// Threads of a process share its address space, but each has its own registers and stack
+#[derive(Debug)]
+pub struct Thread {
+    /// Thread ID. The main thread's is the same as its PID
+    pub tid: Pid,
+    /// PID of the process the thread belongs to
+    pub pid: Pid,
+    pub state: ThreadState,
+    pub pc: usize,
+    pub frame: PageAllocation<TrapFrame>,
+    /// The process the thread belongs to
+    pub process: Arc<Mutex<Process>>,
+    /// Niceness, which decides priority and time slice length
+    pub nice: Nice,
+    // Stack slot the thread runs on
+    stack: usize,
+    // When the thread was last switched in
+    slice_start: Instant,
+    // Whether the thread blocked before using up its last time slice
+    interactive: bool,
+    // When the thread last became ready to run
+    ready_since: Instant,
+    // Total time spent running
+    cpu_time: Duration,
 }
 
 impl Process {
-    /// Construct a new [Process]
+    /// Construct a new [Process] from an ELF file
     ///
-    /// # Safety
-    /// `code_src` and `code_size` must be valid
-    pub unsafe fn new(
-        code_src: *const u8,
-        code_size: usize,
-        entry_offset: usize,
-    ) -> KernelResult<Self> {
-        // Map code
-        let mut code = mmu::zalloc_slice(align_up::<PAGE_SIZE>(code_size) / PAGE_SIZE);
-        unsafe {
-            ptr::copy(code_src, code.as_mut_ptr() as *mut u8, code_size);
-        }
-        let code = code.into_shared();
-        let pc = USERSPACE_VADDR_START + entry_offset;
-        Self::with_code_and_pc(code, pc)
-    }
-
-    fn with_code_and_pc(
-        code: Arc<SharedAllocation<[Page<PAGE_SIZE>]>>,
-        pc: usize,
-    ) -> KernelResult<Self> {
+    /// `argv` is a list of NUL-terminated arguments. Returns the main thread
+    pub fn new(elf: &[u8], argv: &[u8]) -> KernelResult<Thread> {
         let pid = Pid::generate();
+        let (mut process, entry) = Self::with_image(pid, elf)?;
+        let sp = process.write_arguments(argv, &[])?;
+        process.name = command_name(argv);
+        let console = filesystem::console::open();
+        process
+            .file_descriptors
+            .insert(FileDescriptor::STDOUT, console.clone());
+        process
+            .file_descriptors
+            .insert(FileDescriptor::STDERR, console);
 
-        let mut breakline = USERSPACE_VADDR_START.try_into()?;
-        let mut root_page_table = mmu::zalloc(Sv39PageTable::new());
+        let mut thread = Thread::new(pid, Arc::new(Mutex::new(process)), entry, Some(MAIN_STACK))?;
+        thread.frame.as_mut().set_stack_pointer(sp);
+        Ok(thread)
+    }
 
-        let code_paddr = mmu::ks_vaddr_to_paddr(code.addr())?;
-        breakline = mmu::map_range(
-            root_page_table.as_mut(),
-            breakline,
-            code_paddr,
-            PageType::UserExecute,
-            code.num_pages() * PAGE_SIZE,
-        )?;
+    // Load an ELF file into a fresh address space
+    //
+    // Returns the process, with no threads yet, and its entry point
+    fn with_image(pid: Pid, elf: &[u8]) -> KernelResult<(Self, usize)> {
+        let mut root_page_table = Self::new_page_table()?;
+        let image = loader::load(root_page_table.as_mut(), elf)?;
 
-        // Skip a page for the stack guard
-        breakline = breakline.offset(PAGE_SIZE as isize)?;
+        if image.end >= STACKS_BOTTOM {
+            return Err(KernelError::InvalidElf("Image overlaps the stack"));
+        }
 
-        // Map stack
-        let stack = mmu::zalloc(Default::default());
-        let stack_paddr = mmu::ks_vaddr_to_paddr(stack.as_const_ptr() as usize)?;
-        breakline = mmu::map_range(
-            root_page_table.as_mut(),
-            breakline,
-            stack_paddr,
-            PageType::UserReadWrite,
-            STACK_PAGES_PER_PROCESS * PAGE_SIZE,
-        )?;
-        let stack_top = breakline;
+        // Map the top of the main thread's stack, which is where the arguments go
+        for page in 1..=INITIAL_STACK_PAGES {
+            mmu::map_new_user_page(
+                root_page_table.as_mut(),
+                (STACK_TOP - page * PAGE_SIZE).try_into()?,
+                PageType::UserReadWrite,
+            )?;
+        }
 
         // Skip a page for the heap guard
-        breakline = breakline.offset(PAGE_SIZE as isize)?;
+        let heap_start = image.end + PAGE_SIZE;
 
-        // This doesn't need to be mapped - it's only accessed by the kernel
-        let mut frame: PageAllocation<TrapFrame> = mmu::zalloc(TrapFrame {
-            regs: Default::default(),
-            pid: Some(pid),
-            root_page_table: root_page_table.as_mut_ptr(),
-            satp: mmu::ks_vaddr_to_paddr(root_page_table.as_const_ptr() as usize)?.into(),
-            kernel_frame: ptr::null(),
-        });
-        // Stack grows down, so set to top
-        frame.as_mut().set_stack_pointer(stack_top.into());
+        let process = Self::with_page_table(pid, root_page_table, heap_start, heap_start);
+        Ok((process, image.entry))
+    }
+
+    // Page table with only kernel space mapped
+    fn new_page_table() -> KernelResult<PageAllocation<Sv39PageTable>> {
+        let mut root_page_table = mmu::zalloc(Sv39PageTable::new())?;
 
         // Map kernel space so we can context switch
         mmu::map_kernel_space(root_page_table.as_mut())?;
@@ -139,35 +315,624 @@ impl Process {
             PAGE_SIZE,
         )?;
 
-        Ok(Self {
+        Ok(root_page_table)
+    }
+
+    fn with_page_table(
+        pid: Pid,
+        root_page_table: PageAllocation<Sv39PageTable>,
+        heap_start: usize,
+        breakline: usize,
+    ) -> Self {
+        Self {
             pid,
-            state: ProcessState::Ready,
-            breakline: usize::from(breakline),
-            stdin_buffer: Default::default(),
+            heap_start,
+            breakline,
+            regions: Vec::new(),
+            pgid: pid,
+            ppid: None,
+            name: String::new(),
+            exit_status: None,
+            pending_signals: 0,
+            stopped: None,
+            signal_actions: [SignalAction::Default; Signal::COUNT],
             file_descriptors: Default::default(),
-            pc,
-            code,
+            stacks: 0,
+            threads: 0,
             root_page_table,
+        }
+    }
+
+    // Take a stack slot for a new thread, either `slot` or the first free one
+    fn claim_stack(&mut self, slot: Option<usize>) -> KernelResult<usize> {
+        let slot = match slot {
+            Some(slot) => slot,
+            // Out of stack slots means out of address space for stacks
+            None => (0..MAX_THREADS)
+                .find(|slot| self.stacks & (1 << slot) == 0)
+                .ok_or(KernelError::OutOfMemory)?,
+        };
+        assert!(self.stacks & (1 << slot) == 0, "Stack slot {slot} taken");
+        self.stacks |= 1 << slot;
+        self.threads += 1;
+        Ok(slot)
+    }
+
+    /// Forget a reaped thread, freeing up its stack slot
+    ///
+    /// Returns true if it was the last thread, so the process is gone too
+    pub fn remove_thread(&mut self, thread: &Thread) -> bool {
+        self.stacks &= !(1 << thread.stack);
+        self.threads -= 1;
+        self.threads == 0
+    }
+
+    /// How the process ended, if it has
+    pub fn exit_status(&self) -> Option<ProcessResult> {
+        self.exit_status
+    }
+
+    /// Make a range of user memory accessible to the kernel on the process's behalf
+    ///
+    /// This maps in pages that haven't been touched yet, and if `write` is set, gives the process
+    /// its own copy of any copy-on-write pages. Invalid addresses are left for the caller to
+    /// trip over
+    pub fn fault_in(
+        &mut self,
+        start: usize,
+        len: usize,
+        write: bool,
+        stale: &mut StaleMappings,
+    ) -> KernelResult<()> {
+        let access = if write { Access::Write } else { Access::Read };
+        let end = start
+            .checked_add(len)
+            .ok_or(KernelError::InvalidArguments)?;
+        for page in (align_down::<PAGE_SIZE>(start)..end).step_by(PAGE_SIZE) {
+            let table = self.root_page_table.as_mut();
+            if !mmu::user_may_access(table, page.try_into()?, access) {
+                self.handle_page_fault(page, access, stale)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a page fault at `addr`
+    ///
+    /// Returns false if the access was actually invalid, meaning it was outside the image,
+    /// stacks, and heap, or it wasn't allowed by the page's protection. Pages that other harts
+    /// may still have cached the old way are only let go of once `stale` is dropped
+    pub fn handle_page_fault(
+        &mut self,
+        addr: usize,
+        access: Access,
+        stale: &mut StaleMappings,
+    ) -> KernelResult<bool> {
+        let page = align_down::<PAGE_SIZE>(addr);
+        let write = access == Access::Write;
+        let demand_paged = self
+            .demand_paged_type(page)
+            .filter(|page_type| !write || page_type.writable());
+        if self.threads > 1 {
+            stale.include_other_harts();
+        }
+        let table = self.root_page_table.as_mut();
+
+        match mmu::vaddr_to_paddr(table, page) {
+            // Another thread beat us to it, or this hart cached the page before it was made
+            // accessible. Either way, only the stale translation is in the way
+            Ok(_) if mmu::user_may_access(table, page.try_into()?, access) => {
+                riscv::asm::sfence_vma_all();
+                Ok(true)
+            }
+            Ok(_) if write => mmu::copy_on_write(table, page.try_into()?, stale),
+            // Already mapped, so this was a permissions problem
+            Ok(_) => Ok(false),
+            Err(KernelError::NotMapped(_)) => match demand_paged {
+                Some(page_type) => {
+                    mmu::map_new_user_page(table, page.try_into()?, page_type)?;
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    // How `page` gets mapped on first access, if it's in a part of the address space that's
+    // mapped that way
+    fn demand_paged_type(&self, page: usize) -> Option<PageType> {
+        if (self.heap_start..self.breakline).contains(&page) {
+            return Some(PageType::UserReadWrite);
+        }
+        if let Some(region) = self.regions.iter().find(|r| r.overlaps(page, page + 1)) {
+            return region.page_type.filter(|_| region.segment.is_none());
+        }
+        if !(STACKS_BOTTOM..STACK_TOP).contains(&page) {
+            return None;
+        }
+        // Only stacks of live threads, and never their guard pages
+        let slot = (STACK_TOP - 1 - page) / STACK_SLOT_SIZE;
+        let live = self.stacks & (1 << slot) != 0;
+        (live && page >= stack_top(slot) - MAX_STACK_PAGES * PAGE_SIZE)
+            .then_some(PageType::UserReadWrite)
+    }
+
+    // Lay out `argc`, then the NULL-terminated `argv` and `envp` pointer arrays, at the top of the
+    // main thread's stack. The strings themselves go above that
+    //
+    // Returns the stack pointer the main thread starts with
+    fn write_arguments(&mut self, argv: &[u8], envp: &[u8]) -> KernelResult<usize> {
+        const WORD: usize = mem::size_of::<usize>();
+        let argc = count_strings(argv)?;
+        let envc = count_strings(envp)?;
+
+        let strings_size = align_up::<WORD>(argv.len() + envp.len());
+        let table_size = (argc + envc + 3) * WORD;
+        // Make sure there's still some stack left for the program itself
+        let size = align_up::<16>(strings_size + table_size);
+        if size > MAX_ARGUMENTS_SIZE {
+            return Err(KernelError::ArgumentListTooLong);
+        }
+
+        // Everything goes in the top page of the stack
+        let stack_bottom = STACK_TOP - PAGE_SIZE;
+        let page = mmu::get_user_page(self.root_page_table.as_ref(), stack_bottom.try_into()?)?;
+        let stack = unsafe { slice::from_raw_parts_mut(usize::from(page) as *mut u8, PAGE_SIZE) };
+
+        let strings_offset = stack.len() - strings_size;
+        stack[strings_offset..][..argv.len()].copy_from_slice(argv);
+        stack[strings_offset + argv.len()..][..envp.len()].copy_from_slice(envp);
+
+        let sp_offset = stack.len() - size;
+        let mut words = stack[sp_offset..strings_offset].chunks_exact_mut(WORD);
+        let mut push = |word: usize| {
+            words
+                .next()
+                .expect("Argument table miscounted")
+                .copy_from_slice(&word.to_ne_bytes());
+        };
+
+        push(argc);
+        let mut offset = stack_bottom + strings_offset;
+        for strings in [argv, envp] {
+            for string in strings.split_inclusive(|byte| *byte == 0) {
+                push(offset);
+                offset += string.len();
+            }
+            push(0);
+        }
+
+        Ok(stack_bottom + sp_offset)
+    }
+
+    /// Terminate the process
+    ///
+    /// Each of its threads exits the next time it's scheduled
+    pub fn exit(&mut self, res: ProcessResult) -> KernelResult<()> {
+        self.exit_status.get_or_insert(res);
+        Ok(())
+    }
+
+    /// Queue a signal for delivery the next time one of the process's threads is scheduled
+    ///
+    /// The scheduler interrupts whatever the main thread is blocked on, unless the signal would
+    /// be ignored anyway. Stopping and continuing take effect right away instead. Returns true
+    /// if the process stopped or continued
+    pub fn signal(&mut self, signal: Signal) -> bool {
+        let was_stopped = self.stopped.is_some();
+        // Nothing else gets through to a stopped process
+        if matches!(signal, Signal::Kill | Signal::Continue) {
+            self.stopped = None;
+        }
+
+        if signal.stops_by_default()
+            && self.signal_actions[usize::from(signal)] == SignalAction::Default
+        {
+            self.stopped.get_or_insert(signal);
+        } else if !self.ignores(signal) {
+            self.pending_signals |= 1 << usize::from(signal);
+        }
+        self.stopped.is_some() != was_stopped
+    }
+
+    /// Get the signal that stopped the process, if it's stopped
+    pub fn stopped(&self) -> Option<Signal> {
+        self.stopped
+    }
+
+    fn ignores(&self, signal: Signal) -> bool {
+        match self.signal_actions[usize::from(signal)] {
+            SignalAction::Default => !signal.terminates_by_default(),
+            SignalAction::Ignore => true,
+            SignalAction::Handler { .. } => false,
+        }
+    }
+
+    /// Choose what happens when `signal` arrives
+    ///
+    /// Returns the previous action
+    pub fn set_signal_action(
+        &mut self,
+        signal: Signal,
+        action: SignalAction,
+    ) -> KernelResult<SignalAction> {
+        if !signal.catchable() && action != SignalAction::Default {
+            return Err(KernelError::NotPermitted);
+        }
+        Ok(mem::replace(
+            &mut self.signal_actions[usize::from(signal)],
+            action,
+        ))
+    }
+
+    /// Add an open file to the file descriptor table
+    ///
+    /// Returns the lowest unused file descriptor
+    pub fn add_file(&mut self, file: FileRef) -> KernelResult<FileDescriptor> {
+        for fd in FIRST_FILE_DESCRIPTOR..=usize::from(u16::MAX) {
+            let fd = FileDescriptor::try_from(fd)?;
+            if !self.file_descriptors.contains_key(&fd) {
+                self.file_descriptors.insert(fd, file);
+                return Ok(fd);
+            }
+        }
+        Err(KernelError::TooManyOpenFiles)
+    }
+
+    /// Get an open file by its file descriptor
+    pub fn file(&self, fd: FileDescriptor) -> KernelResult<FileRef> {
+        self.file_descriptors
+            .get(&fd)
+            .cloned()
+            .ok_or(KernelError::BadFileDescriptor(fd))
+    }
+
+    /// Remove a file from the file descriptor table
+    ///
+    /// Returns the file, so the caller decides where it gets dropped. Closing the last reference
+    /// to a pipe end wakes threads, which can't happen with the process locked
+    pub fn close_file(&mut self, fd: FileDescriptor) -> KernelResult<FileRef> {
+        self.file_descriptors
+            .remove(&fd)
+            .ok_or(KernelError::BadFileDescriptor(fd))
+    }
+
+    /// Make `new` refer to the same open file as `old`
+    ///
+    /// Returns whatever was open at `new` before, which the caller should drop with the process
+    /// unlocked
+    pub fn duplicate_file(
+        &mut self,
+        old: FileDescriptor,
+        new: FileDescriptor,
+    ) -> KernelResult<Option<FileRef>> {
+        let file = self.file(old)?;
+        Ok(self.file_descriptors.insert(new, file))
+    }
+
+    /// Find the futex word at `addr`
+    ///
+    /// Returns its physical address, which is the same through every mapping of it, and its
+    /// current value. Copying the page for us leaves the old one with `stale`
+    pub fn futex_word(
+        &mut self,
+        addr: usize,
+        stale: &mut StaleMappings,
+    ) -> KernelResult<(usize, u32)> {
+        if addr % mem::align_of::<AtomicU32>() != 0 {
+            return Err(KernelError::InvalidArguments);
+        }
+        // Give the process its own copy of the page first, so the word stays put
+        self.fault_in(addr, mem::size_of::<AtomicU32>(), true, stale)?;
+
+        let word = usize::from(mmu::get_user_page(
+            self.root_page_table.as_ref(),
+            addr.try_into()?,
+        )?);
+        let paddr = mmu::ks_vaddr_to_paddr(word)?;
+        let value = unsafe { (*(word as *const AtomicU32)).load(Ordering::SeqCst) };
+        Ok((paddr.into(), value))
+    }
+
+    /// Get the heap breakline
+    pub fn breakline(&self) -> usize {
+        self.breakline
+    }
+
+    /// Grow the heap
+    ///
+    /// Returns the new breakline
+    pub fn request_memory(&mut self, bytes: usize) -> KernelResult<usize> {
+        if bytes == 0 {
+            return Ok(self.breakline);
+        }
+
+        // Pages are mapped when first touched
+        let breakline = self
+            .breakline
+            .checked_add(align_up::<PAGE_SIZE>(bytes))
+            .ok_or(KernelError::OutOfMemory)?;
+        // Leave the guard page below the stacks and mapped memory alone
+        if breakline >= STACKS_BOTTOM || self.regions.iter().any(|r| r.start < breakline) {
+            return Err(KernelError::OutOfMemory);
+        }
+        self.breakline = breakline;
+
+        Ok(self.breakline)
+    }
+
+    /// Map `len` bytes of zeroed memory with protection `prot`
+    ///
+    /// With [MapFlags::FIXED], it goes at exactly `addr`. Otherwise `addr` is a hint, and without
+    /// room there, it goes as high up as there's room. Pages are mapped on first access. Returns
+    /// the address of the mapping
+    pub fn map_memory(
+        &mut self,
+        addr: usize,
+        len: usize,
+        prot: Protection,
+        flags: MapFlags,
+    ) -> KernelResult<usize> {
+        let page_type = page_type(prot)?;
+        let start = self.place(addr, len, flags)?;
+        self.insert_region(Region {
+            start,
+            end: start + align_up::<PAGE_SIZE>(len),
+            page_type,
+            segment: None,
+        });
+        Ok(start)
+    }
+
+    /// Map all of a shared memory segment with protection `prot`, placed like
+    /// [Process::map_memory] places memory
+    ///
+    /// Returns the address of the mapping. If mapping fails partway, whatever was mapped is
+    /// taken back out through `stale`
+    pub fn map_shared_memory(
+        &mut self,
+        segment: Segment,
+        addr: usize,
+        prot: Protection,
+        flags: MapFlags,
+        stale: &mut StaleMappings,
+    ) -> KernelResult<usize> {
+        let page_type = page_type(prot)?;
+        let start = self.place(addr, segment.len(), flags)?;
+        if let Some(page_type) = page_type {
+            if self.threads > 1 {
+                stale.include_other_harts();
+            }
+            let table = self.root_page_table.as_mut();
+            mmu::map_shared_pages(table, start.try_into()?, &segment, page_type, stale)?;
+        }
+        self.insert_region(Region {
+            start,
+            end: start + segment.len(),
+            page_type,
+            segment: Some(segment),
+        });
+        Ok(start)
+    }
+
+    // Where `len` bytes of new mapping go
+    fn place(&self, addr: usize, len: usize, flags: MapFlags) -> KernelResult<usize> {
+        if len == 0 || !aligned::<PAGE_SIZE>(addr) {
+            return Err(KernelError::InvalidArguments);
+        }
+        let len = len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(KernelError::OutOfMemory)?;
+
+        if self.is_free(addr, len) {
+            Ok(addr)
+        } else if flags.contains(MapFlags::FIXED) {
+            Err(KernelError::AlreadyExists)
+        } else {
+            self.find_free(len).ok_or(KernelError::OutOfMemory)
+        }
+    }
+
+    fn insert_region(&mut self, region: Region) {
+        let i = self.regions.partition_point(|r| r.start < region.start);
+        self.regions.insert(i, region);
+    }
+
+    /// Unmap whatever memory was mapped with [Process::map_memory] or
+    /// [Process::map_shared_memory] in the `len` bytes from `addr`, freeing it
+    ///
+    /// Shared memory is only freed once nothing maps it anymore. Either way, nothing is freed
+    /// until `stale` is dropped, after every hart that may have it cached has flushed it
+    pub fn unmap_memory(
+        &mut self,
+        addr: usize,
+        len: usize,
+        stale: &mut StaleMappings,
+    ) -> KernelResult<()> {
+        if len == 0 || !aligned::<PAGE_SIZE>(addr) {
+            return Err(KernelError::InvalidArguments);
+        }
+        let end = len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .and_then(|len| addr.checked_add(len))
+            .ok_or(KernelError::InvalidArguments)?;
+
+        // Cut the range out of each region it overlaps, keeping whatever's left on either side
+        let mut cut = Vec::new();
+        let mut regions = Vec::with_capacity(self.regions.len());
+        for region in &self.regions {
+            if !region.overlaps(addr, end) {
+                regions.push(region.clone());
+                continue;
+            }
+            cut.push((region.start.max(addr), region.end.min(end)));
+            if let Some(segment) = &region.segment {
+                stale.keep_segment(segment.clone());
+            }
+            if region.start < addr {
+                regions.push(Region {
+                    end: addr,
+                    ..region.clone()
+                });
+            }
+            if end < region.end {
+                regions.push(Region {
+                    start: end,
+                    ..region.clone()
+                });
+            }
+        }
+
+        if self.threads > 1 {
+            stale.include_other_harts();
+        }
+        let table = self.root_page_table.as_mut();
+        for (start, end) in cut {
+            for page in (start..end).step_by(PAGE_SIZE) {
+                mmu::unmap_user_page(table, page.try_into()?, stale)?;
+            }
+        }
+        self.regions = regions;
+        Ok(())
+    }
+
+    // Is `len` bytes from `start` between the breakline and the stacks, and not mapped yet?
+    fn is_free(&self, start: usize, len: usize) -> bool {
+        let Some(end) = start.checked_add(len) else {
+            return false;
+        };
+        start >= self.breakline
+            && end <= MAPPINGS_TOP
+            && !self.regions.iter().any(|r| r.overlaps(start, end))
+    }
+
+    // Highest free `len` bytes, so mappings grow down towards the heap
+    fn find_free(&self, len: usize) -> Option<usize> {
+        let mut top = MAPPINGS_TOP;
+        for region in self.regions.iter().rev() {
+            if top - region.end >= len {
+                return Some(top - len);
+            }
+            top = region.start;
+        }
+        (top.checked_sub(self.breakline)? >= len).then(|| top - len)
+    }
+
+    /// Describe the process, given what each of its threads is doing
+    ///
+    /// The process is as active as its most active thread. If they're all blocked, it's blocked
+    /// on whatever the main thread is, or the first thread that's blocked if the main thread
+    /// exited
+    pub fn info(&mut self, threads: &[ThreadInfo]) -> KernelResult<ProcessInfo<'_>> {
+        let any = |f: fn(&ThreadState) -> bool| threads.iter().any(|t| f(&t.state));
+        let state = if self.exit_status.is_some() {
+            ProcessState::Zombie
+        } else if self.stopped.is_some() {
+            ProcessState::Stopped
+        } else if any(|state| *state == ThreadState::Running) {
+            ProcessState::Running
+        } else if any(|state| *state == ThreadState::Ready) {
+            ProcessState::Ready
+        } else if any(|state| matches!(state, ThreadState::Blocked(_))) {
+            ProcessState::Blocked
+        } else {
+            ProcessState::Zombie
+        };
+
+        let main = threads.iter().find(|t| t.tid == self.pid);
+        let blocked_on = match state {
+            ProcessState::Blocked => main.into_iter().chain(threads).find_map(|t| match t.state {
+                ThreadState::Blocked(condition) => condition.reason(),
+                _ => None,
+            }),
+            _ => None,
+        };
+
+        Ok(ProcessInfo {
+            pid: self.pid,
+            ppid: self.ppid,
+            pgid: self.pgid,
+            state,
+            blocked_on,
+            nice: main.or(threads.first()).map_or(Nice::DEFAULT, |t| t.nice),
+            threads: threads.len().try_into()?,
+            cpu_time: threads.iter().map(|t| t.cpu_time).sum(),
+            resident_pages: mmu::count_user_pages(self.root_page_table.as_mut())?.try_into()?,
+            name: &self.name,
+        })
+    }
+}
+
+impl Thread {
+    // Thread of `process` that starts at `pc`, on stack slot `stack` or the first free one
+    fn new(
+        tid: Pid,
+        process: Arc<Mutex<Process>>,
+        pc: usize,
+        stack: Option<usize>,
+    ) -> KernelResult<Self> {
+        let (pid, root_page_table, stack) = {
+            let mut process = process.lock();
+            let root_page_table = process.root_page_table.as_mut_ptr();
+            let stack = process.claim_stack(stack)?;
+            (process.pid, root_page_table, stack)
+        };
+
+        // This doesn't need to be mapped - it's only accessed by the kernel
+        let mut frame: PageAllocation<TrapFrame> = mmu::zalloc(TrapFrame {
+            regs: Default::default(),
+            pid: Some(pid),
+            tid: Some(tid),
+            root_page_table,
+            satp: mmu::ks_vaddr_to_paddr(root_page_table as usize)?.into(),
+            kernel_frame: ptr::null(),
+        })?;
+        // Stack grows down, so set to top
+        frame.as_mut().set_stack_pointer(stack_top(stack));
+
+        Ok(Self {
+            tid,
+            pid,
+            state: ThreadState::Ready,
+            pc,
             frame,
+            process,
+            nice: Nice::DEFAULT,
             stack,
-            heap: Vec::new(),
+            slice_start: Instant::now(),
+            interactive: false,
+            ready_since: Instant::now(),
+            cpu_time: Duration::ZERO,
         })
     }
 
-    /// Switch process to ready
+    /// Switch thread to ready
     pub fn pause(&mut self) {
-        self.state = ProcessState::Ready;
+        if self.state == ThreadState::Running {
+            let ran_for = Instant::now().duration_since(self.slice_start);
+            self.cpu_time += ran_for;
+            self.interactive = ran_for < self.time_slice();
+        }
+        self.state = ThreadState::Ready;
+        self.ready_since = Instant::now();
     }
 
-    /// Switch process to running
+    /// Switch thread to running
+    ///
+    /// This starts a new time slice, unless the thread was already running
     pub fn switch(&mut self) {
+        if self.state != ThreadState::Running {
+            self.slice_start = Instant::now();
+        }
+
         self.frame.as_mut().kernel_frame = {
             let frame = frame::get_current_trap_frame();
             assert!(!frame.is_null());
             unsafe { (*frame).kernel_frame }
         };
 
-        // Set page tables
+        // Set page tables. Threads of a process share its address space ID
         let satp = self.frame.as_ref().satp.try_into().unwrap();
         let pid = u16::from(self.pid);
         mmu::set_root_page_table(pid, satp);
@@ -178,80 +943,367 @@ impl Process {
             sstatus::set_spp(sstatus::SPP::User);
         }
 
-        self.state = ProcessState::Running;
+        self.state = ThreadState::Running;
     }
 
-    /// Fork process
-    pub fn fork(&self) -> KernelResult<Self> {
-        let mut child = Process::with_code_and_pc(self.code.clone(), 0xDEADBEEF)?;
+    /// Scheduling priority. Higher runs first
+    pub fn priority(&self) -> isize {
+        let bonus = if self.interactive {
+            INTERACTIVE_BONUS
+        } else {
+            0
+        };
+        bonus - isize::from(self.nice)
+    }
 
-        // Copy over registers
-        child.pc = self.pc;
-        for (i, reg) in self.frame.as_ref().regs.iter().enumerate() {
-            child.frame.as_mut().regs[i] = *reg;
+    /// Priority gained from waiting to run, which is enough to catch up with any other thread
+    /// given long enough
+    pub fn age(&self) -> isize {
+        if self.state != ThreadState::Ready {
+            return 0;
         }
+        let most = isize::from(Nice::MAX) - isize::from(Nice::MIN) + INTERACTIVE_BONUS;
+        let waited = Instant::now().duration_since(self.ready_since);
+        let age = waited.as_millis() / AGING_INTERVAL.as_millis();
+        isize::try_from(age).map_or(most, |age| age.min(most))
+    }
+
+    /// How long the thread may run before it's preempted by another of the same priority
+    pub fn time_slice(&self) -> Duration {
+        let scale = isize::from(Nice::MAX) + 1 - isize::from(self.nice);
+        DEFAULT_TIME_SLICE * scale as u32 / (isize::from(Nice::MAX) + 1) as u32
+    }
+
+    /// Has the thread used up its time slice?
+    pub fn slice_expired(&self) -> bool {
+        Instant::now().duration_since(self.slice_start) >= self.time_slice()
+    }
+
+    /// Total time spent running, not counting the current time slice
+    pub fn cpu_time(&self) -> Duration {
+        self.cpu_time
+    }
+
+    /// What the thread adds to its process's [ProcessInfo]
+    pub fn info(&self) -> ThreadInfo {
+        ThreadInfo {
+            tid: self.tid,
+            state: self.state,
+            nice: self.nice,
+            cpu_time: self.cpu_time,
+        }
+    }
+
+    /// Process group of the thread's process
+    pub fn pgid(&self) -> Pid {
+        self.process.lock().pgid
+    }
+
+    /// Parent of the thread's process, if it has one
+    pub fn ppid(&self) -> Option<Pid> {
+        self.process.lock().ppid
+    }
 
-        // Copy stack
-        for (pindex, page) in self.stack.as_ref().iter().enumerate() {
-            for (bindex, byte) in page.0.iter().enumerate() {
-                child.stack.as_mut()[pindex].0[bindex] = *byte;
+    /// Fork the thread's process
+    ///
+    /// The child has only a copy of this thread, shares all of our pages copy-on-write, and
+    /// inherits our open files. Returns the child's main thread. Our pages stay writable to
+    /// harts that cached them until `stale` flushes them
+    pub fn fork(&mut self, stale: &mut StaleMappings) -> KernelResult<Self> {
+        let child = {
+            let mut process = self.process.lock();
+            if process.threads > 1 {
+                stale.include_other_harts();
             }
+            let mut root_page_table = Process::new_page_table()?;
+            mmu::fork_user_pages(
+                process.root_page_table.as_mut(),
+                root_page_table.as_mut(),
+                stale,
+            )?;
+
+            let mut child = Process::with_page_table(
+                Pid::generate(),
+                root_page_table,
+                process.heap_start,
+                process.breakline,
+            );
+            child.pgid = process.pgid;
+            child.ppid = Some(process.pid);
+            child.name = process.name.clone();
+            child.regions = process.regions.clone();
+            child.signal_actions = process.signal_actions;
+            child.file_descriptors = process.file_descriptors.clone();
+            child
+        };
+
+        let pid = child.pid;
+        let mut thread = Self::new(pid, Arc::new(Mutex::new(child)), self.pc, Some(self.stack))?;
+
+        // Copy over registers
+        thread.frame.as_mut().regs = self.frame.as_ref().regs;
+        thread.nice = self.nice;
+
+        Ok(thread)
+    }
+
+    /// Start another thread in this one's process
+    ///
+    /// It starts at `entry` on a fresh stack, with `arg0` and `arg1` as arguments. The entry
+    /// point must not return
+    pub fn spawn(&mut self, entry: usize, arg0: usize, arg1: usize) -> KernelResult<Self> {
+        let mut thread = Self::new(Pid::generate(), self.process.clone(), entry, None)?;
+
+        let frame = thread.frame.as_mut();
+        frame.set_reg(Register::Arg0, arg0);
+        frame.set_reg(Register::Arg1, arg1);
+        frame.set_reg(
+            Register::GlobalPointer,
+            self.frame.as_ref().get_reg(Register::GlobalPointer),
+        );
+        thread.nice = self.nice;
+
+        Ok(thread)
+    }
+
+    /// Replace the process image with an ELF file
+    ///
+    /// The PID, process group, file descriptors, and ignored signals are kept. `argv` and `envp`
+    /// are lists of NUL-terminated strings. Any other threads would be left without an address
+    /// space, so this fails if there are some
+    pub fn exec(&mut self, elf: &[u8], argv: &[u8], envp: &[u8]) -> KernelResult<()> {
+        let mut process = self.process.lock();
+        if process.threads > 1 {
+            return Err(KernelError::NotPermitted);
         }
 
-        Ok(child)
+        let (mut image, entry) = Process::with_image(self.pid, elf)?;
+        let sp = image.write_arguments(argv, envp)?;
+
+        image.pgid = process.pgid;
+        image.ppid = process.ppid;
+        image.name = command_name(argv);
+        image.exit_status = process.exit_status;
+        image.pending_signals = process.pending_signals;
+        // Handlers went away with the old image, but ignored signals stay ignored
+        image.signal_actions = process.signal_actions.map(|action| match action {
+            SignalAction::Handler { .. } => SignalAction::Default,
+            action => action,
+        });
+        image.file_descriptors = mem::take(&mut process.file_descriptors);
+        image.stacks = 1 << MAIN_STACK;
+        image.threads = 1;
+
+        // The syscall handler still holds a reference to our trap frame, so keep using it
+        let frame = self.frame.as_mut();
+        frame.regs = Default::default();
+        frame.root_page_table = image.root_page_table.as_mut_ptr();
+        frame.satp = mmu::ks_vaddr_to_paddr(frame.root_page_table as usize)?.into();
+        frame.set_stack_pointer(sp);
+        self.pc = entry;
+        self.stack = MAIN_STACK;
+
+        // Dropping the old page table unmaps and frees the old code, heap, and stacks
+        *process = image;
+        Ok(())
     }
 
-    /// Terminate the process
+    /// Terminate just this thread
     pub fn exit(&mut self, res: ProcessResult) -> KernelResult<()> {
-        self.state = ProcessState::Zombie(res);
+        self.state = ThreadState::Zombie(res);
         Ok(())
     }
 
-    /// Return true if blocked
-    pub fn is_blocked(&mut self) -> bool {
-        matches!(self.state, ProcessState::Blocked(_))
+    /// Terminate the whole process, starting with this thread
+    pub fn exit_process(&mut self, res: ProcessResult) -> KernelResult<()> {
+        self.process.lock().exit(res)?;
+        self.follow_process_exit();
+        Ok(())
     }
 
-    /// Block process on some condition
-    pub fn block(&mut self, condition: BlockCondition) {
-        self.pause();
-        self.state = ProcessState::Blocked(condition);
+    /// Become a zombie if the process has exited
+    ///
+    /// Returns true if the thread is a zombie
+    pub fn follow_process_exit(&mut self) -> bool {
+        if let ThreadState::Zombie(_) = self.state {
+            return true;
+        }
+        if let Some(res) = self.process.lock().exit_status {
+            self.state = ThreadState::Zombie(res);
+            return true;
+        }
+        false
     }
 
-    /// Unblock process
-    pub fn unblock(&mut self) {
-        assert!(self.is_blocked());
-        self.state = ProcessState::Ready;
+    /// Interrupt whatever the thread is blocked on if its process has signals waiting
+    ///
+    /// Only the main thread is interrupted, so a signal doesn't cut every thread's wait short
+    pub fn interrupt_if_signalled(&mut self) {
+        if !self.is_blocked() || self.tid != self.pid {
+            return;
+        }
+        // Stopped threads only go again once continued
+        if self.state == ThreadState::Blocked(BlockCondition::Stopped(self.pid)) {
+            return;
+        }
+        let process = self.process.lock();
+        if process.pending_signals != 0 && process.stopped.is_none() {
+            drop(process);
+            self.frame
+                .as_mut()
+                .set_return_value::<usize>(&Err(KernelError::Interrupted));
+            self.unblock();
+        }
     }
 
-    /// Get the heap breakline
-    pub fn breakline(&self) -> usize {
-        self.breakline
+    /// Act on the process's pending signals before returning to userspace
+    ///
+    /// This either terminates the process, or sets this thread up to run a handler. Any other
+    /// pending signals wait until the handler returns. If the process is stopped, the thread
+    /// blocks until it's continued. Pages faulted in for the handler's stack frame may leave
+    /// stale mappings in `stale`
+    pub fn deliver_signals(&mut self, stale: &mut StaleMappings) -> KernelResult<()> {
+        if self.follow_process_exit() {
+            return Ok(());
+        }
+
+        let mut process = self.process.lock();
+        if process.pending_signals & (1 << usize::from(Signal::Kill)) != 0 {
+            drop(process);
+            return self.exit_process(Err(ProcessError::Killed(Signal::Kill)));
+        }
+        if process.stopped.is_some() {
+            drop(process);
+            self.block(BlockCondition::Stopped(self.pid));
+            return Ok(());
+        }
+
+        while process.pending_signals != 0 {
+            let number = process.pending_signals.trailing_zeros() as usize;
+            process.pending_signals &= !(1 << number);
+            let Some(signal) = Signal::n(number) else {
+                continue;
+            };
+
+            let action = process.signal_actions[number];
+            match action {
+                SignalAction::Default if signal.terminates_by_default() => {
+                    drop(process);
+                    return self.exit_process(Err(ProcessError::Killed(signal)));
+                }
+                SignalAction::Default | SignalAction::Ignore => {}
+                SignalAction::Handler { entry, handler } => {
+                    drop(process);
+                    return self.enter_signal_handler(signal, entry, handler, stale);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Save the current context on the user stack, and jump to the handler's entry point
+    fn enter_signal_handler(
+        &mut self,
+        signal: Signal,
+        entry: usize,
+        handler: usize,
+        stale: &mut StaleMappings,
+    ) -> KernelResult<()> {
+        let saved = SignalFrame {
+            regs: self.frame.as_ref().regs,
+            pc: self.pc,
+        };
+        let size = mem::size_of::<SignalFrame>();
+        let sp = self
+            .frame
+            .as_ref()
+            .stack_pointer()
+            .checked_sub(size)
+            .map(align_down::<16>)
+            .ok_or(KernelError::ForbiddenPage)?;
+
+        {
+            let mut process = self.process.lock();
+            process.fault_in(sp, size, true, stale)?;
+            let bytes = unsafe { slice::from_raw_parts(ptr::from_ref(&saved).cast::<u8>(), size) };
+            mmu::copy_to_user(process.root_page_table.as_ref(), sp, bytes)?;
+        }
+
+        let frame = self.frame.as_mut();
+        frame.set_stack_pointer(sp);
+        frame.set_reg(Register::Arg0, signal.into());
+        frame.set_reg(Register::Arg1, handler);
+        frame.set_reg(Register::Arg2, sp);
+        // The entry point must not return
+        frame.set_reg(Register::ReturnAddress, 0);
+        self.pc = entry;
+        Ok(())
     }
 
-    /// Allocate user pages
+    /// Restore the context saved before a signal handler was entered
     ///
-    /// Returns the new breakline
-    pub fn request_memory(&mut self, bytes: usize) -> KernelResult<usize> {
-        if bytes == 0 {
-            return Ok(self.breakline);
+    /// `addr` is the address of the saved context on the user stack
+    pub fn signal_return(&mut self, addr: usize, stale: &mut StaleMappings) -> KernelResult<()> {
+        let mut saved = SignalFrame::default();
+        let size = mem::size_of::<SignalFrame>();
+        let bytes =
+            unsafe { slice::from_raw_parts_mut(ptr::from_mut(&mut saved).cast::<u8>(), size) };
+        {
+            let mut process = self.process.lock();
+            process.fault_in(addr, size, false, stale)?;
+            mmu::copy_from_user(process.root_page_table.as_ref(), addr, bytes)?;
         }
 
-        let num_pages = align_up::<PAGE_SIZE>(bytes) / PAGE_SIZE;
-        let new_allocation = mmu::zalloc_slice(num_pages);
-        let paddr = mmu::ks_vaddr_to_paddr(new_allocation.addr())?;
+        self.frame.as_mut().regs = saved.regs;
+        self.pc = saved.pc;
+        Ok(())
+    }
 
-        self.breakline = mmu::map_range(
-            self.root_page_table.as_mut(),
-            self.breakline.try_into()?,
-            paddr,
-            PageType::UserReadWrite,
-            new_allocation.num_pages() * PAGE_SIZE,
-        )?
-        .into();
+    /// Return true if blocked
+    pub fn is_blocked(&self) -> bool {
+        matches!(self.state, ThreadState::Blocked(_))
+    }
+
+    /// Block thread on some condition
+    pub fn block(&mut self, condition: BlockCondition) {
+        self.pause();
+        self.state = ThreadState::Blocked(condition);
+    }
 
-        self.heap.push(new_allocation);
+    /// Run the syscall the thread is in again when it next runs
+    ///
+    /// Only for syscalls that blocked without setting a return value, so the arguments are still
+    /// in place
+    pub fn retry_syscall(&mut self) {
+        // Back to the ecall
+        self.pc -= 4;
+    }
 
-        Ok(self.breakline)
+    /// Unblock thread
+    pub fn unblock(&mut self) {
+        assert!(self.is_blocked());
+        self.state = ThreadState::Ready;
+        self.ready_since = Instant::now();
+    }
+}
+
+// Top of the stack in slot `slot`
+fn stack_top(slot: usize) -> usize {
+    STACK_TOP - slot * STACK_SLOT_SIZE
+}
+
+// Name of a program, from the last path component of the first of its arguments
+fn command_name(argv: &[u8]) -> String {
+    let arg0 = argv.split(|byte| *byte == 0).next().unwrap_or_default();
+    let name = arg0.rsplit(|byte| *byte == b'/').next().unwrap_or_default();
+    String::from_utf8_lossy(name).into()
+}
+
+// Count the strings in a list of NUL-terminated strings
+fn count_strings(strings: &[u8]) -> KernelResult<usize> {
+    match strings.last() {
+        None => Ok(0),
+        Some(0) => Ok(strings.iter().filter(|byte| **byte == 0).count()),
+        Some(_) => Err(KernelError::InvalidArguments),
     }
 }
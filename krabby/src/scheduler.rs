@@ -1,153 +1,854 @@
 use crate::{
+    cpu::MAX_HARTS,
     idle,
+    mmu::StaleMappings,
     prelude::*,
-    process::{BlockCondition, Process, ProcessState},
+    process::{BlockCondition, Process, Thread, ThreadInfo, ThreadState},
+    smp,
     timer::Instant,
 };
-use core::sync::atomic::{AtomicUsize, Ordering};
-use riscv::register::{sepc, sstatus};
-use spin::Mutex;
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    iter,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use krabby_abi::{
+    process::ProcessInfo, signal::Signal, wait::WaitStatus, ProcessError, ProcessResult,
+};
+use riscv::register::sepc;
+use spin::{Mutex, RwLock};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_QUEUES: Mutex<RunQueues> = Mutex::new(RunQueues::new());
+
+// Thread lists are per CPU core
+static THREADS: [Mutex<RunQueues>; MAX_HARTS] = [EMPTY_QUEUES; MAX_HARTS];
+
+// Searches across every hart hold this for reading. Moving a thread between harts holds it for
+// writing, so a search can't miss a thread in transit
+static SEARCH_LOCK: RwLock<()> = RwLock::new(());
+
+// Held while checking a futex word before waiting on it, and while waking its waiters, so a
+// wakeup can't slip in between
+static FUTEX_LOCK: Mutex<()> = Mutex::new(());
+
+// Bit mask of harts with nothing to run
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+// How buried processes and threads ended, until somebody waits on them. Recorded with
+// `SEARCH_LOCK` held, so a search finds either the zombie or its record. Nothing else is locked
+// while this is held
+static ZOMBIES: Mutex<Vec<Zombie>> = Mutex::new(Vec::new());
+
+// What's left of a process or thread after it's been buried
+struct Zombie {
+    // [BlockCondition::OnDeathOfPid] or [BlockCondition::OnDeathOfThread]
+    death: BlockCondition,
+    // Process that may wait on it: the parent of a process, or the process of a thread
+    waiter: Pid,
+    status: ProcessResult,
+}
+
+// A death, to let its waiters know about once it's recorded
+struct Death {
+    pid: Pid,
+    tid: Pid,
+    status: ProcessResult,
+    // Set if the thread was the last of its process
+    process_status: Option<ProcessResult>,
+    ppid: Option<Pid>,
+}
+
+// Every thread on a CPU core, sorted by whether it can run
+struct RunQueues {
+    // The thread that has the CPU, if it's not idle
+    running: Option<Thread>,
+    // Threads waiting for their turn. Within a priority, they're run front to back
+    ready: VecDeque<Thread>,
+    // Threads waiting on some condition
+    blocked: Vec<Thread>,
+    // Set when a thread is woken, so the hart can be poked to run it
+    woken: bool,
+}
+
+impl RunQueues {
+    const fn new() -> Self {
+        Self {
+            running: None,
+            ready: VecDeque::new(),
+            blocked: Vec::new(),
+            woken: false,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Thread> {
+        self.running
+            .iter()
+            .chain(self.ready.iter())
+            .chain(self.blocked.iter())
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Thread> {
+        self.running
+            .iter_mut()
+            .chain(self.ready.iter_mut())
+            .chain(self.blocked.iter_mut())
+    }
+
+    fn contains(&self, tid: Pid) -> bool {
+        self.iter().any(|thread| thread.tid == tid)
+    }
+
+    // Run `f` over thread `tid`, and move it to the right queue if it blocked or unblocked
+    fn with_thread<T>(
+        &mut self,
+        tid: Pid,
+        f: impl FnOnce(&mut Thread) -> KernelResult<T>,
+    ) -> KernelResult<T> {
+        if let Some(thread) = self.running.as_mut().filter(|thread| thread.tid == tid) {
+            // The scheduler moves the running thread itself
+            return f(thread);
+        }
+
+        if let Some(i) = self.ready.iter().position(|thread| thread.tid == tid) {
+            let rv = f(&mut self.ready[i]);
+            if self.ready[i].is_blocked() {
+                let thread = self.ready.remove(i).expect("out-of-bounds");
+                self.blocked.push(thread);
+            }
+            return rv;
+        }
+
+        if let Some(i) = self.blocked.iter().position(|thread| thread.tid == tid) {
+            let rv = f(&mut self.blocked[i]);
+            self.wake(i);
+            return rv;
+        }
+
+        Err(KernelError::ProcessNotFound(tid))
+    }
+
+    // Move blocked thread `i` to the ready queue if it's no longer blocked, or a signal
+    // interrupted it
+    //
+    // Returns true if it was moved
+    fn wake(&mut self, i: usize) -> bool {
+        self.blocked[i].interrupt_if_signalled();
+        if self.blocked[i].is_blocked() {
+            return false;
+        }
+        let thread = self.blocked.swap_remove(i);
+        self.ready.push_back(thread);
+        self.woken = true;
+        true
+    }
+
+    // Move every blocked thread that's no longer blocked to the ready queue
+    fn wake_all(&mut self) {
+        let mut i = 0;
+        while i < self.blocked.len() {
+            if !self.wake(i) {
+                i += 1;
+            }
+        }
+    }
+
+    // Take the next thread to run off the ready queue
+    //
+    // This is the first thread with the highest priority, counting what it gained by waiting.
+    // Zombies are left for reaping
+    fn next(&mut self) -> Option<Thread> {
+        let (i, _) = self
+            .ready
+            .iter()
+            .enumerate()
+            .filter(|(_, thread)| !matches!(thread.state, ThreadState::Zombie(_)))
+            // `max_by_key` picks the last of equals, so reverse to get the first
+            .rev()
+            .max_by_key(|(_, thread)| thread.priority() + thread.age())?;
+        self.ready.remove(i)
+    }
+
+    // Should the running thread give up the CPU?
+    //
+    // It goes to the back of the ready queue once its time slice runs out, even if nothing else
+    // is ready, so it's charged for the slice. Waiting threads only catch up once it's there, so
+    // aging doesn't cut time slices short
+    fn should_preempt(&self, thread: &Thread) -> bool {
+        let priority = thread.priority();
+        thread.slice_expired() || self.ready.iter().any(|other| other.priority() > priority)
+    }
+
+    // Put away the running thread if it blocked or exited
+    fn put_away_running(&mut self) {
+        if let Some(thread) = self.running.take() {
+            if thread.state == ThreadState::Running {
+                self.running = Some(thread);
+            } else if thread.is_blocked() {
+                self.blocked.push(thread);
+            } else {
+                self.ready.push_back(thread);
+            }
+        }
+    }
 
-// Only supporting one hart currently
-const MAX_HARTS: usize = 1;
+    // Take every zombie out of the queues, including threads of processes that exited
+    fn take_zombies(&mut self) -> Vec<Thread> {
+        for thread in self.iter_mut() {
+            thread.follow_process_exit();
+        }
+        self.put_away_running();
 
-// Processes lists are per CPU core
-static PROCESSES: Mutex<Vec<Process>> = Mutex::new(Vec::new());
+        let is_zombie = |thread: &Thread| matches!(thread.state, ThreadState::Zombie(_));
+        let mut zombies = Vec::new();
+        let mut i = 0;
+        while i < self.ready.len() {
+            if is_zombie(&self.ready[i]) {
+                zombies.extend(self.ready.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        let mut i = 0;
+        while i < self.blocked.len() {
+            if is_zombie(&self.blocked[i]) {
+                zombies.push(self.blocked.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        zombies
+    }
+
+    fn wake_sleepers(&mut self) {
+        let now = Instant::now();
+        for thread in self.blocked.iter_mut() {
+            match thread.state {
+                ThreadState::Blocked(BlockCondition::Until(instant)) if now >= instant => {
+                    thread.unblock();
+                }
+                ThreadState::Blocked(BlockCondition::OnFutex {
+                    deadline: Some(deadline),
+                    ..
+                }) if now >= deadline => {
+                    thread
+                        .frame
+                        .as_mut()
+                        .set_return_value::<usize>(&Err(KernelError::TimedOut));
+                    thread.unblock();
+                }
+                _ => {}
+            }
+        }
+        self.wake_all();
+    }
+
+    // Unblock threads waiting on a death or stop, which just happened
+    //
+    // Returns true if any were waiting
+    fn notify_waiters(&mut self, condition: BlockCondition, status: WaitStatus) -> bool {
+        let mut notified = false;
+        for thread in self.blocked.iter_mut() {
+            if thread.state == ThreadState::Blocked(condition) {
+                thread.frame.as_mut().set_wait_status(status);
+                thread.unblock();
+                notified = true;
+            }
+        }
+        self.wake_all();
+        notified
+    }
+}
 
 extern "C" {
     fn enter_user_mode();
 }
 
-/// Add a process to the scheduler
-pub fn add_process(process: Process) {
-    PROCESSES.lock().push(process);
+// Run `f` over the queues of `hart`, then poke it if a thread woke up there
+fn with_queues<T>(hart: usize, f: impl FnOnce(&mut RunQueues) -> T) -> T {
+    let (rv, woken) = {
+        let mut queues = THREADS[hart].lock();
+        let rv = f(&mut queues);
+        (rv, core::mem::take(&mut queues.woken))
+    };
+
+    let hart_id = HartId::from(hart);
+    if woken && hart_id != HartId::current() && smp::is_online(hart_id) {
+        // If it fails, the hart finds out on its next tick anyways
+        let _ = smp::send_ipi(hart_id);
+    }
+    rv
+}
+
+// Every hart, starting with the current one
+fn harts() -> impl Iterator<Item = usize> {
+    let current = usize::from(HartId::current());
+    iter::once(current).chain((0..MAX_HARTS).filter(move |hart| *hart != current))
+}
+
+// Wake threads that are no longer blocked on every hart
+fn wake_everywhere() {
+    let _search = SEARCH_LOCK.read();
+    for hart in harts() {
+        with_queues(hart, RunQueues::wake_all);
+    }
+}
+
+/// Add a thread to this hart's scheduler
+///
+/// An idle hart is poked, so it can come take it
+pub fn add_thread(thread: Thread) {
+    let current = usize::from(HartId::current());
+    THREADS[current].lock().ready.push_back(thread);
+
+    let idle = IDLE_HARTS.load(Ordering::SeqCst) & !(1 << current);
+    if idle != 0 {
+        let _ = smp::send_ipi(HartId::from(idle.trailing_zeros() as usize));
+    }
 }
 
-/// Start the scheduler
-pub fn start_with(process: Process) {
-    add_process(process);
-    let pc = switch_processes(HartId::zero());
+/// Start the scheduler on this hart
+pub fn start() {
+    let pc = switch_processes(HartId::current());
 
     unsafe {
-        sstatus::set_spp(sstatus::SPP::User);
         sepc::write(pc);
         enter_user_mode();
     }
 }
 
-/// Change up processes
+/// Start the scheduler with a first thread
+pub fn start_with(thread: Thread) {
+    add_thread(thread);
+    start();
+}
+
+/// Change up threads
 ///
 /// Returns the new program counter
 pub fn switch_processes(hart_id: HartId) -> usize {
-    schedule_inner(hart_id, &mut PROCESSES.lock())
+    let hart = usize::from(hart_id);
+    assert!(hart < MAX_HARTS);
+
+    let deaths: Vec<Death> = {
+        let _search = SEARCH_LOCK.read();
+        let zombies = THREADS[hart].lock().take_zombies();
+        zombies.into_iter().map(bury).collect()
+    };
+    for death in deaths {
+        announce(death);
+    }
+
+    // Dropped after the queues are unlocked, since it may wait on other harts
+    let mut stale = StaleMappings::default();
+    let mut queues = THREADS[hart].lock();
+    schedule_inner(hart, &mut queues, &mut stale)
+}
+
+/// Run method over thread `tid`
+pub fn with_thread<T>(tid: Pid, f: impl FnOnce(&mut Thread) -> KernelResult<T>) -> KernelResult<T> {
+    let _search = SEARCH_LOCK.read();
+
+    let mut f = Some(f);
+    for hart in harts() {
+        let rv = with_queues(hart, |queues| {
+            if !queues.contains(tid) {
+                return None;
+            }
+            let f = f.take().expect("Thread found twice");
+            Some(queues.with_thread(tid, f))
+        });
+        if let Some(rv) = rv {
+            return rv;
+        }
+    }
+    Err(KernelError::ProcessNotFound(tid))
 }
 
 /// Run method over process `pid`
-pub fn with_process<T>(pid: Pid, f: impl Fn(&mut Process) -> KernelResult<T>) -> KernelResult<T> {
-    let mut processes = PROCESSES.lock();
-    for proc in processes.iter_mut() {
-        if proc.pid == pid {
-            return f(proc);
+///
+/// No scheduler locks are held while it runs
+pub fn with_process<T>(
+    pid: Pid,
+    f: impl FnOnce(&mut Process) -> KernelResult<T>,
+) -> KernelResult<T> {
+    let process = {
+        let _search = SEARCH_LOCK.read();
+        harts()
+            .find_map(|hart| {
+                THREADS[hart]
+                    .lock()
+                    .iter()
+                    .find(|thread| thread.pid == pid)
+                    .map(|thread| thread.process.clone())
+            })
+            .ok_or(KernelError::ProcessNotFound(pid))?
+    };
+    let rv = f(&mut process.lock());
+    rv
+}
+
+/// Run method over each process in process group `pgid`
+pub fn with_process_group(
+    pgid: Pid,
+    mut f: impl FnMut(&mut Process) -> KernelResult<()>,
+) -> KernelResult<()> {
+    let mut group: Vec<Arc<Mutex<Process>>> = Vec::new();
+    {
+        let _search = SEARCH_LOCK.read();
+        for hart in harts() {
+            let queues = THREADS[hart].lock();
+            for thread in queues.iter() {
+                let seen = group
+                    .iter()
+                    .any(|process| Arc::ptr_eq(process, &thread.process));
+                if !seen && thread.pgid() == pgid {
+                    group.push(thread.process.clone());
+                }
+            }
         }
     }
-    Err(KernelError::ProcessNotFound(pid))
+
+    let rv = group.iter().try_for_each(|process| f(&mut process.lock()));
+    // In case they were signalled
+    wake_everywhere();
+    rv
 }
 
-pub fn on_interrupt(
-    trigger: InterruptId,
-    func: impl FnOnce(&mut Process) -> KernelResult<()>,
-) -> KernelResult {
-    let mut processes = PROCESSES.lock();
-    for process in processes.iter_mut() {
-        let ProcessState::Blocked(condition) = process.state else {
-            continue;
-        };
-        match condition {
-            BlockCondition::OnUart(id) if id == trigger => {
-                return func(process);
+/// Send `signal` to process `pid`
+///
+/// If that stops the process, its parent and anybody waiting on it hear about it right away
+pub fn signal(pid: Pid, signal: Signal) -> KernelResult<()> {
+    let (changed, stopped, ppid) = with_process(pid, |process| {
+        let changed = process.signal(signal);
+        Ok((changed, process.stopped(), process.ppid))
+    })?;
+
+    match (changed, stopped) {
+        (true, Some(stop_signal)) => {
+            {
+                let _search = SEARCH_LOCK.read();
+                for hart in harts() {
+                    with_queues(hart, |queues| {
+                        queues.notify_waiters(
+                            BlockCondition::OnChangeOfPid(pid),
+                            WaitStatus::Stopped(stop_signal),
+                        );
+                    });
+                }
+            }
+            if let Some(ppid) = ppid {
+                let _ = self::signal(ppid, Signal::Child);
             }
-            _ => {}
         }
+        (true, None) => {
+            let _search = SEARCH_LOCK.read();
+            for hart in harts() {
+                with_queues(hart, |queues| {
+                    for thread in queues.blocked.iter_mut() {
+                        if thread.state == ThreadState::Blocked(BlockCondition::Stopped(pid)) {
+                            thread.unblock();
+                        }
+                    }
+                    queues.wake_all();
+                });
+            }
+        }
+        (false, _) => {}
     }
+
+    wake_everywhere();
     Ok(())
 }
 
-fn reap(processes: &mut Vec<Process>) {
-    let mut zombies = Vec::new();
+/// Send `signal` to every process in process group `pgid`
+pub fn signal_group(pgid: Pid, signal: Signal) -> KernelResult<()> {
+    let mut group = Vec::new();
+    with_process_group(pgid, |process| {
+        group.push(process.pid);
+        Ok(())
+    })?;
+    for pid in group {
+        // It may have exited in the meantime
+        let _ = self::signal(pid, signal);
+    }
+    Ok(())
+}
 
-    let mut i = 0;
-    let mut len = processes.len();
-    while i < len {
-        match processes[i].state {
-            ProcessState::Zombie(_) => {
-                zombies.push(processes.swap_remove(i));
-                len -= 1;
-            }
-            ProcessState::Blocked(BlockCondition::Until(instant)) => {
-                let now = Instant::now();
-                if now >= instant {
-                    processes[i].unblock();
+/// Describe each process with a PID of at least `first`, in PID order, until `f` returns false
+///
+/// `f` runs with the process locked, but no scheduler locks held
+pub fn list_processes(first: u16, mut f: impl FnMut(&ProcessInfo) -> bool) -> KernelResult<()> {
+    let mut processes: Vec<(Pid, Arc<Mutex<Process>>, Vec<ThreadInfo>)> = Vec::new();
+    {
+        let _search = SEARCH_LOCK.read();
+        for hart in harts() {
+            let queues = THREADS[hart].lock();
+            for thread in queues.iter().filter(|t| u16::from(t.pid) >= first) {
+                match processes.iter_mut().find(|(pid, ..)| *pid == thread.pid) {
+                    Some((_, _, threads)) => threads.push(thread.info()),
+                    None => processes.push((
+                        thread.pid,
+                        thread.process.clone(),
+                        Vec::from([thread.info()]),
+                    )),
                 }
             }
-            _ => {}
         }
-        i += 1;
     }
+    processes.sort_unstable_by_key(|(pid, ..)| u16::from(*pid));
+
+    for (_, process, threads) in processes {
+        if !f(&process.lock().info(&threads)?) {
+            break;
+        }
+    }
+    Ok(())
+}
 
-    // Unblock processes waiting on deaths
-    for zombie in zombies {
-        let ProcessState::Zombie(res) = zombie.state else {
-            panic!("Found non-zombie in zombie list!");
+/// Block thread `tid` of process `pid` until the process or thread `condition` waits on dies,
+/// or stops if waiting on [BlockCondition::OnChangeOfPid]
+///
+/// Returns what happened without blocking if it already has. Only the parent can wait on a
+/// process, and only threads of the same process can wait on a thread
+pub fn block_until_death(
+    pid: Pid,
+    tid: Pid,
+    condition: BlockCondition,
+) -> KernelResult<Option<WaitStatus>> {
+    let (death, not_found) = match condition {
+        BlockCondition::OnDeathOfPid(target) | BlockCondition::OnChangeOfPid(target) => (
+            BlockCondition::OnDeathOfPid(target),
+            KernelError::NotAChild(target),
+        ),
+        BlockCondition::OnDeathOfThread(target) => {
+            (condition, KernelError::ProcessNotFound(target))
+        }
+        _ => return Err(KernelError::InvalidArguments),
+    };
+    let is_target = |thread: &&Thread| match death {
+        BlockCondition::OnDeathOfPid(target) => thread.pid == target,
+        BlockCondition::OnDeathOfThread(target) => thread.tid == target,
+        _ => false,
+    };
+
+    // Keeps the target from being buried, or stopping, between checking on it and blocking
+    let _search = SEARCH_LOCK.write();
+
+    let target = (0..MAX_HARTS).find_map(|hart| {
+        THREADS[hart]
+            .lock()
+            .iter()
+            .find(is_target)
+            .map(|thread| thread.process.clone())
+    });
+    let Some(target) = target else {
+        let status = collect_zombie(death, pid).ok_or(not_found)?;
+        return Ok(Some(WaitStatus::Exited(status)));
+    };
+    {
+        let target = target.lock();
+        let waiter = match death {
+            BlockCondition::OnDeathOfPid(_) => target.ppid,
+            _ => Some(target.pid),
         };
+        if waiter != Some(pid) {
+            return Err(not_found);
+        }
+        if let BlockCondition::OnChangeOfPid(_) = condition {
+            if let Some(signal) = target.stopped() {
+                return Ok(Some(WaitStatus::Stopped(signal)));
+            }
+        }
+    }
 
-        for process in processes.iter_mut() {
-            let ProcessState::Blocked(condition) = process.state else {
-                continue;
-            };
-            let BlockCondition::OnDeathOfPid(blocked_on_pid) = condition else {
-                continue;
-            };
+    for hart in 0..MAX_HARTS {
+        let mut queues = THREADS[hart].lock();
+        if queues.contains(tid) {
+            return queues.with_thread(tid, |thread| {
+                thread.block(condition);
+                Ok(None)
+            });
+        }
+    }
+    Err(KernelError::ProcessNotFound(tid))
+}
+
+// Take the record of `death` if process `waiter` may wait on it
+fn collect_zombie(death: BlockCondition, waiter: Pid) -> Option<ProcessResult> {
+    let mut zombies = ZOMBIES.lock();
+    let i = zombies
+        .iter()
+        .position(|zombie| zombie.death == death && zombie.waiter == waiter)?;
+    Some(zombies.swap_remove(i).status)
+}
+
+/// Block thread `tid` of process `pid` on the futex word at `addr`, if it still holds
+/// `expected`
+///
+/// Returns false without blocking if the word has changed. With a `timeout`, the thread gives up
+/// waiting after that long
+pub fn futex_wait(
+    pid: Pid,
+    tid: Pid,
+    addr: usize,
+    expected: u32,
+    timeout: Option<Duration>,
+) -> KernelResult<bool> {
+    // Dropped after the futex lock, since it may wait on other harts
+    let mut stale = StaleMappings::default();
+    let _futex = FUTEX_LOCK.lock();
+
+    let (paddr, value) = with_process(pid, |process| process.futex_word(addr, &mut stale))?;
+    if value != expected {
+        return Ok(false);
+    }
+
+    // A deadline too far off to keep track of is as good as none
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    with_thread(tid, |thread| {
+        thread.block(BlockCondition::OnFutex { paddr, deadline });
+        Ok(true)
+    })
+}
+
+/// Wake up to `count` threads waiting on the futex word at `addr` in process `pid`
+///
+/// Returns how many were woken
+pub fn futex_wake(pid: Pid, addr: usize, count: usize) -> KernelResult<usize> {
+    let mut stale = StaleMappings::default();
+    let _futex = FUTEX_LOCK.lock();
 
-            if zombie.pid == blocked_on_pid {
-                process.frame.as_mut().set_exit_value(res);
-                process.unblock();
+    let (paddr, _) = with_process(pid, |process| process.futex_word(addr, &mut stale))?;
+
+    let _search = SEARCH_LOCK.read();
+    let mut woken = 0;
+    for hart in harts() {
+        with_queues(hart, |queues| {
+            for thread in queues.blocked.iter_mut() {
+                if woken == count {
+                    break;
+                }
+                if let ThreadState::Blocked(BlockCondition::OnFutex {
+                    paddr: waiting_on, ..
+                }) = thread.state
+                {
+                    if waiting_on == paddr {
+                        thread.unblock();
+                        woken += 1;
+                    }
+                }
+            }
+            queues.wake_all();
+        });
+    }
+    Ok(woken)
+}
+
+/// Wake every thread blocked on `condition`, to retry the syscall it blocked in
+pub fn retry_blocked(condition: BlockCondition) {
+    let _search = SEARCH_LOCK.read();
+    for hart in harts() {
+        with_queues(hart, |queues| {
+            for thread in queues.blocked.iter_mut() {
+                if thread.state == ThreadState::Blocked(condition) {
+                    thread.retry_syscall();
+                    thread.unblock();
+                }
             }
+            queues.wake_all();
+        });
+    }
+}
+
+/// Offer an interrupt to threads blocked on it, until `func` accepts one
+///
+/// Returns true if a thread accepted
+pub fn on_interrupt(
+    trigger: InterruptId,
+    mut func: impl FnMut(&mut Thread) -> KernelResult<bool>,
+) -> KernelResult<bool> {
+    let _search = SEARCH_LOCK.read();
+
+    for hart in harts() {
+        let accepted = with_queues(hart, |queues| {
+            for i in 0..queues.blocked.len() {
+                let thread = &mut queues.blocked[i];
+                match thread.state {
+                    ThreadState::Blocked(BlockCondition::OnUart(id)) if id == trigger => {
+                        if func(thread)? {
+                            queues.wake(i);
+                            return Ok(true);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(false)
+        })?;
+        if accepted {
+            return Ok(true);
         }
     }
+    Ok(false)
 }
 
-// Round-robin scheduler
-fn schedule_inner(hart_id: HartId, processes: &mut Vec<Process>) -> usize {
-    assert!(usize::from(hart_id) < MAX_HARTS);
-    assert!(!processes.is_empty());
+// Free a thread that died, keeping a record of how it ended for whoever waits on it
+//
+// If it was the last of its process, the process died with it. Called with `SEARCH_LOCK` held,
+// so searches can't miss both the thread and its record
+fn bury(zombie: Thread) -> Death {
+    let ThreadState::Zombie(status) = zombie.state else {
+        panic!("Found non-zombie in zombie list!");
+    };
 
-    // Reap any zombie processes
-    reap(processes);
+    let (last, process_status, ppid) = {
+        let mut process = zombie.process.lock();
+        let last = process.remove_thread(&zombie);
+        (last, process.exit_status().unwrap_or(status), process.ppid)
+    };
+    // Only a parent that's still around can wait on the process
+    let ppid =
+        ppid.filter(|&ppid| harts().any(|hart| THREADS[hart].lock().iter().any(|t| t.pid == ppid)));
 
-    // Pause all processes
-    for process in processes.iter_mut() {
-        if process.state == ProcessState::Running {
-            process.pause();
-            break;
+    let mut zombies = ZOMBIES.lock();
+    if last {
+        // Nobody is left to wait on its threads, or on its children
+        zombies.retain(|record| record.waiter != zombie.pid);
+        if let Some(ppid) = ppid {
+            zombies.push(Zombie {
+                death: BlockCondition::OnDeathOfPid(zombie.pid),
+                waiter: ppid,
+                status: process_status,
+            });
         }
+    } else {
+        zombies.push(Zombie {
+            death: BlockCondition::OnDeathOfThread(zombie.tid),
+            waiter: zombie.pid,
+            status,
+        });
     }
 
-    // TODO(optimization): pick a proper ordering
-    // SeqCst is the safest
-    static INDEX: AtomicUsize = AtomicUsize::new(1);
-    let mut index = INDEX.fetch_add(1, Ordering::SeqCst);
-    let len = processes.len();
+    Death {
+        pid: zombie.pid,
+        tid: zombie.tid,
+        status,
+        process_status: last.then_some(process_status),
+        ppid,
+    }
+}
 
-    // Run the next non-blocked process
-    for _ in 0..len {
-        let process: &mut Process = processes.get_mut(index % len).expect("out-of-bounds");
-        if process.is_blocked() {
-            index += 1;
-            continue;
+// Let everybody know about a death
+//
+// Threads waiting on it, and the parent, may be on any hart. A waiter that's woken takes the
+// record of it
+fn announce(death: Death) {
+    let (mut thread_waited_on, mut process_waited_on) = (false, false);
+    {
+        let _search = SEARCH_LOCK.read();
+        for hart in harts() {
+            with_queues(hart, |queues| {
+                thread_waited_on |= queues.notify_waiters(
+                    BlockCondition::OnDeathOfThread(death.tid),
+                    WaitStatus::Exited(death.status),
+                );
+                if let Some(process_status) = death.process_status {
+                    let status = WaitStatus::Exited(process_status);
+                    process_waited_on |=
+                        queues.notify_waiters(BlockCondition::OnDeathOfPid(death.pid), status);
+                    process_waited_on |=
+                        queues.notify_waiters(BlockCondition::OnChangeOfPid(death.pid), status);
+                }
+            });
+        }
+    }
+    if thread_waited_on {
+        let _ = collect_zombie(BlockCondition::OnDeathOfThread(death.tid), death.pid);
+    }
+    if let (true, Some(ppid)) = (process_waited_on, death.ppid) {
+        let _ = collect_zombie(BlockCondition::OnDeathOfPid(death.pid), ppid);
+    }
+
+    // Let the parent know, now that it's done waiting
+    if let (Some(_), Some(ppid)) = (death.process_status, death.ppid) {
+        let _ = signal(ppid, Signal::Child);
+    }
+}
+
+// Take a ready thread from another hart, if one can be had without waiting
+//
+// Called with the current hart's queues locked, so this must never block on another hart's
+fn steal(hart: usize) -> Option<Thread> {
+    let _moving = SEARCH_LOCK.try_write()?;
+
+    (0..MAX_HARTS)
+        .filter(|victim| *victim != hart)
+        .find_map(|victim| {
+            let mut queues = THREADS[victim].try_lock()?;
+            // Take from the back, where it'd have waited longest
+            let i = queues
+                .ready
+                .iter()
+                .rposition(|thread| !matches!(thread.state, ThreadState::Zombie(_)))?;
+            queues.ready.remove(i)
+        })
+}
+
+// Priority scheduler
+//
+// The highest priority ready thread runs, but threads gain priority while they wait, so lower
+// priorities still get a share of time. Threads of the same priority take turns, each running
+// until it blocks or its time slice runs out. A hart with nothing to run steals from the others
+fn schedule_inner(hart: usize, queues: &mut RunQueues, stale: &mut StaleMappings) -> usize {
+    queues.put_away_running();
+    queues.wake_sleepers();
+    // This hart is about to look at everything that woke up anyways
+    queues.woken = false;
+
+    // Let somebody else have a turn, if it's due
+    if let Some(mut thread) = queues.running.take() {
+        if queues.should_preempt(&thread) {
+            thread.pause();
+            queues.ready.push_back(thread);
+        } else {
+            queues.running = Some(thread);
         }
-        process.switch();
-        return process.pc;
     }
 
-    idle::chill()
+    loop {
+        if queues.running.is_none() {
+            let Some(thread) = queues.next().or_else(|| steal(hart)) else {
+                IDLE_HARTS.fetch_or(1 << hart, Ordering::SeqCst);
+                return idle::chill();
+            };
+            queues.running = Some(thread);
+        }
+        let thread = queues.running.as_mut().expect("No running thread");
+
+        // Signals may send the thread off to a handler, or kill the process outright
+        if let Err(err) = thread.deliver_signals(stale) {
+            println!(
+                "[kernel: failed to deliver signal to process {}: {err}]",
+                thread.pid
+            );
+            let _ = thread.exit_process(Err(ProcessError::SegmentationFault));
+        }
+
+        if matches!(thread.state, ThreadState::Zombie(_)) {
+            // Reaped next time around
+            queues.ready.extend(queues.running.take());
+            continue;
+        }
+        if thread.is_blocked() {
+            // Its process is stopped
+            queues.blocked.extend(queues.running.take());
+            continue;
+        }
+
+        IDLE_HARTS.fetch_and(!(1 << hart), Ordering::SeqCst);
+        thread.switch();
+        return thread.pc;
+    }
 }
@@ -0,0 +1,50 @@
+//! Named segments of memory that processes map to share it without copying
+//!
+//! A segment's memory is freed once its name is removed and no process maps it anymore
+use crate::{
+    mmu::{self, Page, SharedAllocation, PAGE_SIZE},
+    prelude::*,
+    util::*,
+};
+use alloc::{collections::BTreeMap, sync::Arc};
+use spin::Mutex;
+
+/// Largest segment, in bytes. Segments are physically contiguous, so this keeps one from eating
+/// up the page heap
+pub const MAX_SEGMENT_SIZE: usize = 256 * PAGE_SIZE;
+
+/// Zeroed pages of memory that can be mapped into several processes at once
+pub type Segment = Arc<SharedAllocation<[Page<PAGE_SIZE>]>>;
+
+static SEGMENTS: Mutex<BTreeMap<String, Segment>> = Mutex::new(BTreeMap::new());
+
+/// Create a segment of at least `size` bytes called `name`
+pub fn create(name: String, size: usize) -> KernelResult<()> {
+    if name.is_empty() || size == 0 || size > MAX_SEGMENT_SIZE {
+        return Err(KernelError::InvalidArguments);
+    }
+
+    let mut segments = SEGMENTS.lock();
+    if segments.contains_key(&name) {
+        return Err(KernelError::AlreadyExists);
+    }
+    let segment = mmu::zalloc_slice(align_up::<PAGE_SIZE>(size) / PAGE_SIZE)?.into_shared();
+    segments.insert(name, segment);
+    Ok(())
+}
+
+/// Find the segment called `name`
+pub fn get(name: &str) -> KernelResult<Segment> {
+    SEGMENTS
+        .lock()
+        .get(name)
+        .cloned()
+        .ok_or(KernelError::NotFound)
+}
+
+/// Remove the name of a segment, so it's freed once nothing maps it
+pub fn remove(name: &str) -> KernelResult<()> {
+    // Dropped once the lock is released, in case this frees it
+    let _segment = SEGMENTS.lock().remove(name).ok_or(KernelError::NotFound)?;
+    Ok(())
+}
@@ -0,0 +1,155 @@
+//! Bringing up the other harts, and poking them once they're up
+use crate::{
+    cpu::MAX_HARTS,
+    drivers::DRIVERS,
+    mmu::{self, PAGE_SIZE},
+    prelude::*,
+    timer::Instant,
+};
+use core::{
+    hint,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use fdt::Fdt;
+
+// satp mode field for Sv39 paging
+const SATP_MODE_SV39: usize = 8 << 60;
+// How long to wait for a hart to pick up its boot info before giving up on it
+const BOOT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Where a parked hart finds what it needs to enter supervisor mode. Layout is shared with
+// entry.S
+#[repr(C)]
+struct HartBootInfo {
+    // The hart the info is for, or 0 if nobody. The hart clears this once it's read the rest
+    hart_id: AtomicUsize,
+    stack_top: AtomicUsize,
+    satp: AtomicUsize,
+    // Supervisor mode entry point
+    entry: AtomicUsize,
+}
+
+#[export_name = "hart_boot_info"]
+static HART_BOOT_INFO: HartBootInfo = HartBootInfo {
+    hart_id: AtomicUsize::new(0),
+    stack_top: AtomicUsize::new(0),
+    satp: AtomicUsize::new(0),
+    entry: AtomicUsize::new(0),
+};
+
+// Bit mask of harts that are ready for work
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+// TLB flushes asked of each hart, and how many of those it's done
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_FLUSHES: AtomicUsize = AtomicUsize::new(0);
+static FLUSHES_REQUESTED: [AtomicUsize; MAX_HARTS] = [NO_FLUSHES; MAX_HARTS];
+static FLUSHES_DONE: [AtomicUsize; MAX_HARTS] = [NO_FLUSHES; MAX_HARTS];
+
+extern "C" {
+    fn secondary_sv_entry();
+}
+
+/// Bring up every hart in the device tree, one at a time
+///
+/// Each one runs `kmain_secondary` on its own stack
+pub fn start_harts(fdt: &Fdt) -> KernelResult<()> {
+    let satp = SATP_MODE_SV39 | (usize::from(mmu::ks_satp()?) / PAGE_SIZE);
+
+    for cpu in fdt.cpus() {
+        let hart = cpu.ids().first();
+        if hart >= MAX_HARTS {
+            warn!("Leaving hart {hart} parked: only {MAX_HARTS} harts are supported");
+            continue;
+        }
+        let hart_id = HartId::from(hart);
+        if hart_id == HartId::current() {
+            continue;
+        }
+
+        let (_, stack_top) = mmu::kernel_stack(hart_id);
+        HART_BOOT_INFO.stack_top.store(stack_top, Ordering::Relaxed);
+        HART_BOOT_INFO.satp.store(satp, Ordering::Relaxed);
+        HART_BOOT_INFO
+            .entry
+            .store(secondary_sv_entry as usize, Ordering::Relaxed);
+        HART_BOOT_INFO.hart_id.store(hart, Ordering::SeqCst);
+        send_ipi(hart_id)?;
+
+        let deadline = Instant::now() + BOOT_TIMEOUT;
+        while HART_BOOT_INFO.hart_id.load(Ordering::SeqCst) != 0 {
+            if Instant::now() >= deadline {
+                HART_BOOT_INFO.hart_id.store(0, Ordering::SeqCst);
+                warn!("Hart {hart} didn't come up");
+                break;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    Ok(())
+}
+
+/// Mark the current hart as ready for work
+pub fn set_online() {
+    ONLINE_HARTS.fetch_or(1 << usize::from(HartId::current()), Ordering::SeqCst);
+}
+
+/// Check if `hart` is ready for work
+pub fn is_online(hart: HartId) -> bool {
+    ONLINE_HARTS.load(Ordering::SeqCst) & (1 << usize::from(hart)) != 0
+}
+
+/// Interrupt `hart`, so it reschedules
+pub fn send_ipi(hart: HartId) -> KernelResult<()> {
+    let mut timer = DRIVERS.timer.lock();
+    if let Some(timer) = &mut *timer {
+        timer.send_ipi(hart);
+        Ok(())
+    } else {
+        Err(KernelError::DriverUninitialized)
+    }
+}
+
+/// Make every hart drop stale address translations, after user mappings were changed
+///
+/// This hart flushes right away, and the IPI gets the others to flush too. Returns once they all
+/// have, so whatever was unmapped can be freed. Harts only flush between traps, so this must be
+/// called with no locks held, or it can wait on a hart that's waiting on it
+pub fn flush_tlbs() {
+    riscv::asm::sfence_vma_all();
+    let current = HartId::current();
+    let tickets: [Option<usize>; MAX_HARTS] = core::array::from_fn(|hart| {
+        let hart_id = HartId::from(hart);
+        if hart_id == current || !is_online(hart_id) {
+            return None;
+        }
+        let ticket = FLUSHES_REQUESTED[hart].fetch_add(1, Ordering::SeqCst) + 1;
+        // If it fails, the hart still flushes on its next tick
+        let _ = send_ipi(hart_id);
+        Some(ticket)
+    });
+
+    for (hart, ticket) in tickets.iter().enumerate() {
+        let Some(ticket) = *ticket else {
+            continue;
+        };
+        while FLUSHES_DONE[hart].load(Ordering::SeqCst) < ticket {
+            // The hart may be stuck here waiting on this one in turn
+            on_flush_request();
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Flush this hart's TLB if another hart asked for it with [flush_tlbs]
+pub fn on_flush_request() {
+    let hart = usize::from(HartId::current());
+    let requested = FLUSHES_REQUESTED[hart].load(Ordering::SeqCst);
+    if FLUSHES_DONE[hart].load(Ordering::SeqCst) < requested {
+        riscv::asm::sfence_vma_all();
+        // This covers every request made before it was loaded
+        FLUSHES_DONE[hart].store(requested, Ordering::SeqCst);
+    }
+}
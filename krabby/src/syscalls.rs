@@ -1,29 +1,51 @@
 use crate::{
     drivers::DRIVERS,
+    filesystem::{self, pipe, FileRef, FileSystem},
     frame::TrapFrame,
-    mmu::{self, PAGE_SIZE},
+    loader::ElfFile,
+    mmu::{self, Page, PageAllocation, StaleMappings, Sv39PageTable, PAGE_SIZE},
     prelude::*,
-    process::BlockCondition,
-    scheduler,
-    timer::Instant,
-    util::*,
+    process::{BlockCondition, SignalAction},
+    scheduler, shared_memory,
+    timer::{self, Instant},
+    tty,
+};
+use alloc::vec;
+use core::{mem, str, time::Duration};
+use krabby_abi::{
+    fs::{FileDescriptor, OpenFlags, Whence},
+    futex::NO_TIMEOUT,
+    memory::{MapFlags, Protection},
+    sched::Nice,
+    signal::{Signal, SIGNAL_DEFAULT, SIGNAL_IGNORE},
+    tty::TtyMode,
+    wait::WaitFlags,
+    ProcessError, Syscall,
 };
-use core::{cmp, time::Duration};
-use krabby_abi::{ProcessError, Syscall};
 use utf8_parser::Utf8Parser;
 
 type Args = (usize, usize, usize, usize, usize, usize, usize);
 
+// Longest path accepted from userspace, in bytes
+const MAX_PATH_LEN: usize = 256;
+
 /// Handle ecall exception
 pub fn syscall_handler(frame: &mut TrapFrame, call: usize, args: Args) -> KernelResult<()> {
     let rv = syscall_inner(frame, call, args);
-    frame.set_return_value(&rv);
+    // Returning from a signal handler restores every register, return values included, and a
+    // syscall that will be retried needs its arguments left in place
+    let keep_registers =
+        call == Syscall::SigReturn as usize || matches!(rv, Ok(SyscallResult::Retry));
+    if rv.is_err() || !keep_registers {
+        frame.set_return_value(&rv);
+    }
     rv.map(|_| ())
 }
 
 fn syscall_inner(frame: &mut TrapFrame, call: usize, args: Args) -> KernelResult<SyscallResult> {
     let call = Syscall::n(call).ok_or(KernelError::InvalidSyscall(call))?;
     let pid = frame.pid.expect("Process without PID!");
+    let tid = frame.tid.expect("Thread without TID!");
 
     let rv = match call {
         Syscall::PutChar => {
@@ -37,24 +59,12 @@ fn syscall_inner(frame: &mut TrapFrame, call: usize, args: Args) -> KernelResult
             SyscallResult::Success
         }
         Syscall::GetChar => {
-            if let Some(ch) = scheduler::with_process(pid, |p| {
-                if let Some(c) = p.stdin_buffer.pop_front() {
-                    Ok(Some(c))
-                } else {
-                    let uart = DRIVERS.uart.read();
-                    let int_id: InterruptId = if let Some(uart) = &*uart {
-                        let uart = uart.lock();
-                        *uart
-                            .info
-                            .interrupts
-                            .first()
-                            .ok_or(KernelError::InterruptUnavailable)?
-                    } else {
-                        return Err(KernelError::DriverUninitialized);
-                    };
-                    p.block(BlockCondition::OnUart(int_id));
-                    Ok(None)
+            if let Some(ch) = scheduler::with_thread(tid, |t| {
+                let ch = tty::read(t.pgid());
+                if ch.is_none() {
+                    t.block(BlockCondition::OnUart(tty::interrupt_id()?));
                 }
+                Ok(ch)
             })? {
                 SyscallResult::Value(ch as usize)
             } else {
@@ -62,67 +72,78 @@ fn syscall_inner(frame: &mut TrapFrame, call: usize, args: Args) -> KernelResult
             }
         }
         Syscall::PutString => {
-            let table = frame.root_page_table();
             let mut parser = Utf8Parser::new();
-
-            // This has to be done per page because the pages not may be contiguous in kernel space
-            let mut start = args.0;
-            let mut bytes_left = args.1;
-            while bytes_left > 0 {
-                {
-                    let size = cmp::min(bytes_left, align_next::<PAGE_SIZE>(start) - start);
-                    let start = usize::from(mmu::get_user_page(table, start.try_into()?)?);
-                    let slice: &[u8] =
-                        unsafe { core::slice::from_raw_parts(start as *const u8, size) };
-                    for byte in slice {
-                        if let Some(ch) = parser.push(*byte)? {
-                            print!("{ch}");
-                        }
+            fault_in(pid, args.0, args.1, false)?;
+            mmu::with_user_buffer(frame.root_page_table(), args.0, args.1, false, |slice| {
+                for byte in slice.iter() {
+                    if let Some(ch) = parser.push(*byte)? {
+                        print!("{ch}");
                     }
                 }
-
-                bytes_left = bytes_left.saturating_sub(PAGE_SIZE);
-                start += PAGE_SIZE;
-            }
+                Ok(slice.len())
+            })?;
             SyscallResult::Success
         }
         Syscall::Pinfo => {
-            // Currently just returning the PID, but later we can return all sorts of things
-            SyscallResult::Value(pid.into())
+            let target_pid = Pid::maybe_from_usize(args.0)?.unwrap_or(pid);
+            let mut info = None;
+            scheduler::list_processes(target_pid.into(), |found| {
+                if found.pid == target_pid {
+                    let mut encoded = vec![0; found.encoded_len()];
+                    found.encode(&mut encoded);
+                    info = Some(encoded);
+                }
+                false
+            })?;
+            let info = info.ok_or(KernelError::ProcessNotFound(target_pid))?;
+            if info.len() > args.2 {
+                return Err(KernelError::InvalidArguments);
+            }
+
+            fault_in(pid, args.1, info.len(), true)?;
+            mmu::copy_to_user(frame.root_page_table(), args.1, &info)?;
+            SyscallResult::Value(info.len())
         }
         Syscall::Fork => {
-            let mut child = scheduler::with_process(pid, |p| p.fork())?;
+            let mut child = {
+                // The parent's other threads have to stop writing to pages it now shares with
+                // the child before the child can run
+                let mut stale = StaleMappings::default();
+                scheduler::with_thread(tid, |t| t.fork(&mut stale))?
+            };
             child
                 .frame
                 .as_mut()
                 .set_return_value(&Ok(SyscallResult::Value(0)));
             let child_pid = child.pid;
-            scheduler::add_process(child);
+            scheduler::add_thread(child);
             SyscallResult::Value(child_pid.into())
         }
         Syscall::Exit => {
-            let res = ProcessError::n(args.1).map(Err).unwrap_or(Ok(()));
+            let res = ProcessError::from_code(args.1).map(Err).unwrap_or(Ok(()));
             scheduler::with_process(pid, |p| p.exit(res))?;
             SyscallResult::Success
         }
         Syscall::WaitPid => {
             let target_pid = Pid::try_from(args.0)?;
+            let flags = WaitFlags::try_from(args.1)?;
+            let condition = if flags.contains(WaitFlags::STOPPED) {
+                BlockCondition::OnChangeOfPid(target_pid)
+            } else {
+                BlockCondition::OnDeathOfPid(target_pid)
+            };
 
-            // Return immediately if process is stopped already
-            if scheduler::with_process(target_pid, |_| Ok(())).is_err() {
-                return Ok(SyscallResult::Success);
+            // Returns immediately if process is gone or stopped already. Otherwise the status is
+            // filled in when it wakes up
+            match scheduler::block_until_death(pid, tid, condition)? {
+                Some(status) => SyscallResult::Value(status.into()),
+                None => SyscallResult::Success,
             }
-
-            scheduler::with_process(pid, |p| {
-                p.block(BlockCondition::OnDeathOfPid(target_pid));
-                Ok(())
-            })?;
-            SyscallResult::Success
         }
         Syscall::Sleep => {
             let duration = Duration::new(args.0.try_into()?, args.1.try_into()?);
-            scheduler::with_process(pid, |p| {
-                p.block(BlockCondition::Until(Instant::now() + duration));
+            scheduler::with_thread(tid, |t| {
+                t.block(BlockCondition::Until(Instant::now() + duration));
                 Ok(())
             })?;
             SyscallResult::Success
@@ -138,17 +159,321 @@ fn syscall_inner(frame: &mut TrapFrame, call: usize, args: Args) -> KernelResult
             unimplemented!();
         }
         Syscall::Open => {
-            let _path_ptr = args.0;
-            let _flags = args.1;
-            todo!()
+            let path = string_arg(pid, frame, args.0, args.1)?;
+            let flags = OpenFlags::try_from(args.2)?;
+            let file = filesystem::root()?.open_blocking(path, flags)?;
+            let fd = scheduler::with_process(pid, |p| p.add_file(file))?;
+            SyscallResult::Value(fd.into())
+        }
+        Syscall::Read => {
+            let fd = FileDescriptor::try_from(args.0)?;
+            fault_in(pid, args.1, args.2, true)?;
+            let file = scheduler::with_process(pid, |p| p.file(fd))?;
+            let table = frame.root_page_table();
+            match mmu::with_user_buffer(table, args.1, args.2, true, |slice| {
+                file.read_blocking(slice)
+            }) {
+                Err(KernelError::WouldBlock) => wait_for(tid, &file, false)?,
+                bytes_read => SyscallResult::Value(bytes_read?),
+            }
+        }
+        Syscall::Close => {
+            let fd = FileDescriptor::try_from(args.0)?;
+            // Dropped here, with the process unlocked
+            let _file = scheduler::with_process(pid, |p| p.close_file(fd))?;
+            SyscallResult::Success
+        }
+        Syscall::Seek => {
+            let fd = FileDescriptor::try_from(args.0)?;
+            let offset = args.1 as isize;
+            let whence = Whence::n(args.2).ok_or(KernelError::InvalidArguments)?;
+            let file = scheduler::with_process(pid, |p| p.file(fd))?;
+            SyscallResult::Value(file.seek_blocking(offset, whence)?)
+        }
+        Syscall::Write => {
+            let fd = FileDescriptor::try_from(args.0)?;
+            fault_in(pid, args.1, args.2, false)?;
+            let file = scheduler::with_process(pid, |p| p.file(fd))?;
+            let table = frame.root_page_table();
+            match mmu::with_user_buffer(table, args.1, args.2, false, |slice| {
+                file.write_blocking(slice)
+            }) {
+                Err(KernelError::WouldBlock) => wait_for(tid, &file, true)?,
+                bytes_written => SyscallResult::Value(bytes_written?),
+            }
+        }
+        Syscall::Truncate => {
+            let fd = FileDescriptor::try_from(args.0)?;
+            let file = scheduler::with_process(pid, |p| p.file(fd))?;
+            file.truncate_blocking(args.1)?;
+            SyscallResult::Success
+        }
+        Syscall::MakeDirectory => {
+            let path = string_arg(pid, frame, args.0, args.1)?;
+            filesystem::root()?.mkdir_blocking(path)?;
+            SyscallResult::Success
+        }
+        Syscall::Remove => {
+            let path = string_arg(pid, frame, args.0, args.1)?;
+            filesystem::root()?.remove_blocking(path)?;
+            SyscallResult::Success
+        }
+        Syscall::Exec => {
+            let (argv_len, envp_len) = (args.3, args.5);
+            if argv_len.saturating_add(envp_len) > PAGE_SIZE {
+                return Err(KernelError::ArgumentListTooLong);
+            }
+
+            let path = string_arg(pid, frame, args.0, args.1)?;
+            fault_in(pid, args.2, argv_len, false)?;
+            fault_in(pid, args.4, envp_len, false)?;
+            let table = frame.root_page_table();
+
+            // Stage arguments in a page, since they won't fit on the kernel heap
+            let mut buffer: PageAllocation<[Page<PAGE_SIZE>]> = mmu::zalloc_slice(1)?;
+            let (argv, envp) = buffer.as_mut()[0].0.split_at_mut(argv_len);
+            let envp = &mut envp[..envp_len];
+            mmu::copy_from_user(table, args.2, argv)?;
+            mmu::copy_from_user(table, args.4, envp)?;
+
+            let file = ElfFile::read(path)?;
+            scheduler::with_thread(tid, |t| t.exec(file.bytes(), argv, envp))?;
+            SyscallResult::Success
+        }
+        Syscall::SetProcessGroup => {
+            let target_pid = Pid::maybe_from_usize(args.0)?.unwrap_or(pid);
+            let pgid = Pid::maybe_from_usize(args.1)?.unwrap_or(target_pid);
+            scheduler::with_process(target_pid, |p| {
+                // Only this process and its children
+                if p.pid != pid && p.ppid != Some(pid) {
+                    return Err(KernelError::NotPermitted);
+                }
+                p.pgid = pgid;
+                Ok(())
+            })?;
+            SyscallResult::Success
+        }
+        Syscall::GetProcessGroup => {
+            let target_pid = Pid::maybe_from_usize(args.0)?.unwrap_or(pid);
+            let pgid = scheduler::with_process(target_pid, |p| Ok(p.pgid))?;
+            SyscallResult::Value(pgid.into())
+        }
+        Syscall::SetForegroundGroup => {
+            let pgid = Pid::maybe_from_usize(args.0)?;
+            // Only the foreground group may hand the terminal off. The parent of the group's
+            // leader may take it back too, like a shell does once a job stops or exits
+            if let Some(foreground) = tty::foreground_group() {
+                if scheduler::with_process(pid, |p| Ok(p.pgid))? != foreground {
+                    match scheduler::with_process(foreground, |p| Ok(p.ppid)) {
+                        Ok(ppid) if ppid != Some(pid) => return Err(KernelError::NotPermitted),
+                        Ok(_) | Err(KernelError::ProcessNotFound(_)) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+            tty::set_foreground_group(pgid)?;
+            SyscallResult::Success
+        }
+        Syscall::GetTtyMode => SyscallResult::Value(tty::mode().into()),
+        Syscall::SetTtyMode => {
+            tty::set_mode(TtyMode::try_from(args.0)?)?;
+            SyscallResult::Success
+        }
+        Syscall::Kill => {
+            let target_pid = Pid::try_from(args.0)?;
+            let signal = Signal::n(args.1).ok_or(KernelError::InvalidArguments)?;
+            scheduler::signal(target_pid, signal)?;
+            SyscallResult::Success
+        }
+        Syscall::SetSignalHandler => {
+            let signal = Signal::n(args.0).ok_or(KernelError::InvalidArguments)?;
+            let action = match args.1 {
+                SIGNAL_DEFAULT => SignalAction::Default,
+                SIGNAL_IGNORE => SignalAction::Ignore,
+                handler => SignalAction::Handler {
+                    entry: args.2,
+                    handler,
+                },
+            };
+            scheduler::with_process(pid, |p| p.set_signal_action(signal, action))?;
+            SyscallResult::Success
+        }
+        Syscall::SigReturn => {
+            let mut stale = StaleMappings::default();
+            scheduler::with_thread(tid, |t| t.signal_return(args.0, &mut stale))?;
+            SyscallResult::Success
+        }
+        Syscall::SetPriority => {
+            let target_tid = Pid::maybe_from_usize(args.0)?.unwrap_or(tid);
+            let nice = Nice::try_from(args.1)?;
+            scheduler::with_thread(target_tid, |t| {
+                // Only threads of this process and its children
+                if t.pid != pid && t.ppid() != Some(pid) {
+                    return Err(KernelError::NotPermitted);
+                }
+                t.nice = nice;
+                Ok(())
+            })?;
+            SyscallResult::Success
+        }
+        Syscall::GetPriority => {
+            let target_tid = Pid::maybe_from_usize(args.0)?.unwrap_or(tid);
+            let nice = scheduler::with_thread(target_tid, |t| Ok(t.nice))?;
+            SyscallResult::Value(nice.into())
+        }
+        Syscall::SpawnThread => {
+            let thread = scheduler::with_thread(tid, |t| t.spawn(args.0, args.1, args.2))?;
+            let thread_tid = thread.tid;
+            scheduler::add_thread(thread);
+            SyscallResult::Value(thread_tid.into())
+        }
+        Syscall::JoinThread => {
+            let target_tid = Pid::try_from(args.0)?;
+            if target_tid == tid {
+                return Err(KernelError::InvalidArguments);
+            }
+            // Returns immediately if thread is gone already
+            let condition = BlockCondition::OnDeathOfThread(target_tid);
+            match scheduler::block_until_death(pid, tid, condition)? {
+                Some(status) => SyscallResult::Value(status.into()),
+                None => SyscallResult::Success,
+            }
+        }
+        Syscall::ExitThread => {
+            let res = ProcessError::from_code(args.0).map(Err).unwrap_or(Ok(()));
+            scheduler::with_thread(tid, |t| t.exit(res))?;
+            SyscallResult::Success
+        }
+        Syscall::FutexWait => {
+            let expected = u32::try_from(args.1)?;
+            let timeout = match args.2 {
+                NO_TIMEOUT => None,
+                // Nanoseconds past a whole second would carry into the seconds
+                _ if args.3 >= 1_000_000_000 => return Err(KernelError::InvalidArguments),
+                secs => Some(Duration::new(secs.try_into()?, args.3.try_into()?)),
+            };
+            scheduler::futex_wait(pid, tid, args.0, expected, timeout)?;
+            SyscallResult::Success
+        }
+        Syscall::FutexWake => {
+            let woken = scheduler::futex_wake(pid, args.0, args.1)?;
+            SyscallResult::Value(woken)
+        }
+        Syscall::Pipe => {
+            fault_in(pid, args.0, 2 * mem::size_of::<usize>(), true)?;
+            let (read_end, write_end) = pipe::new();
+            // Both ends are held onto until the end, so closing them again on failure can't drop
+            // the last reference to either with the process locked
+            let fds = scheduler::with_process(pid, |p| {
+                let read_fd = p.add_file(read_end.clone())?;
+                match p.add_file(write_end.clone()) {
+                    Ok(write_fd) => Ok([read_fd, write_fd]),
+                    Err(err) => {
+                        p.close_file(read_fd)?;
+                        Err(err)
+                    }
+                }
+            })?;
+
+            let mut bytes = [0; 2 * mem::size_of::<usize>()];
+            for (chunk, fd) in bytes.chunks_exact_mut(mem::size_of::<usize>()).zip(fds) {
+                chunk.copy_from_slice(&usize::from(fd).to_ne_bytes());
+            }
+            if let Err(err) = mmu::copy_to_user(frame.root_page_table(), args.0, &bytes) {
+                // Userspace never learns about them, so they'd stay open for good
+                scheduler::with_process(pid, |p| {
+                    for fd in fds {
+                        p.close_file(fd)?;
+                    }
+                    Ok(())
+                })?;
+                return Err(err);
+            }
+            SyscallResult::Success
+        }
+        Syscall::Dup2 => {
+            let old_fd = FileDescriptor::try_from(args.0)?;
+            let new_fd = FileDescriptor::try_from(args.1)?;
+            // Whatever was open at `new_fd` is dropped here, with the process unlocked
+            let _replaced = scheduler::with_process(pid, |p| p.duplicate_file(old_fd, new_fd))?;
+            SyscallResult::Value(new_fd.into())
+        }
+        Syscall::ReadDirectory => {
+            let fd = FileDescriptor::try_from(args.0)?;
+            let file = scheduler::with_process(pid, |p| p.file(fd))?;
+            // Encoded here, since entries would be cut off at page boundaries in user memory.
+            // A page at a time, since short reads are allowed anyway
+            let mut entries = vec![0; args.2.min(PAGE_SIZE)];
+            let bytes_read = file.read_dir_blocking(&mut entries)?;
+
+            fault_in(pid, args.1, bytes_read, true)?;
+            mmu::copy_to_user(frame.root_page_table(), args.1, &entries[..bytes_read])?;
+            SyscallResult::Value(bytes_read)
+        }
+        Syscall::Uptime => SyscallResult::Value(timer::uptime().as_nanos().try_into()?),
+        Syscall::ListProcesses => {
+            let first = u16::try_from(args.0)?;
+            // Encoded here, since processes are locked while they're described
+            let mut infos = Vec::new();
+            let mut full = false;
+            scheduler::list_processes(first, |info| {
+                let start = infos.len();
+                if start + info.encoded_len() > args.2 {
+                    full = true;
+                    return false;
+                }
+                infos.resize(start + info.encoded_len(), 0);
+                info.encode(&mut infos[start..]);
+                true
+            })?;
+            // Not even one fits
+            if full && infos.is_empty() {
+                return Err(KernelError::InvalidArguments);
+            }
+
+            fault_in(pid, args.1, infos.len(), true)?;
+            mmu::copy_to_user(frame.root_page_table(), args.1, &infos)?;
+            SyscallResult::Value(infos.len())
+        }
+        Syscall::GetPid => SyscallResult::Value(pid.into()),
+        Syscall::Mmap => {
+            let prot = Protection::try_from(args.2)?;
+            let flags = MapFlags::try_from(args.3)?;
+            let addr = scheduler::with_process(pid, |p| p.map_memory(args.0, args.1, prot, flags))?;
+            SyscallResult::Value(addr)
+        }
+        Syscall::Munmap => {
+            // Dropped once the process is unlocked, freeing the pages after every hart flushes
+            let mut stale = StaleMappings::default();
+            scheduler::with_process(pid, |p| p.unmap_memory(args.0, args.1, &mut stale))?;
+            SyscallResult::Success
+        }
+        Syscall::CreateSharedMemory => {
+            let name = string_arg(pid, frame, args.0, args.1)?;
+            shared_memory::create(name, args.2)?;
+            SyscallResult::Success
+        }
+        Syscall::MapSharedMemory => {
+            let name = string_arg(pid, frame, args.0, args.1)?;
+            let prot = Protection::try_from(args.3)?;
+            let flags = MapFlags::try_from(args.4)?;
+            let segment = shared_memory::get(&name)?;
+            let mut stale = StaleMappings::default();
+            let addr = scheduler::with_process(pid, |p| {
+                p.map_shared_memory(segment, args.2, prot, flags, &mut stale)
+            })?;
+            SyscallResult::Value(addr)
+        }
+        Syscall::RemoveSharedMemory => {
+            let name = string_arg(pid, frame, args.0, args.1)?;
+            shared_memory::remove(&name)?;
+            SyscallResult::Success
         }
         // Development test aid
         // This does whatever I want it to do
         Syscall::Test => {
-            if let Some(device) = &*DRIVERS.block.read() {
-                use crate::filesystem::FileSystem;
-                let mut fs = crate::filesystem::fat32::Fat32FileSystem::new(device.clone())?;
-                let items = fs.list_blocking("home")?;
+            if DRIVERS.block.read().is_some() {
+                let items = filesystem::root()?.list_blocking("home")?;
                 println!("{items:?}")
             }
 
@@ -158,16 +483,53 @@ fn syscall_inner(frame: &mut TrapFrame, call: usize, args: Args) -> KernelResult
     Ok(rv)
 }
 
+// Make sure a user buffer is mapped before the kernel touches it
+fn fault_in(pid: Pid, start: usize, len: usize, write: bool) -> KernelResult<()> {
+    let mut stale = StaleMappings::default();
+    scheduler::with_process(pid, |p| p.fault_in(start, len, write, &mut stale))?;
+    Ok(())
+}
+
+// Block until `file` is ready to read from or write to, then run the syscall again
+fn wait_for(tid: Pid, file: &FileRef, write: bool) -> KernelResult<SyscallResult> {
+    scheduler::with_thread(tid, |t| {
+        // Checked with the thread's hart locked, so a wakeup can't slip in before it blocks
+        match file.wait_condition(write) {
+            Some(condition) => t.block(condition),
+            None => t.retry_syscall(),
+        }
+        Ok(SyscallResult::Retry)
+    })
+}
+
+// A path or name passed to a syscall, faulted in and copied out of user memory
+fn string_arg(pid: Pid, frame: &TrapFrame, start: usize, len: usize) -> KernelResult<String> {
+    if len > MAX_PATH_LEN {
+        return Err(KernelError::InvalidArguments);
+    }
+    fault_in(pid, start, len, false)?;
+    string_from_user(frame.root_page_table(), start, len)
+}
+
+// Copy a UTF-8 string out of user memory
+fn string_from_user(table: &Sv39PageTable, start: usize, len: usize) -> KernelResult<String> {
+    let mut bytes = vec![0; len];
+    mmu::copy_from_user(table, start, &mut bytes)?;
+    Ok(String::from(str::from_utf8(&bytes)?))
+}
+
 #[derive(Copy, Clone, Debug)]
 enum SyscallResult {
     Success,
     Value(usize),
+    // The thread runs the syscall again, once whatever it's waiting on is ready
+    Retry,
 }
 
 impl From<SyscallResult> for usize {
     fn from(res: SyscallResult) -> Self {
         match res {
-            SyscallResult::Success => 0,
+            SyscallResult::Success | SyscallResult::Retry => 0,
             SyscallResult::Value(val) => val,
         }
     }
@@ -2,7 +2,6 @@
 //
 // Allows us to make sure page tables and whatnot get set up in CI
 use crate::{frame, mmu, process::Process, scheduler, userspace, KernelResult};
-use core::ptr;
 use qemu_exit::QEMUExit;
 
 // Address of qemu test device
@@ -16,16 +15,13 @@ pub fn test_and_exit() {
 
 fn test_kernel() -> KernelResult<()> {
     crate::util::test();
+    crate::allocator::test();
     Ok(())
 }
 
 fn test_userspace() -> KernelResult<()> {
-    let size = userspace::gary::BIN.len();
-    let address = ptr::addr_of!(userspace::gary::BIN) as *const u8;
-    let entry_offset = userspace::gary::ENTRY_OFFSET;
-
-    let process = unsafe { Process::new(address, size, entry_offset)? };
-    scheduler::start_with(process);
+    let thread = Process::new(userspace::gary::ELF, b"gary\0")?;
+    scheduler::start_with(thread);
     unreachable!("Should have exited from userspace");
 }
 
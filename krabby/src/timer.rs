@@ -1,13 +1,40 @@
-use crate::{drivers::DRIVERS, prelude::*};
+use crate::{cpu::MAX_HARTS, drivers::DRIVERS, prelude::*};
 use core::{
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
     time::Duration,
 };
+use fdt::Fdt;
+use riscv::register::time;
 use spin::RwLock;
 
-static INSTANT_IN_NS: AtomicU64 = AtomicU64::new(0);
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+// Rate at which the `time` CSR counts up
+static TIMEBASE_FREQUENCY: AtomicU64 = AtomicU64::new(0);
 static DURATION: RwLock<Duration> = RwLock::new(Duration::new(0, 0));
 
+// Scratch space for the machine mode trap handler. Layout is shared with entry.S
+#[repr(C)]
+#[allow(dead_code)] // Mostly used from assembly
+struct MachineScratch {
+    // Registers saved while the handler runs
+    saved: [AtomicUsize; 2],
+    // Set when the hart's alarm goes off
+    alarm: AtomicUsize,
+    // Pads this out to a power of two
+    _reserved: AtomicUsize,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SCRATCH: MachineScratch = MachineScratch {
+    saved: [AtomicUsize::new(0), AtomicUsize::new(0)],
+    alarm: AtomicUsize::new(0),
+    _reserved: AtomicUsize::new(0),
+};
+
+#[export_name = "mtrap_scratch"]
+static MACHINE_SCRATCH: [MachineScratch; MAX_HARTS] = [EMPTY_SCRATCH; MAX_HARTS];
+
 /// Some opaque monotonic time point - Similar to `Instant` in `std`
 // Keeps track in nanos
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -15,8 +42,26 @@ pub struct Instant(u64);
 
 impl Instant {
     /// Get the current instant
+    ///
+    /// Time stands still until [init] is called
     pub fn now() -> Self {
-        Instant(INSTANT_IN_NS.load(Ordering::Relaxed))
+        let freq = TIMEBASE_FREQUENCY.load(Ordering::Relaxed);
+        if freq == 0 {
+            return Instant(0);
+        }
+        let ticks = time::read() as u128;
+        Instant((ticks * NANOS_PER_SECOND / u128::from(freq)) as u64)
+    }
+
+    /// Time elapsed from `earlier` to this instant, or zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    /// The instant `dur` after this one, or `None` if that's too far off to keep track of
+    pub fn checked_add(&self, dur: Duration) -> Option<Self> {
+        let nanos = u64::try_from(dur.as_nanos()).ok()?;
+        self.0.checked_add(nanos).map(Instant)
     }
 }
 
@@ -27,7 +72,27 @@ impl core::ops::Add<Duration> for Instant {
     }
 }
 
+/// Time since the hart clocks started counting, which is about when the machine booted
+pub fn uptime() -> Duration {
+    Instant::now().duration_since(Instant(0))
+}
+
+/// Get the timebase frequency from the device tree
+pub fn init(fdt: &Fdt) -> KernelResult<()> {
+    let freq = fdt
+        .find_node("/cpus")
+        .ok_or(KernelError::MissingProperty("cpus"))?
+        .property("timebase-frequency")
+        .ok_or(KernelError::MissingProperty("timebase-frequency"))?
+        .as_usize()
+        .ok_or(KernelError::Generic("Invalid timebase frequency"))?;
+    TIMEBASE_FREQUENCY.store(freq.try_into()?, Ordering::Relaxed);
+    Ok(())
+}
+
 /// Set the timer period
+///
+/// Every hart shares the same period, but each has its own alarm
 pub fn set_timer_period(hart: HartId, duration: Duration) -> KernelResult<()> {
     // Opening this lock should prevent race condition on multiple calls to set_timer() We don't
     // want duration_cache and the actual duration in the timer driver to get out-of-sync
@@ -43,10 +108,22 @@ pub fn set_timer_period(hart: HartId, duration: Duration) -> KernelResult<()> {
     }
 }
 
-/// Increment the current Instant
-pub fn tick() -> KernelResult<()> {
+/// Handle a supervisor software interrupt
+///
+/// These are raised both by a hart's alarm and by IPIs from other harts. If it was the alarm,
+/// set it again
+pub fn on_software_interrupt(hart: HartId) -> KernelResult<()> {
+    let scratch = &MACHINE_SCRATCH[usize::from(hart)];
+    if scratch.alarm.swap(0, Ordering::SeqCst) == 0 {
+        return Ok(());
+    }
+
     let duration = DURATION.read();
-    let duration = duration.as_nanos().try_into()?;
-    INSTANT_IN_NS.fetch_add(duration, Ordering::Relaxed);
-    Ok(())
+    let mut timer = DRIVERS.timer.lock();
+    if let Some(timer) = &mut *timer {
+        timer.set_alarm(hart, *duration);
+        Ok(())
+    } else {
+        Err(KernelError::DriverUninitialized)
+    }
 }
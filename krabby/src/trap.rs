@@ -2,13 +2,13 @@
 use crate::{
     frame::{self, TrapFrame},
     interrupts,
-    mmu::PAGE_SIZE,
+    mmu::{self, Access, StaleMappings, PAGE_SIZE},
     prelude::*,
-    scheduler,
+    scheduler, smp,
     syscalls::syscall_handler,
     timer,
 };
-use core::{ffi::c_void, ptr};
+use krabby_abi::ProcessError;
 use owo_colors::OwoColorize;
 use riscv::register::{
     self,
@@ -30,20 +30,21 @@ extern "C" fn exception_handler(
     let trap_frame = unsafe { trap_frame.as_mut().unwrap() };
     let scause = register::scause::read();
     let mut pc = register::sepc::read();
+    let hart = HartId::current();
 
     frame::switch_to_kernel_frame();
 
-    check_for_stack_overflow();
+    check_for_stack_overflow(hart);
 
-    // Exceptions are synchronous so the PC needs to move up
-    if scause.is_exception() {
+    // Syscalls return to the next instruction, but faults retry the faulting one
+    if matches!(scause.cause(), Trap::Exception(Exception::UserEnvCall)) {
         pc += 4;
     }
 
     // set PC
-    if let Some(pid) = trap_frame.pid {
-        let _ = scheduler::with_process(pid, |p| {
-            p.pc = pc;
+    if let Some(tid) = trap_frame.tid {
+        let _ = scheduler::with_thread(tid, |t| {
+            t.pc = pc;
             Ok(())
         });
     }
@@ -52,25 +53,62 @@ extern "C" fn exception_handler(
         Trap::Exception(exception) => match exception {
             Exception::UserEnvCall => {
                 let rv = syscall_handler(trap_frame, a7, (a0, a1, a2, a3, a4, a5, a6));
-                pc = scheduler::switch_processes(HartId::zero());
+                pc = scheduler::switch_processes(hart);
+                rv
+            }
+            Exception::LoadPageFault
+            | Exception::StorePageFault
+            | Exception::InstructionPageFault
+                if trap_frame.pid.is_some() =>
+            {
+                let pid = trap_frame.pid.unwrap();
+                let stval = register::stval::read();
+                let access = match exception {
+                    Exception::StorePageFault => Access::Write,
+                    Exception::InstructionPageFault => Access::Execute,
+                    _ => Access::Read,
+                };
+                let mut stale = StaleMappings::default();
+                let handled = scheduler::with_process(pid, |p| {
+                    p.handle_page_fault(stval, access, &mut stale)
+                })
+                .unwrap_or_else(|err| {
+                    println!("<page fault error: {err}>");
+                    false
+                });
+
+                // Take down only the offending process
+                let rv = if handled {
+                    Ok(())
+                } else {
+                    println!("[kernel: segmentation fault in process {pid} at 0x{stval:08x}]");
+                    scheduler::with_process(pid, |p| p.exit(Err(ProcessError::SegmentationFault)))
+                };
+                pc = scheduler::switch_processes(hart);
+                rv
+            }
+            _ if trap_frame.pid.is_some() => {
+                let rv = kill_faulting_process(trap_frame.pid.unwrap());
+                pc = scheduler::switch_processes(hart);
                 rv
             }
             _ => unhandled_exception(trap_frame),
         },
         Trap::Interrupt(interrupt) => match interrupt {
             Interrupt::SupervisorSoft => {
-                if timer::tick().is_err() {
-                    println!("[kernel: timer tick failed]");
+                smp::on_flush_request();
+                if timer::on_software_interrupt(hart).is_err() {
+                    println!("[kernel: failed to set timer alarm]");
                 };
                 unsafe {
                     register::sip::clear_ssoft();
                 }
-                pc = scheduler::switch_processes(HartId::zero());
+                pc = scheduler::switch_processes(hart);
                 Ok(())
             }
             Interrupt::SupervisorExternal => {
                 let res = interrupts::run_next_handler();
-                pc = scheduler::switch_processes(HartId::zero());
+                pc = scheduler::switch_processes(hart);
                 res
             }
             _ => {
@@ -86,6 +124,21 @@ extern "C" fn exception_handler(
     register::sepc::write(pc);
 }
 
+/// Turn an exception from userspace into the death of the process that raised it
+fn kill_faulting_process(pid: Pid) -> KernelResult<()> {
+    let scause = register::scause::read();
+    let stval = register::stval::read();
+    let pc = register::sepc::read();
+
+    println!(
+        "[kernel: process {pid} killed by {:?} at pc 0x{pc:08x}, stval: 0x{stval:08x}]",
+        scause.cause()
+    );
+
+    // The cause only makes it to the log, since exit codes have no room for it
+    scheduler::with_process(pid, |p| p.exit(Err(ProcessError::Exception)))
+}
+
 fn unhandled_exception(trap_frame: &TrapFrame) -> ! {
     let scause = register::scause::read();
     let stval = register::stval::read();
@@ -104,16 +157,12 @@ fn unhandled_exception(trap_frame: &TrapFrame) -> ! {
     );
 }
 
-fn check_for_stack_overflow() {
+fn check_for_stack_overflow(hart: HartId) {
     let stval = register::stval::read();
-    let guard = unsafe { ptr::from_ref(&stack_guard) as usize };
+    let (guard, _) = mmu::kernel_stack(hart);
 
     if stval >= guard && stval < guard + PAGE_SIZE {
         println!("[STACK_OVERFLOW]");
         panic!("Stack overflow");
     }
 }
-
-extern "C" {
-    static stack_guard: c_void;
-}
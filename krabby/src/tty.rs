@@ -0,0 +1,188 @@
+//! Terminal line discipline, sitting between the UART and the processes reading from it
+//!
+//! Only processes in the foreground process group may read. If no foreground group has been
+//! set, anybody can
+use crate::{drivers::DRIVERS, prelude::*, scheduler};
+use alloc::collections::VecDeque;
+use krabby_abi::{signal::Signal, tty::TtyMode};
+use spin::Mutex;
+
+const CTRL_C: char = '\x03';
+const CTRL_Z: char = '\x1a';
+const BACKSPACE: char = '\x08';
+const DELETE: char = '\x7f';
+
+static TTY: Mutex<Tty> = Mutex::new(Tty {
+    mode: TtyMode::DEFAULT,
+    foreground: None,
+    line: String::new(),
+    input: VecDeque::new(),
+});
+
+#[derive(Debug)]
+struct Tty {
+    mode: TtyMode,
+    // Process group allowed to read
+    foreground: Option<Pid>,
+    // Line being edited in canonical mode
+    line: String,
+    // Input ready to be read
+    input: VecDeque<char>,
+}
+
+impl Tty {
+    // Handle a character from the UART
+    //
+    // Returns the signal to send the foreground group, if any
+    fn receive(&mut self, ch: char) -> Option<Signal> {
+        let echo = self.mode.contains(TtyMode::ECHO);
+
+        if !self.mode.contains(TtyMode::CANONICAL) {
+            if echo {
+                print!("{ch}");
+            }
+            self.input.push_back(ch);
+            return None;
+        }
+
+        match ch {
+            CTRL_C => {
+                self.line.clear();
+                if echo {
+                    println!("^C");
+                }
+                return Some(Signal::Interrupt);
+            }
+            CTRL_Z => {
+                if echo {
+                    println!("^Z");
+                }
+                return Some(Signal::TerminalStop);
+            }
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() && echo {
+                    print!("{BACKSPACE} {BACKSPACE}");
+                }
+            }
+            '\r' | '\n' => {
+                if echo {
+                    println!();
+                }
+                self.input.extend(self.line.drain(..));
+                self.input.push_back('\n');
+            }
+            ch => {
+                if echo {
+                    print!("{ch}");
+                }
+                self.line.push(ch);
+            }
+        }
+        None
+    }
+
+    fn may_read(&self, pgid: Pid) -> bool {
+        self.foreground.is_none() || self.foreground == Some(pgid)
+    }
+}
+
+/// Feed input from the UART to the terminal
+pub fn receive(chars: &[char]) -> KernelResult<()> {
+    let mut signals = Vec::new();
+    {
+        let mut tty = TTY.lock();
+        signals.extend(chars.iter().filter_map(|ch| tty.receive(*ch)));
+    }
+
+    for signal in signals {
+        signal_foreground_group(signal)?;
+    }
+    wake_readers()
+}
+
+/// Take the next character of input for a process in process group `pgid`
+///
+/// Returns `None` if there's nothing for the process to read yet
+pub fn read(pgid: Pid) -> Option<char> {
+    let mut tty = TTY.lock();
+    if tty.may_read(pgid) {
+        tty.input.pop_front()
+    } else {
+        None
+    }
+}
+
+/// Interrupt that signals new input
+pub fn interrupt_id() -> KernelResult<InterruptId> {
+    let uart = DRIVERS.uart.read();
+    let Some(uart) = &*uart else {
+        return Err(KernelError::DriverUninitialized);
+    };
+    let uart = uart.lock();
+    uart.info
+        .interrupts
+        .first()
+        .copied()
+        .ok_or(KernelError::InterruptUnavailable)
+}
+
+/// Get the terminal mode
+pub fn mode() -> TtyMode {
+    TTY.lock().mode
+}
+
+/// Set the terminal mode
+///
+/// Leaving canonical mode hands over whatever line was being edited
+pub fn set_mode(mode: TtyMode) -> KernelResult<()> {
+    {
+        let mut tty = TTY.lock();
+        if !mode.contains(TtyMode::CANONICAL) {
+            let line = core::mem::take(&mut tty.line);
+            tty.input.extend(line.chars());
+        }
+        tty.mode = mode;
+    }
+    wake_readers()
+}
+
+/// Process group in control of the terminal, if any
+pub fn foreground_group() -> Option<Pid> {
+    TTY.lock().foreground
+}
+
+/// Give process group `pgid` control of the terminal, or let anybody read if `None`
+pub fn set_foreground_group(pgid: Option<Pid>) -> KernelResult<()> {
+    TTY.lock().foreground = pgid;
+    wake_readers()
+}
+
+// Hand out input to blocked readers that are allowed to have it
+fn wake_readers() -> KernelResult<()> {
+    let Ok(int_id) = interrupt_id() else {
+        // No UART, so nobody can be waiting on it
+        return Ok(());
+    };
+
+    loop {
+        let woke = scheduler::on_interrupt(int_id, |thread| {
+            let Some(ch) = read(thread.pgid()) else {
+                return Ok(false);
+            };
+            thread.frame.as_mut().set_return_value(&Ok(ch as usize));
+            thread.unblock();
+            Ok(true)
+        })?;
+        if !woke {
+            return Ok(());
+        }
+    }
+}
+
+// Ctrl-C interrupts every process in the foreground group, and Ctrl-Z stops them
+fn signal_foreground_group(signal: Signal) -> KernelResult<()> {
+    let Some(pgid) = TTY.lock().foreground else {
+        return Ok(());
+    };
+    scheduler::signal_group(pgid, signal)
+}
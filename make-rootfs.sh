@@ -0,0 +1,53 @@
+#!/usr/bin/env bash
+# Build the userspace programs and install them into the FAT32 root filesystem
+# shellcheck disable=SC2086
+set -e
+
+main() {
+    local -r USAGE="Usage: $(basename "${0}") [--release] [IMAGE]"
+    local -r HELP="Install KabutOS programs into a FAT32 image, creating it if needed
+
+$USAGE
+
+Help:
+    --release   Install release builds instead of debug builds
+    IMAGE       Image to install into. Defaults to rootfs.img"
+
+    local profile=debug
+    while true; do
+        case "$1" in
+            --release) profile=release; shift ;;
+            -h | --help ) echo "$HELP"; return 0 ;;
+            -- ) shift; break ;;
+            -* ) echo -e "Unrecognized option: $1\n$USAGE" >&2; return 1 ;;
+            * ) break ;;
+        esac
+    done
+
+    local -r root="$(cd "$(dirname "${0}")" && pwd)"
+    local -r image="${1:-${root}/rootfs.img}"
+    local -r programs="ls cat echo env mkdir rm cp mv ps top kill sleep hexdump uptime"
+
+    # Build
+    local cargo_args=""
+    if [ "$profile" = release ]; then
+        cargo_args="--release"
+    fi
+    (cd "${root}/userspace/coreutils" && cargo build ${cargo_args})
+
+    # FAT32 needs at least 65525 clusters, which 64MiB of single sector clusters is plenty for
+    if [ ! -f "$image" ]; then
+        truncate -s 64M "$image"
+        mkfs.fat -F 32 -s 1 -n KABUTOS "$image" > /dev/null
+    fi
+
+    # Install, replacing anything already there
+    local -r build_dir="${root}/userspace/target/riscv64gc-unknown-none-elf/${profile}"
+    mmd -i "$image" ::/bin 2> /dev/null || true
+    for program in $programs; do
+        mcopy -o -i "$image" "${build_dir}/${program}" "::/bin/${program}"
+    done
+    echo "Installed ${programs} into ${image}"
+}
+
+main "${@}"
@@ -119,12 +119,13 @@ impl<const PAGE_SIZE: usize> RecordsPage<PAGE_SIZE> {
         num_deallocated
     }
 
+    // Find and take `num_pages` contiguous free pages, or return `None` if there's no room
     fn allocate_inner(
         &mut self,
         heap_start: *const c_void,
         heap_size: usize,
         num_pages: usize,
-    ) -> *mut () {
+    ) -> Option<*mut ()> {
         assert_eq!(
             heap_start as usize & (PAGE_SIZE - 1),
             0,
@@ -136,7 +137,7 @@ impl<const PAGE_SIZE: usize> RecordsPage<PAGE_SIZE> {
 
         for record_index in 0..Self::NUM_RECORDS_IN_PAGE {
             if record_index >= heap_size {
-                panic!("Heap overflow!");
+                return None;
             }
             let record = self.get_record(record_index);
 
@@ -163,7 +164,7 @@ impl<const PAGE_SIZE: usize> RecordsPage<PAGE_SIZE> {
                 self.set_last(page_start + count - 1, true);
 
                 // And return start of page
-                return ((heap_start as usize) + page_start * PAGE_SIZE) as *mut ();
+                return Some(((heap_start as usize) + page_start * PAGE_SIZE) as *mut ());
             }
 
             // Nevermind, we can't use this
@@ -172,7 +173,7 @@ impl<const PAGE_SIZE: usize> RecordsPage<PAGE_SIZE> {
             }
         }
 
-        panic!("Heap overflow(book-keeping overrun)!");
+        None
     }
 
     /// Allocate some pages
@@ -190,11 +191,20 @@ impl<const PAGE_SIZE: usize> RecordsPage<PAGE_SIZE> {
     /// # Returns
     /// The address of the first allocated page, and the number of pages
     pub fn allocate<T>(&mut self, heap_start: *const c_void, heap_size: usize) -> (*mut T, usize) {
+        self.try_allocate(heap_start, heap_size)
+            .expect("Heap overflow!")
+    }
+
+    /// Same as [RecordsPage::allocate], but returns `None` instead of panicking if there's no
+    /// room
+    pub fn try_allocate<T>(
+        &mut self,
+        heap_start: *const c_void,
+        heap_size: usize,
+    ) -> Option<(*mut T, usize)> {
         let num_pages = align_up::<PAGE_SIZE>(mem::size_of::<T>()) / PAGE_SIZE;
-        (
-            self.allocate_inner(heap_start, heap_size, num_pages) as *mut T,
-            num_pages,
-        )
+        let address = self.allocate_inner(heap_start, heap_size, num_pages)?;
+        Some((address as *mut T, num_pages))
     }
 
     /// Same as [RecordsPage::allocate], but for slices. This is typically used to dynamically allocate multiple
@@ -212,11 +222,23 @@ impl<const PAGE_SIZE: usize> RecordsPage<PAGE_SIZE> {
         heap_size: usize,
         num_pages: usize,
     ) -> *mut [T] {
+        self.try_allocate_slice(heap_start, heap_size, num_pages)
+            .expect("Heap overflow!")
+    }
+
+    /// Same as [RecordsPage::allocate_slice], but returns `None` instead of panicking if there's
+    /// no room
+    pub fn try_allocate_slice<T>(
+        &mut self,
+        heap_start: *const c_void,
+        heap_size: usize,
+        num_pages: usize,
+    ) -> Option<*mut [T]> {
         assert_eq!(mem::size_of::<T>(), PAGE_SIZE);
 
-        let ptr = self.allocate_inner(heap_start, heap_size, num_pages) as *mut T;
+        let ptr = self.allocate_inner(heap_start, heap_size, num_pages)? as *mut T;
         let size = num_pages * PAGE_SIZE;
-        ptr::slice_from_raw_parts_mut(ptr, size)
+        Some(ptr::slice_from_raw_parts_mut(ptr, size))
     }
 }
 
@@ -269,4 +291,25 @@ mod test {
             assert_eq!(records_page.deallocate(null(), PAGES, *address), tv[index]);
         }
     }
+
+    #[test]
+    fn try_allocate_when_full() {
+        const PAGES: usize = 8;
+
+        let mut records_page = RecordsPage([Default::default(); PAGE_SIZE]);
+
+        let first = records_page
+            .try_allocate_slice::<Page>(null(), PAGES, 6)
+            .unwrap();
+        assert!(records_page
+            .try_allocate_slice::<Page>(null(), PAGES, 3)
+            .is_none());
+        assert!(records_page
+            .try_allocate_slice::<Page>(null(), PAGES, 2)
+            .is_some());
+        assert!(records_page.try_allocate::<Page>(null(), PAGES).is_none());
+
+        records_page.deallocate(null(), PAGES, first);
+        assert!(records_page.try_allocate::<Page>(null(), PAGES).is_some());
+    }
 }
@@ -6,10 +6,13 @@ set -e
 CARGO_OUTPUT=target/riscv64gc-unknown-none-elf/debug/krabby
 cargo build
 
+# Install userspace programs into the root filesystem
+./make-rootfs.sh
+
 # Make a bin file because if we use the elf file QEMU will want to load it at
 # the intended virtual address because it's stupid or something
 riscv64-unknown-elf-objcopy -O binary ${CARGO_OUTPUT}{,.bin}
-qemu-system-riscv64 -serial mon:stdio -nographic -machine virt -bios none "${@}" \
+qemu-system-riscv64 -serial mon:stdio -nographic -machine virt -smp 4 -bios none "${@}" \
     -drive if=none,format=raw,file=rootfs.img,id=foo \
     -device virtio-blk-device,scsi=off,drive=foo\
     -kernel "${CARGO_OUTPUT}.bin"
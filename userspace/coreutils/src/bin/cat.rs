@@ -0,0 +1,42 @@
+//! Print files one after another
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Args, Context, Result};
+use kanto::{
+    fs::File,
+    io::{self, Read, Write},
+    prelude::*,
+};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("cat", cat)
+}
+
+// With no files, or for `-`, standard input is printed
+fn cat(args: Vec<String>) -> Result {
+    let args = Args::parse(args, "")?;
+    if args.operands.is_empty() {
+        return copy(&mut io::stdin()).context("stdin");
+    }
+    for path in &args.operands {
+        if path == "-" {
+            copy(&mut io::stdin()).context("stdin")?;
+        } else {
+            copy(&mut File::open(path).context(path)?).context(path)?;
+        }
+    }
+    Ok(())
+}
+
+fn copy(input: &mut impl Read) -> io::Result<()> {
+    let mut buffer = [0; 512];
+    loop {
+        match input.read(&mut buffer)? {
+            0 => return Ok(()),
+            len => io::stdout().write_all(&buffer[..len])?,
+        }
+    }
+}
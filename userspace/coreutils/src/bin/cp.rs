@@ -0,0 +1,20 @@
+//! Copy files
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Args, Context, Result};
+use kanto::{fs, prelude::*};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("cp", cp)
+}
+
+fn cp(args: Vec<String>) -> Result {
+    let args = Args::parse(args, "")?;
+    for (from, to) in coreutils::destinations(args.operands)? {
+        fs::copy(&from, &to).context(&from)?;
+    }
+    Ok(())
+}
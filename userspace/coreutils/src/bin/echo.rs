@@ -0,0 +1,25 @@
+//! Print the arguments, separated by spaces
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::Result;
+use kanto::prelude::*;
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("echo", echo)
+}
+
+// Like other echos, only `-n` as the first argument is an option
+fn echo(args: Vec<String>) -> Result {
+    let (newline, args) = match args.split_first() {
+        Some((first, rest)) if first == "-n" => (false, rest),
+        _ => (true, args.as_slice()),
+    };
+    print!("{}", args.join(" "));
+    if newline {
+        println!();
+    }
+    Ok(())
+}
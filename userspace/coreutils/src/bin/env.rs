@@ -0,0 +1,22 @@
+//! Print the environment, one `NAME=value` per line
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::Result;
+use kanto::{env, prelude::*};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("env", print_env)
+}
+
+fn print_env(args: Vec<String>) -> Result {
+    if !args.is_empty() {
+        return Err("no arguments allowed".into());
+    }
+    for (name, value) in env::vars() {
+        println!("{name}={value}");
+    }
+    Ok(())
+}
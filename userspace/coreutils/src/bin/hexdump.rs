@@ -0,0 +1,92 @@
+//! Print files as hex and ASCII, like `hexdump -C`
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Args, Context, Result};
+use kanto::{
+    fs::File,
+    io::{self, Read},
+    prelude::*,
+};
+
+const LINE_LEN: usize = 16;
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("hexdump", hexdump)
+}
+
+// The files are dumped as one stream, like other hexdumps. With none, standard input is dumped
+fn hexdump(args: Vec<String>) -> Result {
+    let args = Args::parse(args, "C")?;
+    let mut dump = Dump::default();
+    if args.operands.is_empty() {
+        dump.read(&mut io::stdin()).context("stdin")?;
+    }
+    for path in &args.operands {
+        dump.read(&mut File::open(path).context(path)?)
+            .context(path)?;
+    }
+    dump.finish();
+    Ok(())
+}
+
+// Prints a line every time it fills up
+#[derive(Default)]
+struct Dump {
+    line: [u8; LINE_LEN],
+    len: usize,
+    offset: usize,
+}
+
+impl Dump {
+    fn read(&mut self, input: &mut impl Read) -> io::Result<()> {
+        loop {
+            match input.read(&mut self.line[self.len..])? {
+                0 => return Ok(()),
+                len => self.len += len,
+            }
+            if self.len == LINE_LEN {
+                self.print_line();
+            }
+        }
+    }
+
+    // Print what's left, then the total length
+    fn finish(mut self) {
+        if self.len > 0 {
+            self.print_line();
+        }
+        println!("{:08x}", self.offset);
+    }
+
+    fn print_line(&mut self) {
+        let bytes = &self.line[..self.len];
+        let mut hex = String::new();
+        for i in 0..LINE_LEN {
+            // An extra space separates the two halves
+            if i == LINE_LEN / 2 {
+                hex.push(' ');
+            }
+            match bytes.get(i) {
+                Some(byte) => hex.push_str(&format!("{byte:02x} ")),
+                None => hex.push_str("   "),
+            }
+        }
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!("{:08x}  {hex} |{ascii}|", self.offset);
+
+        self.offset += self.len;
+        self.len = 0;
+    }
+}
@@ -0,0 +1,72 @@
+//! Send signals to processes
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Context, Result};
+use kanto::{
+    abi::{signal::Signal, Pid},
+    prelude::*,
+    sys,
+};
+
+// Names without the usual SIG prefix, like other kills
+const SIGNALS: &[(&str, Signal)] = &[
+    ("INT", Signal::Interrupt),
+    ("KILL", Signal::Kill),
+    ("USR1", Signal::User1),
+    ("USR2", Signal::User2),
+    ("TERM", Signal::Terminate),
+    ("CHLD", Signal::Child),
+    ("CONT", Signal::Continue),
+    ("STOP", Signal::Stop),
+    ("TSTP", Signal::TerminalStop),
+];
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("kill", kill)
+}
+
+// `kill [-SIGNAL] PID...` sends TERM unless told otherwise, and `kill -l` lists signals
+fn kill(args: Vec<String>) -> Result {
+    let (signal, pids) = match args.split_first() {
+        Some((first, _)) if first == "-l" => {
+            for (name, signal) in SIGNALS {
+                println!("{:>2} {name}", *signal as usize);
+            }
+            return Ok(());
+        }
+        Some((first, rest)) if first.starts_with('-') => (parse_signal(&first[1..])?, rest),
+        _ => (Signal::Terminate, args.as_slice()),
+    };
+    if pids.is_empty() {
+        return Err("usage: kill [-SIGNAL] PID...".into());
+    }
+
+    for pid in pids {
+        let parsed = pid
+            .parse::<usize>()
+            .ok()
+            .and_then(|pid| Pid::try_from(pid).ok())
+            .ok_or_else(|| format!("{pid}: invalid PID"))?;
+        sys::kill(parsed, signal).context(pid)?;
+    }
+    Ok(())
+}
+
+// By number or name, with or without the SIG prefix
+fn parse_signal(signal: &str) -> Result<Signal> {
+    let name = signal.strip_prefix("SIG").unwrap_or(signal);
+    signal
+        .parse::<usize>()
+        .ok()
+        .and_then(Signal::n)
+        .or_else(|| {
+            SIGNALS
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                .map(|(_, signal)| *signal)
+        })
+        .ok_or_else(|| format!("{signal}: unknown signal").into())
+}
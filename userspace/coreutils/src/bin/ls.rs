@@ -0,0 +1,62 @@
+//! List directories
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Args, Context, Result};
+use kanto::{
+    fs::{self, Metadata},
+    io,
+    prelude::*,
+};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("ls", ls)
+}
+
+// `-l` adds each entry's type and size. Directories are marked with a trailing `/`
+fn ls(args: Vec<String>) -> Result {
+    let args = Args::parse(args, "l")?;
+    let long = args.flag('l');
+    let paths = if args.operands.is_empty() {
+        vec![".".into()]
+    } else {
+        args.operands
+    };
+
+    for (i, path) in paths.iter().enumerate() {
+        let metadata = fs::metadata(path).context(path)?;
+        if !metadata.is_dir() {
+            print_entry(path, metadata, long);
+            continue;
+        }
+
+        let mut entries = fs::read_dir(path)
+            .and_then(|dir| dir.collect::<io::Result<Vec<_>>>())
+            .context(path)?;
+        entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+
+        // Like other ls's, only name directories when there's more than one thing listed
+        if paths.len() > 1 {
+            if i > 0 {
+                println!();
+            }
+            println!("{path}:");
+        }
+        for entry in entries {
+            print_entry(entry.file_name(), entry.metadata(), long);
+        }
+    }
+    Ok(())
+}
+
+fn print_entry(name: &str, metadata: Metadata, long: bool) {
+    let suffix = if metadata.is_dir() { "/" } else { "" };
+    if long {
+        let kind = if metadata.is_dir() { 'd' } else { '-' };
+        println!("{kind} {:>10} {name}{suffix}", metadata.len());
+    } else {
+        println!("{name}{suffix}");
+    }
+}
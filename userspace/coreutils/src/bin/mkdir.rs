@@ -0,0 +1,28 @@
+//! Create directories
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Args, Context, Result};
+use kanto::{fs, prelude::*};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("mkdir", mkdir)
+}
+
+// `-p` creates missing parents too, and doesn't mind if the directory exists
+fn mkdir(args: Vec<String>) -> Result {
+    let args = Args::parse(args, "p")?;
+    if args.operands.is_empty() {
+        return Err("usage: mkdir [-p] DIRECTORY...".into());
+    }
+    for path in &args.operands {
+        if args.flag('p') {
+            fs::create_dir_all(path).context(path)?;
+        } else {
+            fs::create_dir(path).context(path)?;
+        }
+    }
+    Ok(())
+}
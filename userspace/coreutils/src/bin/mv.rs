@@ -0,0 +1,21 @@
+//! Move files
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Args, Context, Result};
+use kanto::{fs, prelude::*};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("mv", mv)
+}
+
+// Directories can't be moved, since fs::rename can't move them
+fn mv(args: Vec<String>) -> Result {
+    let args = Args::parse(args, "")?;
+    for (from, to) in coreutils::destinations(args.operands)? {
+        fs::rename(&from, &to).context(&from)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! List processes
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Context, Result};
+use kanto::{
+    prelude::*,
+    process::{self, ProcessInfo},
+};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("ps", ps)
+}
+
+fn ps(_args: Vec<String>) -> Result {
+    let processes = process::list().context("listing processes")?;
+    println!(
+        "{:>5} {:>5} {:>5} {:<8} {:>3} {:>3} {:>9} {:>6} {:<12} COMMAND",
+        "PID", "PPID", "PGID", "STATE", "NI", "THR", "TIME", "RSS", "WAITING"
+    );
+    for process in &processes {
+        print_process(process);
+    }
+    Ok(())
+}
+
+// Memory is shown in KiB, and what a blocked process is waiting for under WAITING
+fn print_process(process: &ProcessInfo) {
+    let ppid = process.ppid.map_or("-".into(), |ppid| ppid.to_string());
+    let waiting = process
+        .blocked_on
+        .map_or("-".into(), |reason| reason.to_string());
+    println!(
+        "{:>5} {ppid:>5} {:>5} {:<8} {:>3} {:>3} {:>9} {:>6} {waiting:<12} {}",
+        process.pid,
+        process.pgid,
+        process.state.to_string(),
+        process.nice.to_string(),
+        process.threads,
+        coreutils::format_time(process.cpu_time),
+        process.resident_pages * 4,
+        process.name,
+    );
+}
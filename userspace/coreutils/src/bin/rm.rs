@@ -0,0 +1,42 @@
+//! Remove files and directories
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Args, Context, Result};
+use kanto::{abi::Errno, fs, io, prelude::*};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("rm", rm)
+}
+
+// `-r` removes directories and everything in them. `-f` ignores missing files
+fn rm(args: Vec<String>) -> Result {
+    let args = Args::parse(args, "rf")?;
+    if args.operands.is_empty() && !args.flag('f') {
+        return Err("usage: rm [-rf] FILE...".into());
+    }
+    for path in &args.operands {
+        let result = if args.flag('r') {
+            remove_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        match result {
+            Err(err) if args.flag('f') && err.errno() == Errno::NotFound => {}
+            result => result.context(path)?,
+        }
+    }
+    Ok(())
+}
+
+fn remove_all(path: &str) -> io::Result<()> {
+    if !fs::metadata(path)?.is_dir() {
+        return fs::remove_file(path);
+    }
+    for entry in fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()? {
+        remove_all(entry.path())?;
+    }
+    fs::remove_dir(path)
+}
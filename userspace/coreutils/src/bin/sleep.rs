@@ -0,0 +1,22 @@
+//! Wait for a number of seconds
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Context, Result};
+use kanto::{prelude::*, sys};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("sleep", sleep)
+}
+
+fn sleep(args: Vec<String>) -> Result {
+    let [seconds] = args.as_slice() else {
+        return Err("usage: sleep SECONDS".into());
+    };
+    let duration =
+        coreutils::parse_seconds(seconds).ok_or_else(|| format!("{seconds}: invalid time"))?;
+    sys::sleep(duration).context("sleep")?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! Show the busiest processes, refreshing every few seconds
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use core::time::Duration;
+use coreutils::{Context, Result};
+use kanto::{abi::Pid, prelude::*, process, sys};
+
+// Most processes shown at once
+const MAX_ROWS: usize = 20;
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("top", top)
+}
+
+// `-d SECONDS` sets the delay between refreshes, and `-n COUNT` stops after that many. Otherwise
+// it runs until it's interrupted
+fn top(args: Vec<String>) -> Result {
+    let mut delay = Duration::from_secs(2);
+    let mut count = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or("usage: top [-d SECONDS] [-n COUNT]")?;
+        match arg.as_str() {
+            "-d" => {
+                delay = coreutils::parse_seconds(value)
+                    .ok_or_else(|| format!("{value}: invalid time"))?;
+            }
+            "-n" => {
+                count = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("{value}: invalid count"))?,
+                )
+            }
+            _ => return Err("usage: top [-d SECONDS] [-n COUNT]".into()),
+        }
+    }
+
+    // CPU time of each process as of the last refresh, to see how much each used since
+    let mut last: Vec<(Pid, Duration)> = Vec::new();
+    let mut last_uptime = Duration::ZERO;
+    let mut refreshes = 0_usize;
+    loop {
+        let uptime = sys::uptime().context("uptime")?;
+        let processes = process::list().context("listing processes")?;
+        let elapsed = uptime - last_uptime;
+
+        let mut rows: Vec<_> = processes
+            .iter()
+            .map(|process| {
+                let before = last
+                    .iter()
+                    .find(|(pid, _)| *pid == process.pid)
+                    .map_or(Duration::ZERO, |(_, time)| *time);
+                let used = process.cpu_time.saturating_sub(before);
+                (
+                    used.as_micros() * 1000 / elapsed.as_micros().max(1),
+                    process,
+                )
+            })
+            .collect();
+        rows.sort_by(|a, b| b.0.cmp(&a.0));
+
+        // Clear the screen and start from the top
+        print!("\x1b[2J\x1b[H");
+        let seconds = uptime.as_secs();
+        println!(
+            "up {}:{:02}:{:02}, {} processes\n",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            processes.len()
+        );
+        println!(
+            "{:>5} {:<8} {:>3} {:>6} {:>9} {:>6} COMMAND",
+            "PID", "STATE", "NI", "%CPU", "TIME", "RSS"
+        );
+        for (permille, process) in rows.iter().take(MAX_ROWS) {
+            println!(
+                "{:>5} {:<8} {:>3} {:>4}.{} {:>9} {:>6} {}",
+                process.pid,
+                process.state.to_string(),
+                process.nice.to_string(),
+                permille / 10,
+                permille % 10,
+                coreutils::format_time(process.cpu_time),
+                process.resident_pages * 4,
+                process.name,
+            );
+        }
+
+        last = processes
+            .iter()
+            .map(|process| (process.pid, process.cpu_time))
+            .collect();
+        last_uptime = uptime;
+        refreshes += 1;
+        if count == Some(refreshes) {
+            return Ok(());
+        }
+        sys::sleep(delay).context("sleep")?;
+    }
+}
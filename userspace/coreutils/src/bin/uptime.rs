@@ -0,0 +1,28 @@
+//! Print how long the machine has been running
+#![no_std]
+#![no_main]
+extern crate alloc;
+
+use coreutils::{Context, Result};
+use kanto::{prelude::*, sys};
+
+#[no_mangle]
+extern "C" fn main() {
+    coreutils::run("uptime", uptime)
+}
+
+fn uptime(_args: Vec<String>) -> Result {
+    let seconds = sys::uptime().context("uptime")?.as_secs();
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+    );
+    match days {
+        0 => println!("up {hours}:{minutes:02}:{seconds:02}"),
+        1 => println!("up 1 day, {hours}:{minutes:02}:{seconds:02}"),
+        _ => println!("up {days} days, {hours}:{minutes:02}:{seconds:02}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Shared parts of the KabutOS command line programs
+//!
+//! Each program is its own binary in `src/bin`, installed into `/bin` on the root filesystem
+#![no_std]
+#![warn(missing_docs)]
+extern crate alloc;
+
+use core::{
+    fmt::{self, Display},
+    time::Duration,
+};
+use kanto::{abi::ProcessError, env, fs, io, prelude::*, process};
+
+/// Why a program failed
+#[derive(Debug)]
+pub struct Error(String);
+
+/// Result type for the programs
+pub type Result<T = ()> = core::result::Result<T, Error>;
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error(err.to_string())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error(message.into())
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error(message)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Say what failed, usually with the path it failed on
+pub trait Context<T> {
+    /// Prefix the error with `what`
+    fn context(self, what: &str) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for core::result::Result<T, E> {
+    fn context(self, what: &str) -> Result<T> {
+        self.map_err(|err| {
+            let err: Error = err.into();
+            Error(format!("{what}: {err}"))
+        })
+    }
+}
+
+/// Run a program's real main with its arguments, not including its name, then exit
+///
+/// An error is printed as `name: error` and the program fails
+pub fn run(name: &str, main: fn(Vec<String>) -> Result) -> ! {
+    let args = env::args().skip(1).collect();
+    match main(args) {
+        Ok(()) => process::exit(Ok(())),
+        Err(err) => {
+            eprintln!("{name}: {err}");
+            process::exit(Err(ProcessError::Failure))
+        }
+    }
+}
+
+/// Arguments split into single letter flags and everything else
+#[derive(Debug)]
+pub struct Args {
+    flags: Vec<char>,
+    /// Arguments that aren't flags, in order
+    pub operands: Vec<String>,
+}
+
+impl Args {
+    /// Split out flags, which may be combined like `-rf`, allowing only those in `allowed`
+    ///
+    /// Flags end at the first operand, `-` or `--`
+    pub fn parse(args: Vec<String>, allowed: &str) -> Result<Self> {
+        let mut flags = Vec::new();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next_if(|arg| arg.starts_with('-') && arg != "-") {
+            if arg == "--" {
+                break;
+            }
+            for flag in arg.chars().skip(1) {
+                if !allowed.contains(flag) {
+                    return Err(format!("unknown option -{flag}").into());
+                }
+                flags.push(flag);
+            }
+        }
+        Ok(Args {
+            flags,
+            operands: args.collect(),
+        })
+    }
+
+    /// Whether a flag was given
+    pub fn flag(&self, flag: char) -> bool {
+        self.flags.contains(&flag)
+    }
+}
+
+/// Name of the last component of a path
+pub fn basename(path: &str) -> &str {
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Pair each source with where it goes, for `cp` and `mv`
+///
+/// The last operand is the destination. With several sources it must be a directory, and
+/// sources going into a directory keep their names
+pub fn destinations(mut operands: Vec<String>) -> Result<Vec<(String, String)>> {
+    if operands.len() < 2 {
+        return Err("missing destination".into());
+    }
+    let destination = operands.pop().unwrap();
+    let into_dir = fs::metadata(&destination).is_ok_and(|metadata| metadata.is_dir());
+    if operands.len() > 1 && !into_dir {
+        return Err(format!("{destination}: not a directory").into());
+    }
+
+    Ok(operands
+        .into_iter()
+        .map(|source| {
+            let to = if into_dir {
+                format!("{destination}/{}", basename(&source))
+            } else {
+                destination.clone()
+            };
+            (source, to)
+        })
+        .collect())
+}
+
+/// Parse a number of seconds, which may have a fraction like `0.25`
+///
+/// Anything past nanoseconds is ignored
+pub fn parse_seconds(seconds: &str) -> Option<Duration> {
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+
+    let mut nanos = 0;
+    for (i, digit) in fraction.chars().enumerate() {
+        let digit = digit.to_digit(10)?;
+        if i < 9 {
+            nanos += digit * 10_u32.pow(8 - i as u32);
+        }
+    }
+    Some(Duration::new(whole, nanos))
+}
+
+/// Format a duration as minutes, seconds, and hundredths, like `12:03.45`
+pub fn format_time(time: Duration) -> String {
+    let hundredths = time.subsec_millis() / 10;
+    let seconds = time.as_secs();
+    format!("{}:{:02}.{hundredths:02}", seconds / 60, seconds % 60)
+}
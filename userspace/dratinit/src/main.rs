@@ -1,5 +1,6 @@
 #![no_std]
 #![no_main]
+extern crate alloc;
 mod shell;
 use core::time::Duration;
 use kanto::{prelude::*, sys};
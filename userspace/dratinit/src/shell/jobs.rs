@@ -0,0 +1,90 @@
+//! Pipelines the shell started, running in their own process groups
+use core::fmt::{self, Display};
+use kanto::{
+    abi::{
+        signal::Signal,
+        wait::{WaitFlags, WaitStatus},
+        Pid,
+    },
+    prelude::*,
+    sys,
+};
+
+/// A pipeline the shell is keeping track of
+#[derive(Debug)]
+pub struct Job {
+    /// Number for referring to it with `fg` and `bg`
+    pub id: usize,
+    /// Process group of every process in the job
+    pub pgid: Pid,
+    /// The line that started it
+    pub line: String,
+    /// Stopped by a signal, waiting for `fg` or `bg`
+    pub stopped: bool,
+    // Processes that haven't been seen to exit, in pipeline order
+    pids: Vec<Pid>,
+    // The process whose status is the job's status
+    last: Pid,
+}
+
+impl Job {
+    /// Track processes started from `line`. The first is the process group leader
+    pub fn new(id: usize, pids: Vec<Pid>, line: &str) -> Self {
+        Job {
+            id,
+            pgid: pids[0],
+            line: line.into(),
+            stopped: false,
+            last: *pids.last().unwrap(),
+            pids,
+        }
+    }
+
+    /// Wait for the job to exit or be stopped
+    ///
+    /// Like other shells, the job's exit status is that of the last command in the pipeline
+    pub fn wait(&mut self) -> WaitStatus {
+        let mut status = WaitStatus::Exited(Ok(()));
+        while let Some(&pid) = self.pids.first() {
+            match sys::wait_pid_status(pid, WaitFlags::STOPPED) {
+                Ok(WaitStatus::Stopped(signal)) => {
+                    self.stopped = true;
+                    return WaitStatus::Stopped(signal);
+                }
+                Ok(exited) if pid == self.last => status = exited,
+                // Only the last command's status matters
+                _ => {}
+            }
+            self.pids.remove(0);
+        }
+        status
+    }
+
+    /// Continue the job if it was stopped
+    pub fn resume(&mut self) {
+        for &pid in &self.pids {
+            let _ = sys::kill(pid, Signal::Continue);
+        }
+        self.stopped = false;
+    }
+
+    /// Reap processes that have exited, returning whether there are none left
+    pub fn poll(&mut self) -> bool {
+        self.pids.retain(|&pid| {
+            let running = sys::process_group(Some(pid)).is_ok();
+            if !running {
+                // Gone already, so this doesn't block
+                let _ = sys::wait_pid_status(pid, WaitFlags::EXITED);
+            }
+            running
+        });
+        self.pids.is_empty()
+    }
+}
+
+impl Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let state = if self.stopped { "Stopped" } else { "Running" };
+        write!(f, "[{}]  {state}\t{}", self.id, self.line)
+    }
+}
@@ -0,0 +1,447 @@
+//! Interactive shell with pipes, redirection, and job control
+mod jobs;
+mod parse;
+
+use core::fmt;
+use crusty_line::CrustyLine;
+use jobs::Job;
+use kanto::{
+    abi::{
+        fs::{FileDescriptor, OpenFlags},
+        tty::TtyMode,
+        wait::WaitStatus,
+        Errno, Pid, ProcessError, ProcessResult,
+    },
+    env, fs,
+    prelude::*,
+    sys,
+};
+use parse::Command;
+
+struct Serial;
+
+impl fmt::Write for Serial {
+    fn write_str(&mut self, string: &str) -> Result<(), fmt::Error> {
+        sys::puts(string).unwrap();
+        Ok(())
+    }
+}
+
+impl Iterator for Serial {
+    type Item = Result<char, sys::SyscallError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(sys::getc())
+    }
+}
+
+struct Shell {
+    // Process group that gets the terminal back when a foreground job is done
+    pgid: Pid,
+    // `NAME=value` pairs, passed to every program
+    env: Vec<String>,
+    // Exit status of the last foreground job, for `$?`
+    status: usize,
+    // Background and stopped jobs, in order of ID
+    jobs: Vec<Job>,
+}
+
+pub fn shell() {
+    // Take the terminal, so Ctrl-C and Ctrl-Z only go to the jobs we hand it to
+    sys::set_process_group(None, None).unwrap();
+    let pgid = sys::process_group(None).unwrap();
+    sys::set_foreground_group(Some(pgid)).unwrap();
+
+    let mut shell = Shell {
+        pgid,
+        env: alloc::vec!["PATH=/bin".into(), "HOME=/".into(), "PWD=/".into()],
+        status: 0,
+        jobs: Vec::new(),
+    };
+    let mut readline = CrustyLine::<256, 16>::default();
+    let prompt = "$ ";
+
+    loop {
+        shell.report_finished_jobs();
+
+        // CrustyLine does its own line editing and echo
+        sys::set_tty_mode(TtyMode::RAW).unwrap();
+        let line = readline.get_line(prompt, Serial, Serial).unwrap();
+        sys::set_tty_mode(TtyMode::DEFAULT).unwrap();
+
+        shell.run(line);
+    }
+}
+
+impl Shell {
+    fn run(&mut self, line: &str) {
+        let pipeline = match parse::parse(line, |name| self.var(name)) {
+            Ok(Some(pipeline)) => pipeline,
+            Ok(None) => return,
+            Err(err) => {
+                println!("syntax error: {err}");
+                self.status = ProcessError::Failure.into();
+                return;
+            }
+        };
+
+        // Builtins change the shell itself, unless they're part of something bigger
+        if let [command] = &pipeline.commands[..] {
+            if !pipeline.background && command.stdin.is_none() && command.stdout.is_none() {
+                let argv: Vec<&str> = command.argv.iter().map(String::as_str).collect();
+                if let Some(result) = self.builtin(&argv) {
+                    self.status = WaitStatus::Exited(result).into();
+                    return;
+                }
+            }
+        }
+
+        // Start every command, connecting each one's output to the next one's input
+        let mut pids: Vec<Pid> = Vec::new();
+        let mut stdin = None;
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            let pipe = if i + 1 < pipeline.commands.len() {
+                match sys::pipe() {
+                    Ok(pipe) => Some(pipe),
+                    Err(err) => {
+                        println!("pipe: {err}");
+                        break;
+                    }
+                }
+            } else {
+                None
+            };
+
+            match sys::fork() {
+                Ok(Some(pid)) => {
+                    // The child does this too, so it doesn't matter which runs first
+                    let pgid = pids.first().copied().unwrap_or(pid);
+                    let _ = sys::set_process_group(Some(pid), Some(pgid));
+                    pids.push(pid);
+                }
+                Ok(None) => {
+                    if let Some((read, _)) = pipe {
+                        let _ = sys::close(read);
+                    }
+                    let stdout = pipe.map(|(_, write)| write);
+                    let pgid = pids.first().copied();
+                    self.exec(command, pgid, stdin, stdout);
+                }
+                Err(err) => {
+                    println!("fork: {err}");
+                    if let Some((read, write)) = pipe {
+                        let _ = sys::close(read);
+                        let _ = sys::close(write);
+                    }
+                    break;
+                }
+            }
+
+            if let Some(fd) = stdin {
+                let _ = sys::close(fd);
+            }
+            stdin = pipe.map(|(read, write)| {
+                let _ = sys::close(write);
+                read
+            });
+        }
+        if let Some(fd) = stdin {
+            let _ = sys::close(fd);
+        }
+
+        if pids.is_empty() {
+            self.status = ProcessError::Failure.into();
+            return;
+        }
+
+        let job = Job::new(self.next_job_id(), pids, line);
+        if pipeline.background {
+            println!("[{}] {}", job.id, job.pgid);
+            self.jobs.push(job);
+            self.status = 0;
+        } else {
+            self.status = WaitStatus::Exited(self.foreground(job)).into();
+        }
+    }
+
+    // Runs in a forked child, and never returns
+    fn exec(
+        &mut self,
+        command: &Command,
+        pgid: Option<Pid>,
+        stdin: Option<FileDescriptor>,
+        stdout: Option<FileDescriptor>,
+    ) -> ! {
+        let argv: Vec<&str> = command.argv.iter().map(String::as_str).collect();
+        let result = match self.setup_child(command, pgid, stdin, stdout) {
+            Ok(()) => self
+                .builtin(&argv)
+                .unwrap_or_else(|| self.exec_program(&argv)),
+            Err(err) => {
+                println!("{}: {err}", argv[0]);
+                Err(ProcessError::Failure)
+            }
+        };
+        sys::exit(result).unwrap();
+        unreachable!("Exited child returned");
+    }
+
+    // Join the job's process group and hook up standard input and output
+    fn setup_child(
+        &self,
+        command: &Command,
+        pgid: Option<Pid>,
+        stdin: Option<FileDescriptor>,
+        stdout: Option<FileDescriptor>,
+    ) -> sys::SyscallResult {
+        // The shell hands over the terminal for foreground jobs, since we can't take it
+        sys::set_process_group(None, pgid)?;
+
+        // Redirections take the place of pipes
+        let stdin = match &command.stdin {
+            Some(path) => {
+                close_if_open(stdin)?;
+                Some(sys::open(&fs::absolute(path), OpenFlags::READ)?)
+            }
+            None => stdin,
+        };
+        let stdout = match &command.stdout {
+            Some(output) => {
+                close_if_open(stdout)?;
+                let mode = if output.append {
+                    OpenFlags::APPEND
+                } else {
+                    OpenFlags::TRUNCATE
+                };
+                let flags = OpenFlags::WRITE | OpenFlags::CREATE | mode;
+                Some(sys::open(&fs::absolute(&output.path), flags)?)
+            }
+            None => stdout,
+        };
+
+        if let Some(fd) = stdin {
+            move_fd(fd, FileDescriptor::STDIN)?;
+        }
+        if let Some(fd) = stdout {
+            move_fd(fd, FileDescriptor::STDOUT)?;
+        }
+        Ok(())
+    }
+
+    // Replace this process with a program, returning only if that failed
+    fn exec_program(&self, argv: &[&str]) -> ProcessResult {
+        let name = argv[0];
+        let env: Vec<&str> = self.env.iter().map(String::as_str).collect();
+
+        let err = if name.contains('/') {
+            sys::exec(&fs::absolute(name), argv, &env).unwrap_err()
+        } else {
+            // Try each directory in $PATH until one has it
+            let mut err = Errno::NotFound.into();
+            for dir in self.var("PATH").split(':').filter(|dir| !dir.is_empty()) {
+                let path = fs::absolute(&alloc::format!("{dir}/{name}"));
+                err = sys::exec(&path, argv, &env).unwrap_err();
+                if err.errno() != Errno::NotFound {
+                    break;
+                }
+            }
+            err
+        };
+
+        if err.errno() == Errno::NotFound {
+            println!("{name}: command not found");
+        } else {
+            println!("{name}: {err}");
+        }
+        Err(ProcessError::Failure)
+    }
+
+    // Give the job the terminal and wait for it to exit or stop
+    //
+    // Like other shells, a stopped job's status is as if the signal had killed it
+    fn foreground(&mut self, mut job: Job) -> ProcessResult {
+        let _ = sys::set_foreground_group(Some(job.pgid));
+        let status = job.wait();
+        let _ = sys::set_foreground_group(Some(self.pgid));
+
+        match status {
+            WaitStatus::Exited(result) => result,
+            WaitStatus::Stopped(signal) => {
+                println!();
+                println!("{job}");
+                self.jobs.push(job);
+                self.jobs.sort_by_key(|job| job.id);
+                Err(ProcessError::Killed(signal))
+            }
+        }
+    }
+
+    // Run a builtin command, or return `None` if there's no builtin by that name
+    fn builtin(&mut self, argv: &[&str]) -> Option<ProcessResult> {
+        let result = match argv {
+            ["cd"] => self.cd(&self.var("HOME")),
+            ["cd", dir] => self.cd(dir),
+            ["cd", ..] => usage("cd [dir]"),
+            ["pwd", ..] => {
+                println!("{}", env::current_dir());
+                Ok(())
+            }
+            ["exit"] => exit(self.status),
+            ["exit", code] => match code.parse() {
+                Ok(code) => exit(code),
+                Err(_) => usage("exit [code]"),
+            },
+            ["exit", ..] => usage("exit [code]"),
+            ["export"] => {
+                for var in &self.env {
+                    println!("export {var}");
+                }
+                Ok(())
+            }
+            ["export", vars @ ..] => vars.iter().try_for_each(|var| match var.split_once('=') {
+                Some((name, value)) if !name.is_empty() => {
+                    self.set_var(name, value);
+                    Ok(())
+                }
+                _ => usage("export [NAME=value]..."),
+            }),
+            ["jobs", ..] => {
+                self.report_finished_jobs();
+                for job in &self.jobs {
+                    println!("{job}");
+                }
+                Ok(())
+            }
+            ["fg"] => self.fg(None),
+            ["fg", id] => self.fg(Some(id)),
+            ["bg"] => self.bg(None),
+            ["bg", id] => self.bg(Some(id)),
+            ["fg" | "bg", ..] => usage("fg|bg [%job]"),
+            ["test"] => sys::test().map(|_| ()).map_err(|err| {
+                println!("test: {err}");
+                ProcessError::Failure
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn cd(&mut self, dir: &str) -> ProcessResult {
+        let path = fs::absolute(dir);
+        let checked = fs::metadata(&path).and_then(|metadata| {
+            if metadata.is_dir() {
+                Ok(())
+            } else {
+                Err(Errno::NotADirectory.into())
+            }
+        });
+        if let Err(err) = checked {
+            println!("cd: {dir}: {err}");
+            return Err(ProcessError::Failure);
+        }
+        // Relative paths are resolved against the process's $PWD, and programs get ours
+        env::set_var("PWD", &path);
+        self.set_var("PWD", &path);
+        Ok(())
+    }
+
+    fn fg(&mut self, id: Option<&str>) -> ProcessResult {
+        let index = self.find_job(id)?;
+        let mut job = self.jobs.remove(index);
+        println!("{}", job.line);
+        job.resume();
+        self.foreground(job)
+    }
+
+    fn bg(&mut self, id: Option<&str>) -> ProcessResult {
+        let index = self.find_job(id)?;
+        let job = &mut self.jobs[index];
+        job.resume();
+        println!("[{}] {} &", job.id, job.line);
+        Ok(())
+    }
+
+    // Find the job for a `%n` argument, defaulting to the most recent
+    fn find_job(&self, id: Option<&str>) -> Result<usize, ProcessError> {
+        let index = match id {
+            None => self.jobs.len().checked_sub(1),
+            Some(id) => id
+                .trim_start_matches('%')
+                .parse()
+                .ok()
+                .and_then(|id: usize| self.jobs.iter().position(|job| job.id == id)),
+        };
+        index.ok_or_else(|| {
+            println!("no such job");
+            ProcessError::Failure
+        })
+    }
+
+    fn next_job_id(&self) -> usize {
+        self.jobs.last().map_or(1, |job| job.id + 1)
+    }
+
+    // Print and forget background jobs that have finished
+    fn report_finished_jobs(&mut self) {
+        self.jobs.retain_mut(|job| {
+            let done = job.poll();
+            if done {
+                println!("[{}]  Done\t{}", job.id, job.line);
+            }
+            !done
+        });
+    }
+
+    // Value of a variable, or an empty string if it isn't set
+    fn var(&self, name: &str) -> String {
+        if name == "?" {
+            return alloc::format!("{}", self.status);
+        }
+        self.env
+            .iter()
+            .find_map(|var| var.strip_prefix(name)?.strip_prefix('='))
+            .unwrap_or_default()
+            .into()
+    }
+
+    fn set_var(&mut self, name: &str, value: &str) {
+        let var = alloc::format!("{name}={value}");
+        match self
+            .env
+            .iter_mut()
+            .find(|var| var.split_once('=').map(|(n, _)| n) == Some(name))
+        {
+            Some(existing) => *existing = var,
+            None => self.env.push(var),
+        }
+    }
+}
+
+// Put `fd` in place of `target`, closing the original
+fn move_fd(fd: FileDescriptor, target: FileDescriptor) -> sys::SyscallResult {
+    if fd != target {
+        sys::dup2(fd, target)?;
+        sys::close(fd)?;
+    }
+    Ok(())
+}
+
+fn close_if_open(fd: Option<FileDescriptor>) -> sys::SyscallResult {
+    if let Some(fd) = fd {
+        sys::close(fd)?;
+    }
+    Ok(())
+}
+
+fn exit(code: usize) -> ProcessResult {
+    let result = match code {
+        0 => Ok(()),
+        code => Err(ProcessError::from_code(code).unwrap_or(ProcessError::Failure)),
+    };
+    sys::exit(result).unwrap();
+    unreachable!("Exited shell returned");
+}
+
+fn usage(usage: &str) -> ProcessResult {
+    println!("usage: {usage}");
+    Err(ProcessError::Failure)
+}
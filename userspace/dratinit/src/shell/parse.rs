@@ -0,0 +1,200 @@
+//! Splitting a command line into words and operators, then into a pipeline
+use core::{iter::Peekable, mem, str::Chars};
+use kanto::prelude::*;
+
+// Characters that end a word unless they're quoted or escaped
+const OPERATORS: [char; 4] = ['|', '&', '<', '>'];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Pipe,
+    Background,
+    RedirectIn,
+    RedirectOut,
+    RedirectAppend,
+}
+
+/// Where a command's standard output goes
+#[derive(Debug)]
+pub struct Output {
+    /// File to write to
+    pub path: String,
+    /// Add to the end of the file instead of truncating it
+    pub append: bool,
+}
+
+/// A single program and its arguments
+#[derive(Debug, Default)]
+pub struct Command {
+    /// Program name, followed by its arguments
+    pub argv: Vec<String>,
+    /// File to read standard input from
+    pub stdin: Option<String>,
+    /// File to write standard output to
+    pub stdout: Option<Output>,
+}
+
+/// Commands with each one's output piped into the next one's input
+#[derive(Debug)]
+pub struct Pipeline {
+    /// Never empty
+    pub commands: Vec<Command>,
+    /// Don't wait for it to finish
+    pub background: bool,
+}
+
+/// Parse a line, expanding `$NAME` and `$?` with `lookup`
+///
+/// Returns `None` if there's nothing to run
+pub fn parse(
+    line: &str,
+    lookup: impl Fn(&str) -> String,
+) -> Result<Option<Pipeline>, &'static str> {
+    let tokens = tokenize(line, &lookup)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut tokens = tokens.into_iter();
+    let mut commands = Vec::new();
+    let mut command = Command::default();
+    let mut background = false;
+
+    while let Some(token) = tokens.next() {
+        if background {
+            return Err("`&` must come last");
+        }
+        match token {
+            Token::Word(word) => command.argv.push(word),
+            Token::Pipe => commands.push(finish(mem::take(&mut command))?),
+            Token::Background => background = true,
+            Token::RedirectIn => command.stdin = Some(target(tokens.next())?),
+            Token::RedirectOut | Token::RedirectAppend => {
+                let append = token == Token::RedirectAppend;
+                let path = target(tokens.next())?;
+                command.stdout = Some(Output { path, append });
+            }
+        }
+    }
+    commands.push(finish(command)?);
+
+    Ok(Some(Pipeline {
+        commands,
+        background,
+    }))
+}
+
+fn finish(command: Command) -> Result<Command, &'static str> {
+    if command.argv.is_empty() {
+        return Err("missing command");
+    }
+    Ok(command)
+}
+
+fn target(token: Option<Token>) -> Result<String, &'static str> {
+    match token {
+        Some(Token::Word(path)) => Ok(path),
+        _ => Err("missing file to redirect"),
+    }
+}
+
+fn tokenize(line: &str, lookup: &impl Fn(&str) -> String) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(c) = chars.peek() else {
+            break;
+        };
+
+        if !OPERATORS.contains(c) {
+            if let Some(word) = word(&mut chars, lookup)? {
+                tokens.push(Token::Word(word));
+            }
+            continue;
+        }
+
+        let token = match chars.next() {
+            Some('|') => Token::Pipe,
+            Some('&') => Token::Background,
+            Some('<') => Token::RedirectIn,
+            _ if chars.next_if_eq(&'>').is_some() => Token::RedirectAppend,
+            _ => Token::RedirectOut,
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+// Read a word, handling quotes, escapes, and variables
+//
+// Returns `None` if it was only unquoted variables that expanded to nothing
+fn word(
+    chars: &mut Peekable<Chars>,
+    lookup: &impl Fn(&str) -> String,
+) -> Result<Option<String>, &'static str> {
+    let mut word = String::new();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !OPERATORS.contains(c)) {
+        match c {
+            '\\' => {
+                word.push(chars.next().ok_or("nothing to escape")?);
+                quoted = true;
+            }
+            '\'' => {
+                quoted = true;
+                loop {
+                    match chars.next().ok_or("unterminated quote")? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                quoted = true;
+                loop {
+                    match chars.next().ok_or("unterminated quote")? {
+                        '"' => break,
+                        '$' => expand(chars, lookup, &mut word),
+                        '\\' => {
+                            // Only these are special in double quotes, other backslashes are kept
+                            let c = chars.next().ok_or("unterminated quote")?;
+                            if !matches!(c, '"' | '\\' | '$') {
+                                word.push('\\');
+                            }
+                            word.push(c);
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '$' => expand(chars, lookup, &mut word),
+            c => word.push(c),
+        }
+    }
+
+    Ok((quoted || !word.is_empty()).then_some(word))
+}
+
+// Expand the variable following a `$`. A `$` not followed by a name is kept as is
+fn expand(chars: &mut Peekable<Chars>, lookup: &impl Fn(&str) -> String, word: &mut String) {
+    if chars.next_if_eq(&'?').is_some() {
+        word.push_str(&lookup("?"));
+        return;
+    }
+
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+        name.push(c);
+    }
+
+    if name.is_empty() {
+        word.push('$');
+    } else {
+        word.push_str(&lookup(&name));
+    }
+}
@@ -1,17 +1,65 @@
 //! KabutOS userspace test suite
 #![no_std]
 #![no_main]
+extern crate alloc;
+
+use alloc::sync::Arc;
 use core::{
-    sync::atomic::{AtomicU32, Ordering},
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
     time::Duration,
 };
-use kanto::{prelude::*, sys};
+use kanto::{
+    abi::{
+        fs::{FileDescriptor, FileType, OpenFlags, Whence},
+        memory::{MapFlags, Protection},
+        sched::Nice,
+        signal::Signal,
+        tty::TtyMode,
+        wait::{WaitFlags, WaitStatus},
+        Errno, ProcessError,
+    },
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    prelude::*,
+    process::{self, BlockReason, Command, ProcessState, Stdio},
+    sync::{Condvar, Mutex, Once},
+    sys::{self, SignalHandler},
+};
 
 const TESTS: &[fn()] = &[
     fork_and_wait,
     static_vars,
+    fork_copies_data,
+    fork_copies_heap,
     allocate_multiple_pages,
+    grow_stack,
+    segfault_only_kills_child,
+    illegal_instruction_only_kills_child,
     sleep_a_bit,
+    open_missing_file,
+    write_and_read_back,
+    exec_missing_file,
+    exec_program,
+    error_codes,
+    bad_pointers_rejected,
+    process_groups,
+    process_group_permissions,
+    tty_modes,
+    signal_handler_runs,
+    kill_child,
+    priorities,
+    threads_share_memory,
+    futex_sync,
+    pipe_captures_child_output,
+    stop_and_continue,
+    files_and_environment,
+    process_listing,
+    map_and_unmap_memory,
+    shared_memory_between_processes,
+    text_is_not_writable,
+    rodata_is_not_writable,
+    no_writable_executable_memory,
 ];
 
 fn fork_and_wait() {
@@ -23,13 +71,39 @@ fn fork_and_wait() {
     }
 }
 
-// This tests a bug that causes a StorePageFault when writing to a static variable.
-// I believe this to be a result of the data section being marked execute only
+// Static variables live in .data, which has to be writable
 fn static_vars() {
     static VAL: core::sync::atomic::AtomicU32 = AtomicU32::new(40);
     VAL.fetch_add(1, Ordering::Relaxed);
 }
 
+// A child's writes to static variables shouldn't be visible to the parent
+fn fork_copies_data() {
+    static VAL: AtomicU32 = AtomicU32::new(7);
+    let pid = sys::fork().unwrap();
+    if let Some(pid) = pid {
+        sys::wait_pid(pid).unwrap();
+        assert_eq!(VAL.load(Ordering::Relaxed), 7);
+    } else {
+        VAL.store(8, Ordering::Relaxed);
+        sys::exit_ok().unwrap();
+    }
+}
+
+// Same as above, but for heap memory allocated before the fork
+fn fork_copies_heap() {
+    let mut vec = Vec::new();
+    vec.resize(64, 1_u8);
+    let pid = sys::fork().unwrap();
+    if let Some(pid) = pid {
+        sys::wait_pid(pid).unwrap();
+        assert!(vec.iter().all(|byte| *byte == 1));
+    } else {
+        vec.fill(2);
+        sys::exit_ok().unwrap();
+    }
+}
+
 // This makes sure the global allocator can allocate more than a single page (4K)
 fn allocate_multiple_pages() {
     const PAGE_SIZE: usize = 0x1000;
@@ -40,11 +114,638 @@ fn allocate_multiple_pages() {
     assert_eq!(vec.len(), PAGE_SIZE);
 }
 
+// The stack should grow past its initial pages on demand
+fn grow_stack() {
+    fn recurse(depth: usize) -> usize {
+        let mut buffer = [0_u8; 1024];
+        buffer[depth % buffer.len()] = 1;
+        let buffer = core::hint::black_box(buffer);
+        if depth == 0 {
+            buffer.iter().map(|byte| usize::from(*byte)).sum()
+        } else {
+            recurse(depth - 1) + buffer.iter().map(|byte| usize::from(*byte)).sum::<usize>()
+        }
+    }
+    assert_eq!(recurse(32), 33);
+}
+
+// Run `f` in a child, which should be killed with `error` before it gets to return
+fn expect_child_killed(error: ProcessError, f: impl FnOnce()) {
+    if let Some(pid) = sys::fork().unwrap() {
+        assert_eq!(
+            sys::wait_pid_status(pid, WaitFlags::EXITED).unwrap(),
+            WaitStatus::Exited(Err(error))
+        );
+    } else {
+        f();
+        unreachable!("Should have been killed");
+    }
+}
+
+// A bad memory access should kill the offending process, and nothing else
+fn segfault_only_kills_child() {
+    expect_child_killed(ProcessError::SegmentationFault, || unsafe {
+        core::ptr::write_volatile(0x1000 as *mut u8, 0xff);
+    });
+}
+
+// Any other exception should also only take down the offender
+fn illegal_instruction_only_kills_child() {
+    expect_child_killed(ProcessError::Exception, || unsafe {
+        core::arch::asm!("unimp");
+    });
+}
+
 // Make sure timer works
 fn sleep_a_bit() {
     sys::sleep(Duration::from_millis(10)).unwrap();
 }
 
+// Opening a file that doesn't exist should fail rather than hand out a file descriptor
+fn open_missing_file() {
+    assert!(sys::open("/this/file/does/not/exist", OpenFlags::READ).is_err());
+}
+
+// Round trip some data through the filesystem
+fn write_and_read_back() {
+    const PATH: &str = "/gary.tmp";
+    const DATA: &[u8] = b"hello from gary";
+    let flags = OpenFlags::READ | OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE;
+    let fd = sys::open(PATH, flags).unwrap();
+    assert_eq!(sys::write(fd, DATA).unwrap(), DATA.len());
+
+    let mut buffer = [0; DATA.len()];
+    sys::seek(fd, 0, Whence::Start).unwrap();
+    assert_eq!(sys::read(fd, &mut buffer).unwrap(), DATA.len());
+    assert_eq!(&buffer, DATA);
+
+    sys::truncate(fd, 5).unwrap();
+    assert_eq!(sys::seek(fd, 0, Whence::End).unwrap(), 5);
+
+    sys::close(fd).unwrap();
+    sys::remove(PATH).unwrap();
+    assert!(sys::open(PATH, OpenFlags::READ).is_err());
+}
+
+// A failed exec should leave the caller running
+fn exec_missing_file() {
+    assert!(sys::exec("/this/file/does/not/exist", &["exist"], &[]).is_err());
+}
+
+// A program run from disk gets the arguments and environment it was given, and its parent gets
+// its exit status
+fn exec_program() {
+    let output = Command::new("/bin/echo")
+        .args(["hello", "world"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hello world\n");
+
+    let output = Command::new("/bin/env")
+        .env_clear()
+        .env("GARY", "yes")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"GARY=yes\n");
+
+    // `env` doesn't take arguments
+    let status = Command::new("/bin/env")
+        .arg("extra")
+        .stderr(Stdio::piped())
+        .status()
+        .unwrap();
+    assert_eq!(status.result(), Err(ProcessError::Failure));
+}
+
+// Syscalls should report why they failed
+fn error_codes() {
+    let err = sys::open("/this/file/does/not/exist", OpenFlags::READ).unwrap_err();
+    assert_eq!(err.errno(), Errno::NotFound);
+
+    let fd = sys::open("/this_file_is_closed", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+    sys::close(fd).unwrap();
+    assert_eq!(
+        sys::close(fd).unwrap_err().errno(),
+        Errno::BadFileDescriptor
+    );
+    sys::remove("/this_file_is_closed").unwrap();
+}
+
+// The kernel should refuse to touch memory we don't have access to
+fn bad_pointers_rejected() {
+    let fd = sys::open(
+        "/bad_pointers",
+        OpenFlags::READ | OpenFlags::WRITE | OpenFlags::CREATE,
+    )
+    .unwrap();
+    sys::write(fd, b"Hello").unwrap();
+    sys::seek(fd, 0, Whence::Start).unwrap();
+
+    // Never mapped, in either direction
+    let unmapped = unsafe { core::slice::from_raw_parts_mut(0x1000 as *mut u8, 16) };
+    assert_eq!(
+        sys::write(fd, unmapped).unwrap_err().errno(),
+        Errno::BadAddress
+    );
+    assert_eq!(
+        sys::read(fd, unmapped).unwrap_err().errno(),
+        Errno::BadAddress
+    );
+
+    sys::close(fd).unwrap();
+    sys::remove("/bad_pointers").unwrap();
+}
+
+// Children start out in their parent's process group, and can leave for their own
+fn process_groups() {
+    let pgid = sys::process_group(None).unwrap();
+    if let Some(pid) = sys::fork().unwrap() {
+        sys::wait_pid(pid).unwrap();
+        assert_eq!(sys::process_group(None).unwrap(), pgid);
+    } else {
+        assert_eq!(sys::process_group(None).unwrap(), pgid);
+        sys::set_process_group(None, None).unwrap();
+        assert_eq!(sys::process_group(None).unwrap(), sys::get_pid().unwrap());
+        sys::exit_ok().unwrap();
+    }
+}
+
+// Processes can only be moved by themselves or their parent, and only the foreground group
+// can hand off the terminal
+fn process_group_permissions() {
+    let parent = sys::get_pid().unwrap();
+    let pgid = sys::process_group(None).unwrap();
+    sys::set_foreground_group(Some(pgid)).unwrap();
+    if let Some(pid) = sys::fork().unwrap() {
+        assert_eq!(
+            sys::wait_pid_status(pid, WaitFlags::EXITED).unwrap(),
+            WaitStatus::Exited(Ok(()))
+        );
+        sys::set_foreground_group(None).unwrap();
+    } else {
+        assert_eq!(
+            sys::set_process_group(Some(parent), None)
+                .unwrap_err()
+                .errno(),
+            Errno::NotPermitted
+        );
+        sys::set_process_group(None, None).unwrap();
+        let own = sys::process_group(None).unwrap();
+        assert_eq!(
+            sys::set_foreground_group(Some(own)).unwrap_err().errno(),
+            Errno::NotPermitted
+        );
+        sys::exit_ok().unwrap();
+    }
+}
+
+fn tty_modes() {
+    let mode = sys::tty_mode().unwrap();
+    sys::set_tty_mode(TtyMode::RAW | TtyMode::ECHO).unwrap();
+    assert_eq!(sys::tty_mode().unwrap(), TtyMode::RAW | TtyMode::ECHO);
+    sys::set_tty_mode(mode).unwrap();
+}
+
+// A signal to ourselves should be handled on the way out of the syscall
+fn signal_handler_runs() {
+    static RECEIVED: AtomicU32 = AtomicU32::new(0);
+    fn on_signal(signal: Signal) {
+        assert_eq!(signal, Signal::User1);
+        RECEIVED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    sys::set_signal_handler(Signal::User1, SignalHandler::Handler(on_signal)).unwrap();
+    sys::kill(sys::get_pid().unwrap(), Signal::User1).unwrap();
+    assert_eq!(RECEIVED.load(Ordering::SeqCst), 1);
+
+    sys::set_signal_handler(Signal::User1, SignalHandler::Ignore).unwrap();
+    sys::kill(sys::get_pid().unwrap(), Signal::User1).unwrap();
+    assert_eq!(RECEIVED.load(Ordering::SeqCst), 1);
+    sys::set_signal_handler(Signal::User1, SignalHandler::Default).unwrap();
+}
+
+// The default action for most signals is to terminate
+fn kill_child() {
+    if let Some(pid) = sys::fork().unwrap() {
+        sys::kill(pid, Signal::Terminate).unwrap();
+        assert_eq!(
+            sys::wait_pid_status(pid, WaitFlags::EXITED).unwrap(),
+            WaitStatus::Exited(Err(ProcessError::Killed(Signal::Terminate)))
+        );
+        assert_eq!(
+            sys::kill(pid, Signal::Terminate).unwrap_err().errno(),
+            Errno::NoSuchProcess
+        );
+    } else {
+        loop {
+            let _ = sys::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+// Children inherit niceness, and a busy nice child shouldn't keep us from running
+fn priorities() {
+    let nice = Nice::try_from(5_isize).unwrap();
+    assert_eq!(sys::priority(None).unwrap(), Nice::DEFAULT);
+    assert!(Nice::try_from(20_isize).is_err());
+    sys::set_priority(None, nice).unwrap();
+
+    if let Some(pid) = sys::fork().unwrap() {
+        sys::wait_pid(pid).unwrap();
+    } else {
+        assert_eq!(sys::priority(None).unwrap(), nice);
+        sys::exit_ok().unwrap();
+    }
+
+    // Only parents may change their children's niceness, not the other way around
+    let parent = sys::get_pid().unwrap();
+    if let Some(pid) = sys::fork().unwrap() {
+        assert_eq!(
+            sys::wait_pid_status(pid, WaitFlags::EXITED).unwrap(),
+            WaitStatus::Exited(Ok(()))
+        );
+    } else {
+        assert_eq!(
+            sys::set_priority(Some(parent), Nice::MIN)
+                .unwrap_err()
+                .errno(),
+            Errno::NotPermitted
+        );
+        sys::exit_ok().unwrap();
+    }
+
+    if let Some(pid) = sys::fork().unwrap() {
+        sys::set_priority(Some(pid), Nice::MAX).unwrap();
+        assert_eq!(sys::priority(Some(pid)).unwrap(), Nice::MAX);
+        sys::sleep(Duration::from_millis(10)).unwrap();
+        sys::kill(pid, Signal::Terminate).unwrap();
+        let _ = sys::wait_pid(pid);
+    } else {
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+// Threads see each other's writes
+fn threads_share_memory() {
+    const THREADS: usize = 4;
+    const INCREMENTS: usize = 1000;
+    let counter = Arc::new(AtomicUsize::new(0));
+
+    let tids: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let counter = counter.clone();
+            sys::spawn_thread(move || {
+                for _ in 0..INCREMENTS {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .unwrap()
+        })
+        .collect();
+    for tid in tids {
+        sys::join_thread(tid).unwrap();
+    }
+    assert_eq!(counter.load(Ordering::Relaxed), THREADS * INCREMENTS);
+
+    // The main thread's ID is the PID, and a thread can't join itself
+    let pid = sys::get_pid().unwrap();
+    assert_eq!(
+        sys::join_thread(pid).unwrap_err().errno(),
+        Errno::InvalidArgument
+    );
+}
+
+fn futex_sync() {
+    const THREADS: usize = 4;
+    const INCREMENTS: usize = 1000;
+    let shared = Arc::new((Mutex::new(0), Condvar::new()));
+
+    let tids: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let shared = shared.clone();
+            sys::spawn_thread(move || {
+                let (count, done) = &*shared;
+                for _ in 0..INCREMENTS {
+                    *count.lock() += 1;
+                }
+                done.notify_all();
+            })
+            .unwrap()
+        })
+        .collect();
+
+    let (count, done) = &*shared;
+    let mut guard = count.lock();
+    while *guard < THREADS * INCREMENTS {
+        guard = done.wait(guard);
+    }
+    drop(guard);
+    for tid in tids {
+        sys::join_thread(tid).unwrap();
+    }
+
+    // Nobody wakes this word, so the wait runs out
+    let word = AtomicU32::new(0);
+    assert_eq!(
+        sys::futex_wait(&word, 0, Some(Duration::from_millis(10)))
+            .unwrap_err()
+            .errno(),
+        Errno::TimedOut
+    );
+    // The word has already changed, so there's nothing to wait for
+    sys::futex_wait(&word, 1, None).unwrap();
+
+    // A timeout too long to count down still ends when the word changes
+    let word = Arc::new(AtomicU32::new(0));
+    let tid = {
+        let word = word.clone();
+        sys::spawn_thread(move || {
+            sys::sleep(Duration::from_millis(10)).unwrap();
+            word.store(1, Ordering::SeqCst);
+            sys::futex_wake(&word, 1).unwrap();
+        })
+        .unwrap()
+    };
+    while word.load(Ordering::SeqCst) == 0 {
+        sys::futex_wait(&word, 0, Some(Duration::MAX)).unwrap();
+    }
+    sys::join_thread(tid).unwrap();
+
+    static ONCE: Once = Once::new();
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let tids: Vec<_> = (0..THREADS)
+        .map(|_| {
+            sys::spawn_thread(|| {
+                ONCE.call_once(|| {
+                    CALLS.fetch_add(1, Ordering::Relaxed);
+                });
+                assert!(ONCE.is_completed());
+            })
+            .unwrap()
+        })
+        .collect();
+    for tid in tids {
+        sys::join_thread(tid).unwrap();
+    }
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+}
+
+// A child's output can be captured through a pipe, even more than the pipe holds at once
+fn pipe_captures_child_output() {
+    const LINES: usize = 1000;
+    let (read_fd, write_fd) = sys::pipe().unwrap();
+
+    if let Some(pid) = sys::fork().unwrap() {
+        // Otherwise we'd never see the end of the file
+        sys::close(write_fd).unwrap();
+
+        let mut output = Vec::new();
+        let mut buffer = [0; 64];
+        loop {
+            let len = sys::read(read_fd, &mut buffer).unwrap();
+            if len == 0 {
+                break;
+            }
+            output.extend_from_slice(&buffer[..len]);
+        }
+        sys::wait_pid(pid).unwrap();
+
+        let expected: String = (0..LINES).map(|i| alloc::format!("line {i}\n")).collect();
+        assert_eq!(core::str::from_utf8(&output).unwrap(), expected);
+        sys::close(read_fd).unwrap();
+    } else {
+        sys::dup2(write_fd, FileDescriptor::STDOUT).unwrap();
+        sys::close(read_fd).unwrap();
+        sys::close(write_fd).unwrap();
+        for i in 0..LINES {
+            println!("line {i}");
+        }
+        sys::exit_ok().unwrap();
+    }
+
+    // Nobody is left to read what's written
+    let (read_fd, write_fd) = sys::pipe().unwrap();
+    sys::close(read_fd).unwrap();
+    assert_eq!(
+        sys::write(write_fd, b"anyone?").unwrap_err().errno(),
+        Errno::BrokenPipe
+    );
+}
+
+// Stopped children can be waited on, then continued and waited on again until they exit
+fn stop_and_continue() {
+    if let Some(pid) = sys::fork().unwrap() {
+        sys::kill(pid, Signal::TerminalStop).unwrap();
+        assert_eq!(
+            sys::wait_pid_status(pid, WaitFlags::STOPPED).unwrap(),
+            WaitStatus::Stopped(Signal::TerminalStop)
+        );
+
+        sys::kill(pid, Signal::Continue).unwrap();
+        sys::kill(pid, Signal::Terminate).unwrap();
+        assert_eq!(
+            sys::wait_pid_status(pid, WaitFlags::STOPPED).unwrap(),
+            WaitStatus::Exited(Err(ProcessError::Killed(Signal::Terminate)))
+        );
+    } else {
+        loop {
+            let _ = sys::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+// kanto's std-like layer over files, directories, and the environment
+fn files_and_environment() {
+    const DIR: &str = "/gary_dir";
+    fs::create_dir_all("/gary_dir/sub").unwrap();
+    fs::write("/gary_dir/a.txt", "hello").unwrap();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open("/gary_dir/a.txt")
+        .unwrap();
+    writeln!(file, " world").unwrap();
+    drop(file);
+    assert_eq!(
+        fs::read_to_string("/gary_dir/a.txt").unwrap(),
+        "hello world\n"
+    );
+
+    let mut entries: Vec<_> = fs::read_dir(DIR)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let name = entry.file_name().to_string();
+            (name, entry.file_type(), entry.metadata().len())
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        entries,
+        [
+            ("a.txt".to_string(), FileType::File, 12),
+            ("sub".to_string(), FileType::Directory, 0)
+        ]
+    );
+
+    // Relative paths are resolved against the working directory
+    env::set_current_dir(DIR).unwrap();
+    assert!(fs::metadata("sub").unwrap().is_dir());
+    fs::rename("a.txt", "sub/b.txt").unwrap();
+    assert_eq!(fs::read("../gary_dir/sub/b.txt").unwrap(), b"hello world\n");
+    fs::remove_file("sub/b.txt").unwrap();
+    fs::remove_dir("sub").unwrap();
+    env::set_current_dir("/").unwrap();
+    fs::remove_dir(DIR).unwrap();
+    assert_eq!(fs::metadata(DIR).unwrap_err().errno(), Errno::NotFound);
+
+    assert_eq!(env::args().next().as_deref(), Some("gary"));
+    assert_eq!(
+        Command::new("no-such-program").spawn().unwrap_err().errno(),
+        Errno::NotFound
+    );
+}
+
+// Processes show up in listings, with what blocked ones are waiting for
+fn process_listing() {
+    let me = process::info(None).unwrap();
+    assert_eq!(me.pid, sys::get_pid().unwrap());
+    assert_eq!(me.name, "gary");
+    assert_eq!(me.state, ProcessState::Running);
+    assert!(me.resident_pages > 0);
+
+    if let Some(pid) = sys::fork().unwrap() {
+        // Give it a chance to fall asleep
+        sys::sleep(Duration::from_millis(50)).unwrap();
+        let processes = process::list().unwrap();
+        assert!(processes
+            .windows(2)
+            .all(|pair| u16::from(pair[0].pid) < u16::from(pair[1].pid)));
+
+        let child = processes.iter().find(|info| info.pid == pid).unwrap();
+        assert_eq!(child.ppid, Some(me.pid));
+        assert_eq!(child.state, ProcessState::Blocked);
+        assert_eq!(child.blocked_on, Some(BlockReason::Sleep));
+        assert_eq!(child.name, "gary");
+
+        sys::kill(pid, Signal::Kill).unwrap();
+        let _ = sys::wait_pid(pid);
+    } else {
+        loop {
+            let _ = sys::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+// Mapped memory is zeroed and paged in when touched, and unmapping gives the pages back
+fn map_and_unmap_memory() {
+    const LEN: usize = 4 * 0x1000;
+    let read_write = Protection::READ | Protection::WRITE;
+    let resident = || process::info(None).unwrap().resident_pages;
+
+    let addr = sys::mmap(None, LEN, read_write, MapFlags::NONE).unwrap();
+    let before = resident();
+    let memory = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, LEN) };
+    assert!(memory.iter().all(|&byte| byte == 0));
+    memory.fill(0xab);
+    let touched = resident();
+    assert!(touched >= before + 4);
+
+    assert_eq!(
+        sys::mmap(Some(addr), LEN, read_write, MapFlags::FIXED)
+            .unwrap_err()
+            .errno(),
+        Errno::AlreadyExists
+    );
+    sys::munmap(addr, LEN).unwrap();
+    assert!(resident() <= touched - 4);
+
+    // The same spot is free again
+    let fixed = sys::mmap(Some(addr), LEN, read_write, MapFlags::FIXED).unwrap();
+    assert_eq!(fixed, addr);
+    sys::munmap(addr, LEN).unwrap();
+
+    expect_child_killed(ProcessError::SegmentationFault, || unsafe {
+        core::ptr::write_volatile(addr as *mut u8, 0xff);
+    });
+}
+
+// Processes mapping the same segment see each other's writes, even after a fork
+fn shared_memory_between_processes() {
+    const NAME: &str = "gary-shared";
+    const LEN: usize = 2 * 0x1000;
+    let read_write = Protection::READ | Protection::WRITE;
+
+    sys::create_shared_memory(NAME, LEN).unwrap();
+    assert_eq!(
+        sys::create_shared_memory(NAME, LEN).unwrap_err().errno(),
+        Errno::AlreadyExists
+    );
+    let inherited = sys::map_shared_memory(NAME, None, read_write, MapFlags::NONE).unwrap();
+
+    if let Some(pid) = sys::fork().unwrap() {
+        sys::wait_pid(pid).unwrap();
+        let memory = unsafe { core::slice::from_raw_parts(inherited as *const u8, LEN) };
+        assert_eq!(memory[0], 1);
+        assert_eq!(memory[LEN - 1], 2);
+    } else {
+        // Through the mapping from before the fork, and through a fresh one
+        unsafe {
+            core::ptr::write_volatile(inherited as *mut u8, 1);
+        }
+        let fresh = sys::map_shared_memory(NAME, None, read_write, MapFlags::NONE).unwrap();
+        assert_ne!(fresh, inherited);
+        unsafe {
+            core::ptr::write_volatile((fresh + LEN - 1) as *mut u8, 2);
+        }
+        sys::munmap(fresh, LEN).unwrap();
+        sys::exit_ok().unwrap();
+    }
+
+    // The memory outlives its name until it's unmapped
+    sys::remove_shared_memory(NAME).unwrap();
+    assert_eq!(
+        unsafe { core::ptr::read_volatile(inherited as *const u8) },
+        1
+    );
+    assert_eq!(
+        sys::map_shared_memory(NAME, None, read_write, MapFlags::NONE)
+            .unwrap_err()
+            .errno(),
+        Errno::NotFound
+    );
+    sys::munmap(inherited, LEN).unwrap();
+}
+
+// Program text is read-execute, so overwriting code is a segmentation fault
+fn text_is_not_writable() {
+    expect_child_killed(ProcessError::SegmentationFault, || unsafe {
+        core::ptr::write_volatile(text_is_not_writable as *mut u8, 0);
+    });
+}
+
+// Constants are read-only
+fn rodata_is_not_writable() {
+    static MESSAGE: &str = "read only";
+    expect_child_killed(ProcessError::SegmentationFault, || unsafe {
+        core::ptr::write_volatile(MESSAGE.as_ptr() as *mut u8, 0);
+    });
+}
+
+// Memory is never both writable and executable
+fn no_writable_executable_memory() {
+    let prot = Protection::WRITE | Protection::EXECUTE;
+    assert_eq!(
+        sys::mmap(None, 0x1000, prot, MapFlags::NONE)
+            .unwrap_err()
+            .errno(),
+        Errno::NotPermitted
+    );
+}
+
 #[no_mangle]
 extern "C" fn main() {
     for test in TESTS {
@@ -1,55 +1,115 @@
-use crate::sys;
-use core::alloc::Layout;
-use lock_api::RawMutex;
+use crate::{sync::RawMutex, sys};
+use core::alloc::{GlobalAlloc, Layout};
+use krabby_abi::memory::{MapFlags, Protection};
 use talc::*;
 
+const PAGE_SIZE: usize = 0x1000;
+// Smallest chunk of memory mapped for the heap at a time, so small allocations don't each cost a
+// syscall
+const MIN_HEAP_SIZE: usize = 16 * PAGE_SIZE;
+// Most chunks mapped at once. Each is at least as big as all the others together, so this is
+// plenty
+const MAX_HEAPS: usize = 32;
+// Freeing something at least this big checks for chunks that can be unmapped
+const RELEASE_THRESHOLD: usize = MIN_HEAP_SIZE;
+
+// Threads share the heap, so they take turns with it
 #[global_allocator]
-static ALLOCATOR: Talck<DummyMutex, UserClaimer> = Talc::new(UserClaimer(Span::empty())).lock();
+static ALLOCATOR: Allocator = Allocator(Talc::new(UserClaimer::new()).lock());
+
+struct Allocator(Talck<RawMutex, UserClaimer>);
 
-struct UserClaimer(Span);
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.alloc(layout)
+    }
 
-fn sbrk(size: usize) -> Result<usize, ()> {
-    sys::request_memory(size).map_err(|_| ())
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout);
+        if layout.size() >= RELEASE_THRESHOLD {
+            UserClaimer::release(&mut self.0.lock());
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.0.realloc(ptr, layout, new_size)
+    }
 }
 
-impl OomHandler for UserClaimer {
-    fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()> {
-        let base = sbrk(0)?;
-        let new_breakline = sbrk(layout.size())?;
-        let growth = new_breakline.checked_sub(base).unwrap();
+// Chunks of memory from mmap that make up the heap
+struct UserClaimer {
+    heaps: [Span; MAX_HEAPS],
+}
 
-        let old_span = talc.oom_handler.0;
+impl UserClaimer {
+    const fn new() -> Self {
+        Self {
+            heaps: [Span::empty(); MAX_HEAPS],
+        }
+    }
 
-        if old_span.is_empty() {
-            unsafe {
-                talc.oom_handler.0 = talc.claim(Span::from_base_size(base as *mut u8, growth))?;
+    // Unmap every chunk with nothing allocated in it
+    fn release(talc: &mut Talc<Self>) {
+        // The first chunk holds talc's bins, so it's kept for good
+        for i in 1..MAX_HEAPS {
+            let heap = talc.oom_handler.heaps[i];
+            if heap.is_empty() || !talc.get_allocated_span(heap).is_empty() {
+                continue;
             }
-        } else {
+            let Some((base, acme)) = heap.get_base_acme() else {
+                continue;
+            };
             unsafe {
-                talc.oom_handler.0 = talc.extend(old_span, old_span.extend(0, growth));
+                talc.truncate(heap, Span::empty());
             }
-        }
+            talc.oom_handler.heaps[i] = Span::empty();
 
-        Ok(())
+            // Talc may have used less than was mapped to keep the heap aligned
+            let start = (base as usize) & !(PAGE_SIZE - 1);
+            let end = (acme as usize).next_multiple_of(PAGE_SIZE);
+            let _ = sys::munmap(start, end - start);
+        }
     }
 }
 
-struct DummyMutex;
-
-unsafe impl RawMutex for DummyMutex {
-    type GuardMarker = lock_api::GuardNoSend;
-
-    const INIT: Self = DummyMutex;
-
-    fn lock(&self) {}
-
-    fn try_lock(&self) -> bool {
-        true
-    }
+impl OomHandler for UserClaimer {
+    fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()> {
+        let slot = talc
+            .oom_handler
+            .heaps
+            .iter()
+            .position(|heap| heap.is_empty())
+            .ok_or(())?;
+        let mapped: usize = talc.oom_handler.heaps.iter().map(|heap| heap.size()).sum();
 
-    unsafe fn unlock(&self) {}
+        // Leave room for talc's own bookkeeping and for aligning the allocation
+        let needed = layout
+            .size()
+            .checked_add(layout.align() + PAGE_SIZE)
+            .ok_or(())?;
+        let size = needed
+            .max(mapped)
+            .max(MIN_HEAP_SIZE)
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(())?;
+        let base = sys::mmap(
+            None,
+            size,
+            Protection::READ | Protection::WRITE,
+            MapFlags::NONE,
+        )
+        .map_err(|_| ())?;
 
-    fn is_locked(&self) -> bool {
-        false
+        let span = Span::from_base_size(base as *mut u8, size);
+        match unsafe { talc.claim(span) } {
+            Ok(heap) => {
+                talc.oom_handler.heaps[slot] = heap;
+                Ok(())
+            }
+            Err(()) => {
+                let _ = sys::munmap(base, size);
+                Err(())
+            }
+        }
     }
 }
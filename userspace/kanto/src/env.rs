@@ -0,0 +1,128 @@
+//! Arguments and environment variables the process was started with. Modelled on `std::env`
+use crate::{fs, io, sync::Mutex};
+use alloc::{
+    string::String,
+    vec::{self, Vec},
+};
+use core::ffi::{c_char, CStr};
+use krabby_abi::Errno;
+
+// Filled in from what the kernel left on the stack, before main runs
+static ARGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+static VARS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+// Called from `start` with the NULL-terminated argv and envp arrays
+pub(crate) unsafe fn init(argv: *const *const c_char, envp: *const *const c_char) {
+    let mut args = ARGS.lock();
+    for arg in strings(argv) {
+        args.push(arg.into());
+    }
+
+    let mut vars = VARS.lock();
+    for var in strings(envp) {
+        // Skip anything that isn't NAME=value
+        if let Some((name, value)) = var.split_once('=') {
+            vars.push((name.into(), value.into()));
+        }
+    }
+}
+
+unsafe fn strings(mut array: *const *const c_char) -> impl Iterator<Item = &'static str> {
+    core::iter::from_fn(move || {
+        let string = *array;
+        if string.is_null() {
+            return None;
+        }
+        array = array.add(1);
+        Some(CStr::from_ptr(string).to_str().unwrap_or_default())
+    })
+}
+
+/// Iterator over the process's arguments, from [args]
+#[derive(Debug)]
+pub struct Args(vec::IntoIter<String>);
+
+impl Iterator for Args {
+    type Item = String;
+    fn next(&mut self) -> Option<String> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+impl DoubleEndedIterator for Args {
+    fn next_back(&mut self) -> Option<String> {
+        self.0.next_back()
+    }
+}
+
+/// Get the arguments the process was started with. The first is usually the program's name
+pub fn args() -> Args {
+    Args(ARGS.lock().clone().into_iter())
+}
+
+/// Iterator over environment variables, from [vars]
+#[derive(Debug)]
+pub struct Vars(vec::IntoIter<(String, String)>);
+
+impl Iterator for Vars {
+    type Item = (String, String);
+    fn next(&mut self) -> Option<(String, String)> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// Get a snapshot of every environment variable, as name and value pairs
+pub fn vars() -> Vars {
+    Vars(VARS.lock().clone().into_iter())
+}
+
+/// Get the value of an environment variable
+pub fn var(name: &str) -> Option<String> {
+    VARS.lock()
+        .iter()
+        .find(|(var, _)| var == name)
+        .map(|(_, value)| value.clone())
+}
+
+/// Set an environment variable. Programs spawned afterwards inherit it
+pub fn set_var(name: &str, value: &str) {
+    let mut vars = VARS.lock();
+    match vars.iter_mut().find(|(var, _)| var == name) {
+        Some((_, existing)) => *existing = value.into(),
+        None => vars.push((name.into(), value.into())),
+    }
+}
+
+/// Unset an environment variable
+pub fn remove_var(name: &str) {
+    VARS.lock().retain(|(var, _)| var != name);
+}
+
+/// Get the working directory that relative paths are resolved against
+///
+/// The kernel doesn't have one, so like shells do, it's kept in `$PWD`. It defaults to `/`
+pub fn current_dir() -> String {
+    var("PWD")
+        .filter(|dir| dir.starts_with('/'))
+        .unwrap_or_else(|| "/".into())
+}
+
+/// Change the working directory, if `path` is a directory
+pub fn set_current_dir(path: &str) -> io::Result<()> {
+    let path = fs::absolute(path);
+    if !fs::metadata(&path)?.is_dir() {
+        return Err(Errno::NotADirectory.into());
+    }
+    set_var("PWD", &path);
+    Ok(())
+}
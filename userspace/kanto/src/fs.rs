@@ -0,0 +1,415 @@
+//! Files and directories. Modelled on `std::fs`
+//!
+//! Relative paths are resolved against [env::current_dir], since the kernel only takes absolute
+//! paths
+use crate::{
+    env,
+    io::{self, Read, Seek, SeekFrom, Write},
+    sys,
+};
+use alloc::{format, string::String, vec::Vec};
+use krabby_abi::{
+    fs::{DirEntry as RawDirEntry, FileDescriptor, OpenFlags},
+    Errno,
+};
+
+pub use krabby_abi::fs::FileType;
+
+// Big enough for the longest possible FAT32 name
+const READ_DIR_BUFFER_SIZE: usize = 1024;
+
+/// An open file, closed when dropped
+#[derive(Debug)]
+pub struct File {
+    fd: FileDescriptor,
+}
+
+impl File {
+    /// Open an existing file for reading
+    pub fn open(path: &str) -> io::Result<File> {
+        OpenOptions::new().read(true).open(path)
+    }
+
+    /// Open a file for writing, creating it if it doesn't exist and truncating it if it does
+    pub fn create(path: &str) -> io::Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    }
+
+    /// Take ownership of an open file descriptor
+    pub fn from_fd(fd: FileDescriptor) -> File {
+        File { fd }
+    }
+
+    /// Get the file descriptor, which stays owned by the `File`
+    pub fn fd(&self) -> FileDescriptor {
+        self.fd
+    }
+
+    /// Give up ownership of the file descriptor without closing it
+    pub fn into_fd(self) -> FileDescriptor {
+        let fd = self.fd;
+        core::mem::forget(self);
+        fd
+    }
+
+    /// Shrink or extend the file to `size` bytes
+    pub fn set_len(&self, size: usize) -> io::Result<()> {
+        sys::truncate(self.fd, size)
+    }
+
+    /// Get the file's type and size
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        let position = io::seek_fd(self.fd, SeekFrom::Current(0))?;
+        let len = io::seek_fd(self.fd, SeekFrom::End(0))?;
+        io::seek_fd(self.fd, SeekFrom::Start(position))?;
+        Ok(Metadata {
+            file_type: FileType::File,
+            len: len as u64,
+        })
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        sys::read(self.fd, buffer)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        sys::write(self.fd, buffer)
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<usize> {
+        io::seek_fd(self.fd, position)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = sys::close(self.fd);
+    }
+}
+
+/// Options for opening a file, like `fopen` modes
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+}
+
+impl OpenOptions {
+    /// Options that open nothing until some are set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open for reading
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Open for writing
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Open for writing, with every write going to the end of the file
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Empty the file when opening it for writing
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Create the file if it doesn't exist
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Open the file at `path` with these options
+    pub fn open(&self, path: &str) -> io::Result<File> {
+        if !self.read && !self.write && !self.append {
+            return Err(Errno::InvalidArgument.into());
+        }
+
+        let mut flags = 0;
+        for (set, flag) in [
+            (self.read, OpenFlags::READ),
+            (self.write || self.append, OpenFlags::WRITE),
+            (self.append, OpenFlags::APPEND),
+            (self.truncate, OpenFlags::TRUNCATE),
+            (self.create, OpenFlags::CREATE),
+        ] {
+            if set {
+                flags |= usize::from(flag);
+            }
+        }
+        let flags = OpenFlags::try_from(flags)?;
+
+        let fd = sys::open(&absolute(path), flags)?;
+        Ok(File { fd })
+    }
+}
+
+/// Type and size of a file or directory
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    file_type: FileType,
+    len: u64,
+}
+
+impl Metadata {
+    /// What it is
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Whether it's a directory
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
+    /// Whether it's a regular file
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+
+    /// Size in bytes. Zero for directories
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the size is zero
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Get the type and size of whatever is at `path`
+pub fn metadata(path: &str) -> io::Result<Metadata> {
+    let path = absolute(path);
+    match sys::open(&path, OpenFlags::READ | OpenFlags::DIRECTORY) {
+        Ok(fd) => {
+            let _ = sys::close(fd);
+            Ok(Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+            })
+        }
+        Err(err) if err.errno() == Errno::NotADirectory => File::open(&path)?.metadata(),
+        Err(err) => Err(err),
+    }
+}
+
+/// Iterator over the entries of a directory, from [read_dir]
+#[derive(Debug)]
+pub struct ReadDir {
+    path: String,
+    dir: File,
+    buffer: Vec<u8>,
+    // Range of `buffer` that hasn't been handed out yet
+    start: usize,
+    end: usize,
+}
+
+impl Iterator for ReadDir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            match sys::read_dir(self.dir.fd, &mut self.buffer) {
+                Ok(0) => return None,
+                Ok(len) => (self.start, self.end) = (0, len),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        let (entry, len) = match RawDirEntry::decode(&self.buffer[self.start..self.end]) {
+            Ok(decoded) => decoded,
+            Err(err) => return Some(Err(err.into())),
+        };
+        self.start += len;
+
+        let path = match self.path.as_str() {
+            "/" => format!("/{}", entry.name),
+            dir => format!("{dir}/{}", entry.name),
+        };
+        Some(Ok(DirEntry {
+            path,
+            metadata: Metadata {
+                file_type: entry.file_type,
+                len: entry.size,
+            },
+        }))
+    }
+}
+
+/// Entry of a directory, from [ReadDir]
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    path: String,
+    metadata: Metadata,
+}
+
+impl DirEntry {
+    /// Absolute path of the entry
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Name of the entry, without its directory
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// What the entry is
+    pub fn file_type(&self) -> FileType {
+        self.metadata.file_type
+    }
+
+    /// Type and size of the entry
+    pub fn metadata(&self) -> Metadata {
+        self.metadata
+    }
+}
+
+/// List the entries of a directory, not including `.` and `..`
+pub fn read_dir(path: &str) -> io::Result<ReadDir> {
+    let path = absolute(path);
+    let fd = sys::open(&path, OpenFlags::READ | OpenFlags::DIRECTORY)?;
+    Ok(ReadDir {
+        path,
+        dir: File { fd },
+        buffer: alloc::vec![0; READ_DIR_BUFFER_SIZE],
+        start: 0,
+        end: 0,
+    })
+}
+
+/// Read a whole file
+pub fn read(path: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Read a whole file as UTF-8
+pub fn read_to_string(path: &str) -> io::Result<String> {
+    let mut string = String::new();
+    File::open(path)?.read_to_string(&mut string)?;
+    Ok(string)
+}
+
+/// Replace the contents of a file, creating it if it doesn't exist
+pub fn write(path: &str, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    File::create(path)?.write_all(contents.as_ref())
+}
+
+/// Copy a file's contents to another, returning the number of bytes copied
+pub fn copy(from: &str, to: &str) -> io::Result<u64> {
+    let mut from = File::open(from)?;
+    let mut to = File::create(to)?;
+    let mut buffer = [0; 512];
+    let mut copied = 0;
+    loop {
+        match from.read(&mut buffer)? {
+            0 => return Ok(copied),
+            len => {
+                to.write_all(&buffer[..len])?;
+                copied += len as u64;
+            }
+        }
+    }
+}
+
+/// Move a file
+///
+/// There's no rename syscall, so this copies the file and removes the original. It doesn't work
+/// on directories
+pub fn rename(from: &str, to: &str) -> io::Result<()> {
+    if absolute(from) == absolute(to) {
+        return Ok(());
+    }
+    copy(from, to)?;
+    remove_file(from)
+}
+
+/// Create a directory
+pub fn create_dir(path: &str) -> io::Result<()> {
+    sys::mkdir(&absolute(path))
+}
+
+/// Create a directory, along with any missing parents
+pub fn create_dir_all(path: &str) -> io::Result<()> {
+    let mut prefix = String::new();
+    for component in absolute(path)
+        .split('/')
+        .filter(|component| !component.is_empty())
+    {
+        prefix.push('/');
+        prefix.push_str(component);
+        match sys::mkdir(&prefix) {
+            Err(err) if err.errno() == Errno::AlreadyExists => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Remove a file
+pub fn remove_file(path: &str) -> io::Result<()> {
+    let path = absolute(path);
+    if metadata(&path)?.is_dir() {
+        return Err(Errno::IsADirectory.into());
+    }
+    sys::remove(&path)
+}
+
+/// Remove an empty directory
+pub fn remove_dir(path: &str) -> io::Result<()> {
+    let path = absolute(path);
+    if !metadata(&path)?.is_dir() {
+        return Err(Errno::NotADirectory.into());
+    }
+    sys::remove(&path)
+}
+
+/// Make a path absolute by resolving it against [env::current_dir], and remove any `.` and `..`
+pub fn absolute(path: &str) -> String {
+    let cwd = if path.starts_with('/') {
+        None
+    } else {
+        Some(env::current_dir())
+    };
+
+    let mut parts = Vec::new();
+    for part in cwd.iter().map(String::as_str).chain([path]) {
+        for part in part.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                part => parts.push(part),
+            }
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
@@ -0,0 +1,192 @@
+//! Traits for reading and writing, and the standard streams. Modelled on `std::io`
+use crate::sys;
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use krabby_abi::{
+    fs::{FileDescriptor, Whence},
+    Errno,
+};
+
+/// Error from an I/O operation
+pub type Error = sys::SyscallError;
+
+/// Result type for I/O operations
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Something bytes can be read from
+pub trait Read {
+    /// Read into `buffer`, returning the number of bytes read
+    ///
+    /// Zero bytes are read at the end of the input
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize>;
+
+    /// Read everything up to the end of the input onto the end of `buffer`
+    ///
+    /// Returns the number of bytes read
+    fn read_to_end(&mut self, buffer: &mut Vec<u8>) -> Result<usize> {
+        let start = buffer.len();
+        let mut chunk = [0; 512];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(buffer.len() - start),
+                len => buffer.extend_from_slice(&chunk[..len]),
+            }
+        }
+    }
+
+    /// Read everything up to the end of the input onto the end of `string`
+    ///
+    /// Fails with [Errno::InvalidArgument] if it isn't UTF-8, in which case `string` is left
+    /// unchanged
+    fn read_to_string(&mut self, string: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let len = self.read_to_end(&mut bytes)?;
+        let read = core::str::from_utf8(&bytes).map_err(|_| Errno::InvalidArgument)?;
+        string.push_str(read);
+        Ok(len)
+    }
+
+    /// Fill all of `buffer`, failing with [Errno::EndOfInput] if the input ends first
+    fn read_exact(&mut self, mut buffer: &mut [u8]) -> Result<()> {
+        while !buffer.is_empty() {
+            match self.read(buffer)? {
+                0 => return Err(Errno::EndOfInput.into()),
+                len => buffer = &mut buffer[len..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Something bytes can be written to
+pub trait Write {
+    /// Write some of `buffer`, returning the number of bytes written
+    fn write(&mut self, buffer: &[u8]) -> Result<usize>;
+
+    /// Make sure everything written so far has reached its destination
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Write all of `buffer`, failing with [Errno::WriteZero] if nothing more can be written
+    fn write_all(&mut self, mut buffer: &[u8]) -> Result<()> {
+        while !buffer.is_empty() {
+            match self.write(buffer)? {
+                0 => return Err(Errno::WriteZero.into()),
+                len => buffer = &buffer[len..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Write formatted text, for the `write!` and `writeln!` macros
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<()> {
+        // Keep the actual error, since fmt::Error can't carry one
+        struct Adapter<'a, W: ?Sized> {
+            inner: &'a mut W,
+            result: Result<()>,
+        }
+
+        impl<W: Write + ?Sized> fmt::Write for Adapter<'_, W> {
+            fn write_str(&mut self, string: &str) -> fmt::Result {
+                self.result = self.inner.write_all(string.as_bytes());
+                self.result.map_err(|_| fmt::Error)
+            }
+        }
+
+        let mut adapter = Adapter {
+            inner: self,
+            result: Ok(()),
+        };
+        match fmt::write(&mut adapter, args) {
+            Ok(()) => Ok(()),
+            Err(_) => adapter.result.and(Err(Errno::Failure.into())),
+        }
+    }
+}
+
+/// Position to seek to, for [Seek::seek]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// Bytes from the start
+    Start(usize),
+    /// Bytes from the end, usually negative
+    End(isize),
+    /// Bytes from the current position
+    Current(isize),
+}
+
+/// Something with a position that can be moved
+pub trait Seek {
+    /// Move the position, returning the new one as bytes from the start
+    fn seek(&mut self, position: SeekFrom) -> Result<usize>;
+
+    /// Go back to the start
+    fn rewind(&mut self) -> Result<()> {
+        self.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    /// Get the current position
+    fn stream_position(&mut self) -> Result<usize> {
+        self.seek(SeekFrom::Current(0))
+    }
+}
+
+// Seek a file descriptor. Shared by everything with one
+pub(crate) fn seek_fd(fd: FileDescriptor, position: SeekFrom) -> Result<usize> {
+    let (offset, whence) = match position {
+        SeekFrom::Start(offset) => {
+            let offset = isize::try_from(offset).map_err(|_| Errno::InvalidArgument)?;
+            (offset, Whence::Start)
+        }
+        SeekFrom::End(offset) => (offset, Whence::End),
+        SeekFrom::Current(offset) => (offset, Whence::Current),
+    };
+    sys::seek(fd, offset, whence)
+}
+
+/// Handle to the process's standard input
+#[derive(Copy, Clone, Debug)]
+pub struct Stdin;
+
+/// Handle to the process's standard output
+#[derive(Copy, Clone, Debug)]
+pub struct Stdout;
+
+/// Handle to the process's standard error
+#[derive(Copy, Clone, Debug)]
+pub struct Stderr;
+
+/// Get a handle to standard input
+pub fn stdin() -> Stdin {
+    Stdin
+}
+
+/// Get a handle to standard output
+pub fn stdout() -> Stdout {
+    Stdout
+}
+
+/// Get a handle to standard error
+pub fn stderr() -> Stderr {
+    Stderr
+}
+
+impl Read for Stdin {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        sys::read(FileDescriptor::STDIN, buffer)
+    }
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        sys::write(FileDescriptor::STDOUT, buffer)
+    }
+}
+
+impl Write for Stderr {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        sys::write(FileDescriptor::STDERR, buffer)
+    }
+}
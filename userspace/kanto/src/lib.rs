@@ -8,18 +8,30 @@ global_asm!(include_str!("crt.S"));
 mod allocator;
 extern crate alloc;
 
+pub mod env;
+pub mod fs;
+pub mod io;
+pub mod process;
 #[doc(hidden)]
 pub mod serial;
+pub mod sync;
 pub mod sys;
 pub mod prelude {
     //! Userspace prelude
-    pub use crate::{print, println};
-    pub use alloc::{boxed::Box, string::String, vec::Vec};
+    pub use crate::{eprint, eprintln, print, println};
+    pub use alloc::{
+        boxed::Box,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
 }
 
 pub use krabby_abi as abi;
 
 use core::arch::global_asm;
+use core::ffi::c_char;
 use core::panic::PanicInfo;
 
 #[panic_handler]
@@ -28,6 +40,12 @@ fn panic(info: &PanicInfo) -> ! {
     loop {}
 }
 
+// Called by `start` before main, with what the kernel left on the stack
+#[no_mangle]
+unsafe extern "C" fn _init(_argc: usize, argv: *const *const c_char, envp: *const *const c_char) {
+    env::init(argv, envp);
+}
+
 // Automatic exit after program ends
 #[no_mangle]
 extern "C" fn _exit() {
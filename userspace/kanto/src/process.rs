@@ -0,0 +1,399 @@
+//! Starting programs, waiting for them to finish, and seeing what processes are up to. Modelled
+//! on `std::process`
+use crate::{
+    env,
+    fs::{self, File},
+    io::{self, Read},
+    sys,
+};
+use alloc::{format, string::String, vec::Vec};
+use core::{
+    fmt::{self, Display},
+    time::Duration,
+};
+use krabby_abi::{
+    fs::FileDescriptor,
+    process::ProcessInfo as RawProcessInfo,
+    sched::Nice,
+    signal::Signal,
+    wait::{WaitFlags, WaitStatus},
+    Errno, Pid, ProcessError, ProcessResult,
+};
+
+pub use krabby_abi::process::{BlockReason, ProcessState};
+
+// Room for plenty of processes per syscall. A single one only needs a few dozen bytes
+const PROCESS_INFO_BUFFER_SIZE: usize = 1024;
+
+/// Builder for starting a program
+#[derive(Debug)]
+pub struct Command {
+    program: String,
+    args: Vec<String>,
+    // Starts as the current environment, unless cleared
+    env: Vec<(String, String)>,
+    stdin: Stdio,
+    stdout: Stdio,
+    stderr: Stdio,
+}
+
+impl Command {
+    /// Start building a command for `program`
+    ///
+    /// A program without a `/` in it is looked for in each directory in `$PATH`
+    pub fn new(program: &str) -> Self {
+        Command {
+            program: program.into(),
+            args: Vec::new(),
+            env: env::vars().collect(),
+            stdin: Stdio::inherit(),
+            stdout: Stdio::inherit(),
+            stderr: Stdio::inherit(),
+        }
+    }
+
+    /// Add an argument
+    pub fn arg(&mut self, arg: &str) -> &mut Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add several arguments
+    pub fn args<'a>(&mut self, args: impl IntoIterator<Item = &'a str>) -> &mut Self {
+        self.args.extend(args.into_iter().map(String::from));
+        self
+    }
+
+    /// Set an environment variable for the program
+    pub fn env(&mut self, name: &str, value: &str) -> &mut Self {
+        self.env_remove(name);
+        self.env.push((name.into(), value.into()));
+        self
+    }
+
+    /// Leave an environment variable out of the program's environment
+    pub fn env_remove(&mut self, name: &str) -> &mut Self {
+        self.env.retain(|(var, _)| var != name);
+        self
+    }
+
+    /// Don't pass on any of the current environment
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.env.clear();
+        self
+    }
+
+    /// Where the program's standard input comes from
+    pub fn stdin(&mut self, stdin: impl Into<Stdio>) -> &mut Self {
+        self.stdin = stdin.into();
+        self
+    }
+
+    /// Where the program's standard output goes
+    pub fn stdout(&mut self, stdout: impl Into<Stdio>) -> &mut Self {
+        self.stdout = stdout.into();
+        self
+    }
+
+    /// Where the program's standard error goes
+    pub fn stderr(&mut self, stderr: impl Into<Stdio>) -> &mut Self {
+        self.stderr = stderr.into();
+        self
+    }
+
+    /// Start the program, without waiting for it
+    ///
+    /// Fails with [Errno::NotFound] if there's no such program
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        let path = self.find_program()?;
+
+        // Each standard stream gets the child's end, and maybe one for us
+        let mut ends = Vec::new();
+        for (stdio, target) in [
+            (&mut self.stdin, FileDescriptor::STDIN),
+            (&mut self.stdout, FileDescriptor::STDOUT),
+            (&mut self.stderr, FileDescriptor::STDERR),
+        ] {
+            let (child_end, our_end) = match stdio {
+                Stdio(StdioKind::Inherit) => (None, None),
+                Stdio(StdioKind::Piped) => {
+                    let (read, write) = sys::pipe()?;
+                    let (read, write) = (File::from_fd(read), File::from_fd(write));
+                    if target == FileDescriptor::STDIN {
+                        (Some(read), Some(write))
+                    } else {
+                        (Some(write), Some(read))
+                    }
+                }
+                // The file is handed over, so another spawn inherits instead
+                Stdio(StdioKind::File(file)) => (file.take(), None),
+            };
+            ends.push((child_end, our_end, target));
+        }
+
+        let Some(pid) = sys::fork()? else {
+            for (child_end, our_end, target) in ends {
+                drop(our_end);
+                if let Some(file) = child_end {
+                    if file.fd() != target {
+                        let _ = sys::dup2(file.fd(), target);
+                    } else {
+                        // Already in place, so don't close it
+                        file.into_fd();
+                    }
+                }
+            }
+
+            let argv: Vec<&str> = [self.program.as_str()]
+                .into_iter()
+                .chain(self.args.iter().map(String::as_str))
+                .collect();
+            let env: Vec<String> = self
+                .env
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            let env: Vec<&str> = env.iter().map(String::as_str).collect();
+            let _ = sys::exec(&path, &argv, &env);
+            sys::exit(Err(ProcessError::Failure)).unwrap();
+            unreachable!("Exited child returned");
+        };
+
+        // Only the child needed its ends, which are closed as they're dropped
+        let mut ours = ends.into_iter().map(|(_, our_end, _)| our_end);
+        Ok(Child {
+            pid,
+            stdin: ours.next().flatten(),
+            stdout: ours.next().flatten(),
+            stderr: ours.next().flatten(),
+        })
+    }
+
+    /// Run the program and wait for it to finish
+    pub fn status(&mut self) -> io::Result<ExitStatus> {
+        self.spawn()?.wait()
+    }
+
+    /// Run the program, capturing its standard output, and wait for it to finish
+    pub fn output(&mut self) -> io::Result<Output> {
+        self.stdout(Stdio::piped());
+        let mut child = self.spawn()?;
+        let mut stdout = Vec::new();
+        if let Some(mut pipe) = child.stdout.take() {
+            pipe.read_to_end(&mut stdout)?;
+        }
+        let status = child.wait()?;
+        Ok(Output { status, stdout })
+    }
+
+    // Find the program, checking it exists so a missing one is reported by spawn
+    fn find_program(&self) -> io::Result<String> {
+        if self.program.contains('/') {
+            let path = fs::absolute(&self.program);
+            return fs::metadata(&path).map(|_| path);
+        }
+
+        let path_var = self
+            .env
+            .iter()
+            .find(|(name, _)| name == "PATH")
+            .map_or("/bin", |(_, value)| value.as_str());
+        path_var
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| fs::absolute(&format!("{dir}/{}", self.program)))
+            .find(|path| fs::metadata(path).is_ok_and(|metadata| metadata.is_file()))
+            .ok_or_else(|| Errno::NotFound.into())
+    }
+}
+
+/// Where one of a child's standard streams goes
+#[derive(Debug)]
+pub struct Stdio(StdioKind);
+
+#[derive(Debug)]
+enum StdioKind {
+    Inherit,
+    Piped,
+    File(Option<File>),
+}
+
+impl Stdio {
+    /// Share the parent's stream
+    pub fn inherit() -> Self {
+        Stdio(StdioKind::Inherit)
+    }
+
+    /// Connect the stream to a pipe, whose other end is in [Child]
+    pub fn piped() -> Self {
+        Stdio(StdioKind::Piped)
+    }
+}
+
+impl From<File> for Stdio {
+    fn from(file: File) -> Self {
+        Stdio(StdioKind::File(Some(file)))
+    }
+}
+
+/// A started program
+#[derive(Debug)]
+pub struct Child {
+    pid: Pid,
+    /// Our end of its standard input, if it was [Stdio::piped]
+    pub stdin: Option<File>,
+    /// Our end of its standard output, if it was [Stdio::piped]
+    pub stdout: Option<File>,
+    /// Our end of its standard error, if it was [Stdio::piped]
+    pub stderr: Option<File>,
+}
+
+impl Child {
+    /// Process ID of the child
+    pub fn id(&self) -> Pid {
+        self.pid
+    }
+
+    /// Wait for the child to exit
+    ///
+    /// Its standard input is closed first, so it isn't left waiting for more
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        drop(self.stdin.take());
+        match sys::wait_pid_status(self.pid, WaitFlags::EXITED)? {
+            WaitStatus::Exited(result) => Ok(ExitStatus(result)),
+            // Not asked for, so it won't happen
+            WaitStatus::Stopped(_) => Err(Errno::Failure.into()),
+        }
+    }
+
+    /// Kill the child
+    pub fn kill(&mut self) -> io::Result<()> {
+        sys::kill(self.pid, Signal::Kill)
+    }
+}
+
+/// How a program finished
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExitStatus(ProcessResult);
+
+impl ExitStatus {
+    /// Whether it exited successfully
+    pub fn success(&self) -> bool {
+        self.0.is_ok()
+    }
+
+    /// Exit code, which is zero for success
+    pub fn code(&self) -> usize {
+        match self.0 {
+            Ok(()) => 0,
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Why it failed, if it did
+    pub fn result(&self) -> ProcessResult {
+        self.0
+    }
+}
+
+impl Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self.0 {
+            Ok(()) => write!(f, "exit code: 0"),
+            Err(err) => write!(f, "exit code: {}: {err}", usize::from(err)),
+        }
+    }
+}
+
+/// What a finished program printed, from [Command::output]
+#[derive(Debug)]
+pub struct Output {
+    /// How it finished
+    pub status: ExitStatus,
+    /// Everything written to its standard output
+    pub stdout: Vec<u8>,
+}
+
+/// Get the process ID of the calling process
+pub fn id() -> Pid {
+    sys::get_pid().unwrap()
+}
+
+/// Exit the process
+pub fn exit(result: ProcessResult) -> ! {
+    sys::exit(result).unwrap();
+    unreachable!("Exited process returned");
+}
+
+/// Snapshot of a process, from [info] or [list]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Process ID
+    pub pid: Pid,
+    /// Parent process, if it was forked
+    pub ppid: Option<Pid>,
+    /// Process group
+    pub pgid: Pid,
+    /// What it's doing
+    pub state: ProcessState,
+    /// What it's waiting for, if it's blocked
+    pub blocked_on: Option<BlockReason>,
+    /// Niceness of the main thread
+    pub nice: Nice,
+    /// Number of threads
+    pub threads: usize,
+    /// Time all of its threads have spent running
+    pub cpu_time: Duration,
+    /// Pages of memory mapped into its address space
+    pub resident_pages: u64,
+    /// Name of the program, without its directory
+    pub name: String,
+}
+
+impl From<RawProcessInfo<'_>> for ProcessInfo {
+    fn from(info: RawProcessInfo<'_>) -> Self {
+        ProcessInfo {
+            pid: info.pid,
+            ppid: info.ppid,
+            pgid: info.pgid,
+            state: info.state,
+            blocked_on: info.blocked_on,
+            nice: info.nice,
+            threads: info.threads.into(),
+            cpu_time: info.cpu_time,
+            resident_pages: info.resident_pages,
+            name: info.name.into(),
+        }
+    }
+}
+
+/// Get a snapshot of process `pid`, or of this process if `None`
+pub fn info(pid: Option<Pid>) -> io::Result<ProcessInfo> {
+    let mut buffer = [0; PROCESS_INFO_BUFFER_SIZE];
+    let len = sys::process_info(pid, &mut buffer)?;
+    let (info, _) = RawProcessInfo::decode(&buffer[..len])?;
+    Ok(info.into())
+}
+
+/// Get a snapshot of every process, in PID order
+///
+/// Processes can come and go while they're being listed, so it may not be quite consistent
+pub fn list() -> io::Result<Vec<ProcessInfo>> {
+    let mut buffer = [0; PROCESS_INFO_BUFFER_SIZE];
+    let mut processes = Vec::new();
+    let mut first = 0;
+    loop {
+        let len = sys::list_processes(first, &mut buffer)?;
+        if len == 0 {
+            return Ok(processes);
+        }
+
+        let mut rest = &buffer[..len];
+        while !rest.is_empty() {
+            let (info, info_len) = RawProcessInfo::decode(rest)?;
+            first = usize::from(info.pid) + 1;
+            processes.push(info.into());
+            rest = &rest[info_len..];
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! Home of the `Serial` object - used to write to serial
-use crate::sys::puts;
+use crate::sys::{self, puts};
 use core::fmt::{Error, Write};
+use krabby_abi::fs::FileDescriptor;
 
 #[doc(hidden)]
 #[derive(Copy, Clone, Default, Debug)]
@@ -13,6 +14,20 @@ impl Write for Serial {
     }
 }
 
+#[doc(hidden)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SerialError {}
+
+impl Write for SerialError {
+    fn write_str(&mut self, s: &str) -> Result<(), Error> {
+        let mut bytes = s.as_bytes();
+        while let Ok(written @ 1..) = sys::write(FileDescriptor::STDERR, bytes) {
+            bytes = &bytes[written..];
+        }
+        Ok(())
+    }
+}
+
 // Macros copied from <https://osblog.stephenmarz.com/ch2.html>
 
 /// See <https://doc.rust-lang.org/std/macro.print.html>
@@ -40,3 +55,29 @@ macro_rules! println
         let _ = write!(Serial::default(), "\n");
 	});
 }
+
+/// Like [print!], but to standard error
+#[macro_export]
+macro_rules! eprint
+{
+	($($args:tt)+) => ({
+			use core::fmt::Write;
+            use $crate::serial::SerialError;
+            let _ = write!(SerialError::default(), $($args)+);
+	});
+}
+
+/// Like [println!], but to standard error
+#[macro_export]
+macro_rules! eprintln
+{
+	() => ({
+		eprint!("\n")
+	});
+	($($args:tt)+) => ({
+        use core::fmt::Write;
+        use $crate::serial::SerialError;
+        let _ = write!(SerialError::default(), $($args)+);
+        let _ = write!(SerialError::default(), "\n");
+	});
+}
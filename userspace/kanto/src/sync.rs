@@ -0,0 +1,189 @@
+//! Blocking synchronization primitives, built on futexes
+
+use crate::sys;
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+use krabby_abi::Errno;
+
+// Mutex states
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2;
+
+/// Raw mutex that puts waiting threads to sleep instead of spinning
+pub struct RawMutex {
+    state: AtomicU32,
+}
+
+unsafe impl lock_api::RawMutex for RawMutex {
+    type GuardMarker = lock_api::GuardSend;
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = RawMutex {
+        state: AtomicU32::new(UNLOCKED),
+    };
+
+    fn lock(&self) {
+        if self.try_lock() {
+            return;
+        }
+        // Mark it contended so the holder knows to wake us
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            let _ = sys::futex_wait(&self.state, CONTENDED, None);
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            let _ = sys::futex_wake(&self.state, 1);
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != UNLOCKED
+    }
+}
+
+/// Mutual exclusion lock shared between threads
+pub type Mutex<T> = lock_api::Mutex<RawMutex, T>;
+
+/// Guard for a locked [Mutex]
+pub type MutexGuard<'a, T> = lock_api::MutexGuard<'a, RawMutex, T>;
+
+/// Condition variable, for waiting until another thread changes what a [Mutex] protects
+pub struct Condvar {
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    /// Create a condition variable
+    pub const fn new() -> Self {
+        Condvar {
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Unlock the mutex and block until notified, then lock it again
+    ///
+    /// Wakeups may be spurious, so check the condition in a loop
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_inner(guard, None).0
+    }
+
+    /// Like [Condvar::wait], but give up after `timeout`
+    ///
+    /// Also returns whether it timed out
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'a, T>, bool) {
+        self.wait_inner(guard, Some(timeout))
+    }
+
+    fn wait_inner<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Option<Duration>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        // Read the sequence before unlocking, so a notify in between is never missed
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = MutexGuard::mutex(&guard);
+        drop(guard);
+
+        let timed_out = matches!(
+            sys::futex_wait(&self.seq, seq, timeout),
+            Err(err) if err.errno() == Errno::TimedOut
+        );
+        (mutex.lock(), timed_out)
+    }
+
+    /// Wake one waiting thread
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        let _ = sys::futex_wake(&self.seq, 1);
+    }
+
+    /// Wake every waiting thread
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        let _ = sys::futex_wake(&self.seq, usize::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Once states
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const WAITING: u32 = 2;
+const COMPLETE: u32 = 3;
+
+/// One-time initialization. Other threads calling in block until it's done
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    /// Create a `Once` that hasn't run yet
+    pub const fn new() -> Self {
+        Once {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    /// Run `f` if no call has yet, otherwise wait for the one that did to finish
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() {
+            return;
+        }
+
+        match self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                f();
+                if self.state.swap(COMPLETE, Ordering::Release) == WAITING {
+                    let _ = sys::futex_wake(&self.state, usize::MAX);
+                }
+            }
+            Err(_) => loop {
+                match self.state.compare_exchange(
+                    RUNNING,
+                    WAITING,
+                    Ordering::Acquire,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) | Err(WAITING) => {
+                        let _ = sys::futex_wait(&self.state, WAITING, None);
+                    }
+                    Err(_) => break,
+                }
+            },
+        }
+    }
+
+    /// Whether a call has finished
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,6 +1,20 @@
 //! KabutOS syscalls
-use core::time::Duration;
-use krabby_abi::{KrabbyAbiError, Pid, ProcessResult, Syscall};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt::{self, Display},
+    sync::atomic::AtomicU32,
+    time::Duration,
+};
+use krabby_abi::{
+    fs::{FileDescriptor, OpenFlags, Whence},
+    futex::NO_TIMEOUT,
+    memory::{MapFlags, Protection},
+    sched::Nice,
+    signal::{Signal, SIGNAL_DEFAULT, SIGNAL_IGNORE},
+    tty::TtyMode,
+    wait::{WaitFlags, WaitStatus},
+    Errno, KrabbyAbiError, Pid, ProcessResult, Syscall,
+};
 
 #[repr(C)]
 struct RawSyscallResult {
@@ -22,24 +36,61 @@ extern "C" {
 }
 
 /// Error type returned from kernel
-#[derive(Debug)]
-pub struct SyscallError;
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyscallError {
+    errno: Errno,
+}
+
+impl SyscallError {
+    /// Why the syscall failed
+    pub fn errno(&self) -> Errno {
+        self.errno
+    }
+}
+
+impl From<Errno> for SyscallError {
+    fn from(errno: Errno) -> Self {
+        Self { errno }
+    }
+}
+
+impl Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.errno)
+    }
+}
 
 /// Result type for syscalls
 pub type SyscallResult<T = ()> = Result<T, SyscallError>;
 
 impl From<KrabbyAbiError> for SyscallError {
-    fn from(_err: KrabbyAbiError) -> Self {
-        Self
+    fn from(err: KrabbyAbiError) -> Self {
+        Errno::from(err).into()
     }
 }
 
 fn syscall(id: Syscall, arg0: usize, arg1: usize) -> SyscallResult<usize> {
-    let res = unsafe { asm_syscall(arg0, arg1, 0, 0, 0, 0, 0, id as usize) };
+    syscall3(id, arg0, arg1, 0)
+}
+
+fn syscall3(id: Syscall, arg0: usize, arg1: usize, arg2: usize) -> SyscallResult<usize> {
+    syscall6(id, arg0, arg1, arg2, 0, 0, 0)
+}
+
+fn syscall6(
+    id: Syscall,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+    arg5: usize,
+) -> SyscallResult<usize> {
+    let res = unsafe { asm_syscall(arg0, arg1, arg2, arg3, arg4, arg5, 0, id as usize) };
     if res.err == 0 {
         Ok(res.val)
     } else {
-        Err(SyscallError)
+        Err(Errno::n(res.err).unwrap_or(Errno::Failure).into())
     }
 }
 
@@ -49,13 +100,13 @@ pub fn putc(c: char) -> SyscallResult {
     Ok(())
 }
 
-/// Print a string (newline sold separately)
+/// Print a string to standard output (newline sold separately)
 pub fn puts(s: &str) -> SyscallResult {
-    syscall(
-        Syscall::PutString,
-        core::ptr::from_ref(s) as *const u8 as usize,
-        s.len(),
-    )?;
+    let mut bytes = s.as_bytes();
+    while !bytes.is_empty() {
+        let written = write(FileDescriptor::STDOUT, bytes)?;
+        bytes = &bytes[written..];
+    }
     Ok(())
 }
 
@@ -68,7 +119,31 @@ pub fn getc() -> SyscallResult<char> {
 
 /// Get process PID
 pub fn get_pid() -> SyscallResult<Pid> {
-    Ok(syscall(Syscall::Pinfo, 0, 0)?.try_into()?)
+    Ok(syscall(Syscall::GetPid, 0, 0)?.try_into()?)
+}
+
+/// Read an encoded [ProcessInfo](krabby_abi::process::ProcessInfo) for process `pid`, or this
+/// process if `None`, returning the number of bytes read
+pub fn process_info(pid: Option<Pid>, buffer: &mut [u8]) -> SyscallResult<usize> {
+    syscall3(
+        Syscall::Pinfo,
+        pid.map_or(0, usize::from),
+        buffer.as_mut_ptr() as usize,
+        buffer.len(),
+    )
+}
+
+/// Read encoded [ProcessInfo](krabby_abi::process::ProcessInfo)s for processes with PIDs of at
+/// least `first`, returning the number of bytes read
+///
+/// Only whole entries are read. Zero bytes are read once there are no more processes
+pub fn list_processes(first: usize, buffer: &mut [u8]) -> SyscallResult<usize> {
+    syscall3(
+        Syscall::ListProcesses,
+        first,
+        buffer.as_mut_ptr() as usize,
+        buffer.len(),
+    )
 }
 
 /// Fork process - return child PID
@@ -94,19 +169,40 @@ pub fn exit_ok() -> SyscallResult<()> {
 }
 
 /// Wait for PID
+///
+/// Fails if the process failed
 pub fn wait_pid(pid: Pid) -> SyscallResult<()> {
-    syscall(Syscall::WaitPid, pid.into(), 0)?;
-    Ok(())
+    exit_status(wait_pid_status(pid, WaitFlags::EXITED)?)
+}
+
+/// Wait for PID to exit, or to stop as well if `flags` ask, and find out which
+pub fn wait_pid_status(pid: Pid, flags: WaitFlags) -> SyscallResult<WaitStatus> {
+    Ok(syscall(Syscall::WaitPid, pid.into(), flags.into())?.try_into()?)
+}
+
+// Turn how a process or thread ended into success or failure
+fn exit_status(status: WaitStatus) -> SyscallResult<()> {
+    match status {
+        WaitStatus::Exited(Ok(())) => Ok(()),
+        _ => Err(Errno::Failure.into()),
+    }
 }
 
 /// Sleep for a duration
 pub fn sleep(duration: Duration) -> SyscallResult<()> {
-    let secs = usize::try_from(duration.as_secs()).map_err(|_| SyscallError)?;
+    let secs = usize::try_from(duration.as_secs())
+        .map_err(|_| SyscallError::from(Errno::InvalidArgument))?;
     let nanos = usize::try_from(duration.subsec_nanos()).expect("usize should hold u32");
     syscall(Syscall::Sleep, secs, nanos)?;
     Ok(())
 }
 
+/// Get the time since the machine booted
+pub fn uptime() -> SyscallResult<Duration> {
+    let nanos = syscall(Syscall::Uptime, 0, 0)?;
+    Ok(Duration::from_nanos(nanos as u64))
+}
+
 /// Request the heap to be extended.
 ///
 /// This is similar to `sbrk` and used to implement an allocator. Do not call this directly (unless
@@ -117,6 +213,196 @@ pub fn request_memory(bytes: usize) -> SyscallResult<usize> {
     syscall(Syscall::RequestMemory, bytes, 0)
 }
 
+/// Map `len` bytes of zeroed memory, returning its address
+///
+/// `addr` must be page aligned. With [MapFlags::FIXED] the memory goes exactly there, otherwise
+/// it's only a hint and the kernel picks somewhere free if it's taken
+pub fn mmap(
+    addr: Option<usize>,
+    len: usize,
+    prot: Protection,
+    flags: MapFlags,
+) -> SyscallResult<usize> {
+    syscall6(
+        Syscall::Mmap,
+        addr.unwrap_or(0),
+        len,
+        prot.into(),
+        flags.into(),
+        0,
+        0,
+    )
+}
+
+/// Unmap memory mapped with [mmap], returning it to the kernel
+///
+/// Touching it afterwards is a segmentation fault
+pub fn munmap(addr: usize, len: usize) -> SyscallResult {
+    syscall(Syscall::Munmap, addr, len)?;
+    Ok(())
+}
+
+/// Create a shared memory segment of at least `size` bytes called `name`
+///
+/// Fails if there's already one by that name
+pub fn create_shared_memory(name: &str, size: usize) -> SyscallResult {
+    syscall3(
+        Syscall::CreateSharedMemory,
+        name.as_ptr() as usize,
+        name.len(),
+        size,
+    )?;
+    Ok(())
+}
+
+/// Map all of the shared memory segment called `name`, returning its address
+///
+/// `addr` and `flags` work like they do for [mmap]. Unmap it with [munmap]
+pub fn map_shared_memory(
+    name: &str,
+    addr: Option<usize>,
+    prot: Protection,
+    flags: MapFlags,
+) -> SyscallResult<usize> {
+    syscall6(
+        Syscall::MapSharedMemory,
+        name.as_ptr() as usize,
+        name.len(),
+        addr.unwrap_or(0),
+        prot.into(),
+        flags.into(),
+        0,
+    )
+}
+
+/// Remove the name of a shared memory segment, freeing it once it's unmapped everywhere
+pub fn remove_shared_memory(name: &str) -> SyscallResult {
+    syscall(
+        Syscall::RemoveSharedMemory,
+        name.as_ptr() as usize,
+        name.len(),
+    )?;
+    Ok(())
+}
+
+/// Open a file with the given [OpenFlags], returning its file descriptor
+pub fn open(path: &str, flags: OpenFlags) -> SyscallResult<FileDescriptor> {
+    let fd = syscall3(
+        Syscall::Open,
+        path.as_ptr() as usize,
+        path.len(),
+        flags.into(),
+    )?;
+    Ok(fd.try_into()?)
+}
+
+/// Read from a file descriptor, returning the number of bytes read
+///
+/// Zero bytes are read at the end of the file
+pub fn read(fd: FileDescriptor, buffer: &mut [u8]) -> SyscallResult<usize> {
+    syscall3(
+        Syscall::Read,
+        fd.into(),
+        buffer.as_mut_ptr() as usize,
+        buffer.len(),
+    )
+}
+
+/// Close a file descriptor
+pub fn close(fd: FileDescriptor) -> SyscallResult {
+    syscall(Syscall::Close, fd.into(), 0)?;
+    Ok(())
+}
+
+/// Reposition a file descriptor's offset, returning the new offset
+pub fn seek(fd: FileDescriptor, offset: isize, whence: Whence) -> SyscallResult<usize> {
+    syscall3(Syscall::Seek, fd.into(), offset as usize, whence as usize)
+}
+
+/// Write to a file descriptor, returning the number of bytes written
+pub fn write(fd: FileDescriptor, buffer: &[u8]) -> SyscallResult<usize> {
+    syscall3(
+        Syscall::Write,
+        fd.into(),
+        buffer.as_ptr() as usize,
+        buffer.len(),
+    )
+}
+
+/// Create a pipe, returning its read end and its write end
+///
+/// Reads block until there's something to read, and hit the end of the file once every copy of
+/// the write end is closed
+pub fn pipe() -> SyscallResult<(FileDescriptor, FileDescriptor)> {
+    let mut fds = [0usize; 2];
+    syscall(Syscall::Pipe, fds.as_mut_ptr() as usize, 0)?;
+    Ok((fds[0].try_into()?, fds[1].try_into()?))
+}
+
+/// Make `new` refer to the same open file as `old`, closing whatever was open at `new`
+pub fn dup2(old: FileDescriptor, new: FileDescriptor) -> SyscallResult<FileDescriptor> {
+    Ok(syscall(Syscall::Dup2, old.into(), new.into())?.try_into()?)
+}
+
+/// Read encoded [DirEntry](krabby_abi::fs::DirEntry)s from a directory opened with
+/// [OpenFlags::DIRECTORY], returning the number of bytes read
+///
+/// Only whole entries are read. Zero bytes are read once every entry has been
+pub fn read_dir(fd: FileDescriptor, buffer: &mut [u8]) -> SyscallResult<usize> {
+    syscall3(
+        Syscall::ReadDirectory,
+        fd.into(),
+        buffer.as_mut_ptr() as usize,
+        buffer.len(),
+    )
+}
+
+/// Shrink or extend an open file to `size` bytes
+pub fn truncate(fd: FileDescriptor, size: usize) -> SyscallResult {
+    syscall(Syscall::Truncate, fd.into(), size)?;
+    Ok(())
+}
+
+/// Create a directory
+pub fn mkdir(path: &str) -> SyscallResult {
+    syscall(Syscall::MakeDirectory, path.as_ptr() as usize, path.len())?;
+    Ok(())
+}
+
+/// Remove a file or empty directory
+pub fn remove(path: &str) -> SyscallResult {
+    syscall(Syscall::Remove, path.as_ptr() as usize, path.len())?;
+    Ok(())
+}
+
+/// Replace the current process image with the program at `path`
+///
+/// The PID and open file descriptors are kept. This only returns on failure
+pub fn exec(path: &str, args: &[&str], env: &[&str]) -> SyscallResult {
+    let argv = nul_terminated(args);
+    let envp = nul_terminated(env);
+    syscall6(
+        Syscall::Exec,
+        path.as_ptr() as usize,
+        path.len(),
+        argv.as_ptr() as usize,
+        argv.len(),
+        envp.as_ptr() as usize,
+        envp.len(),
+    )?;
+    Ok(())
+}
+
+// Pack strings into a single buffer, each one terminated by NUL
+fn nul_terminated(strings: &[&str]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for string in strings {
+        bytes.extend_from_slice(string.as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
 /// Power off the device
 pub fn power_off() -> SyscallResult<usize> {
     syscall(Syscall::PowerOff, 0, 0)
@@ -126,3 +412,183 @@ pub fn power_off() -> SyscallResult<usize> {
 pub fn test() -> SyscallResult<usize> {
     syscall(Syscall::Test, 0, 0)
 }
+
+/// Move process `pid` into process group `pgid`
+///
+/// `None` for `pid` means this process, and `None` for `pgid` means a new group named after the
+/// process being moved. Only this process and its children can be moved
+pub fn set_process_group(pid: Option<Pid>, pgid: Option<Pid>) -> SyscallResult {
+    syscall(
+        Syscall::SetProcessGroup,
+        pid.map_or(0, usize::from),
+        pgid.map_or(0, usize::from),
+    )?;
+    Ok(())
+}
+
+/// Get the process group of `pid`, or of this process if `None`
+pub fn process_group(pid: Option<Pid>) -> SyscallResult<Pid> {
+    Ok(syscall(Syscall::GetProcessGroup, pid.map_or(0, usize::from), 0)?.try_into()?)
+}
+
+/// Give process group `pgid` control of the terminal, or let anybody read from it if `None`
+///
+/// Only the foreground group can hand the terminal off, though a parent can take it back from
+/// a group its child leads
+pub fn set_foreground_group(pgid: Option<Pid>) -> SyscallResult {
+    syscall(Syscall::SetForegroundGroup, pgid.map_or(0, usize::from), 0)?;
+    Ok(())
+}
+
+/// Get the terminal mode
+pub fn tty_mode() -> SyscallResult<TtyMode> {
+    Ok(syscall(Syscall::GetTtyMode, 0, 0)?.try_into()?)
+}
+
+/// Set the terminal mode
+pub fn set_tty_mode(mode: TtyMode) -> SyscallResult {
+    syscall(Syscall::SetTtyMode, mode.into(), 0)?;
+    Ok(())
+}
+
+/// Set the niceness of thread `pid`, or of this thread if `None`
+///
+/// Less nice threads are scheduled first, and run for longer. A process's main thread has the
+/// same ID as the process. Only threads of this process and its children can be changed
+pub fn set_priority(pid: Option<Pid>, nice: Nice) -> SyscallResult {
+    syscall(
+        Syscall::SetPriority,
+        pid.map_or(0, usize::from),
+        nice.into(),
+    )?;
+    Ok(())
+}
+
+/// Get the niceness of thread `pid`, or of this thread if `None`
+pub fn priority(pid: Option<Pid>) -> SyscallResult<Nice> {
+    Ok(syscall(Syscall::GetPriority, pid.map_or(0, usize::from), 0)?.try_into()?)
+}
+
+// Closure run by a new thread
+type ThreadMain = Box<dyn FnOnce() + Send>;
+
+/// Start a thread running `f`, returning its thread ID
+///
+/// Threads share the process's memory, but each gets its own stack
+pub fn spawn_thread(f: impl FnOnce() + Send + 'static) -> SyscallResult<Pid> {
+    let main: ThreadMain = Box::new(f);
+    let main = Box::into_raw(Box::new(main));
+    match syscall3(
+        Syscall::SpawnThread,
+        thread_entry as usize,
+        main as usize,
+        0,
+    ) {
+        Ok(tid) => Ok(tid.try_into()?),
+        Err(err) => {
+            // The thread never started, so the closure is still ours
+            drop(unsafe { Box::from_raw(main) });
+            Err(err)
+        }
+    }
+}
+
+// New threads start here, with the closure left by `spawn_thread`
+extern "C" fn thread_entry(main: *mut ThreadMain) -> ! {
+    let main = unsafe { Box::from_raw(main) };
+    main();
+    let _ = exit_thread(Ok(()));
+    unreachable!("Failed to exit thread");
+}
+
+/// Wait for thread `tid` of this process to exit
+pub fn join_thread(tid: Pid) -> SyscallResult {
+    exit_status(syscall(Syscall::JoinThread, tid.into(), 0)?.try_into()?)
+}
+
+/// Exit the calling thread
+///
+/// The rest of the process carries on until its last thread exits
+pub fn exit_thread(res: ProcessResult) -> SyscallResult {
+    let res = if let Err(err) = res {
+        usize::from(err)
+    } else {
+        0
+    };
+
+    syscall(Syscall::ExitThread, res, 0)?;
+    Ok(())
+}
+
+/// Block until woken through `word`, unless it no longer holds `expected`
+///
+/// Fails with [Errno::TimedOut] if `timeout` passes first
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> SyscallResult {
+    let (secs, nanos) = match timeout {
+        Some(timeout) => (
+            usize::try_from(timeout.as_secs())
+                .unwrap_or(NO_TIMEOUT - 1)
+                .min(NO_TIMEOUT - 1),
+            usize::try_from(timeout.subsec_nanos()).expect("usize should hold u32"),
+        ),
+        None => (NO_TIMEOUT, 0),
+    };
+    syscall6(
+        Syscall::FutexWait,
+        word as *const AtomicU32 as usize,
+        usize::try_from(expected).expect("usize should hold u32"),
+        secs,
+        nanos,
+        0,
+        0,
+    )?;
+    Ok(())
+}
+
+/// Wake up to `count` threads waiting on `word`
+///
+/// Returns how many were woken
+pub fn futex_wake(word: &AtomicU32, count: usize) -> SyscallResult<usize> {
+    syscall(Syscall::FutexWake, word as *const AtomicU32 as usize, count)
+}
+
+/// What to do when a signal arrives
+#[derive(Copy, Clone, Debug)]
+pub enum SignalHandler {
+    /// Terminate or ignore, depending on the signal
+    Default,
+    /// Discard the signal
+    Ignore,
+    /// Call a function
+    Handler(fn(Signal)),
+}
+
+/// Send `signal` to process `pid`
+pub fn kill(pid: Pid, signal: Signal) -> SyscallResult {
+    syscall(Syscall::Kill, pid.into(), signal.into())?;
+    Ok(())
+}
+
+/// Choose what happens when `signal` arrives
+pub fn set_signal_handler(signal: Signal, handler: SignalHandler) -> SyscallResult {
+    let handler = match handler {
+        SignalHandler::Default => SIGNAL_DEFAULT,
+        SignalHandler::Ignore => SIGNAL_IGNORE,
+        SignalHandler::Handler(handler) => handler as usize,
+    };
+    syscall3(
+        Syscall::SetSignalHandler,
+        signal.into(),
+        handler,
+        signal_entry as usize,
+    )?;
+    Ok(())
+}
+
+// The kernel enters signal handlers through here, with `context` pointing at the interrupted
+// context it saved on our stack
+extern "C" fn signal_entry(signal: Signal, handler: fn(Signal), context: usize) -> ! {
+    handler(signal);
+    let _ = syscall(Syscall::SigReturn, context, 0);
+    unreachable!("Failed to return from signal handler");
+}